@@ -158,6 +158,7 @@ impl<'a> FunctionCodeGenerator<'a> {
             builder: {
                 let mut builder = IRBuilderWithModuleAndFuncId::new(module, func_id);
                 let entry = builder.append_basic_block();
+                builder.set_block_name(entry, "entry");
                 builder.set_insert_point(entry);
                 builder
             },
@@ -169,7 +170,9 @@ impl<'a> FunctionCodeGenerator<'a> {
             let cilk_ty = cilk_func_ty.params_ty[i];
             let ty = gen.compound_types[*ty].as_func().1[i];
             let val = gen.builder.func_ref().get_param_value(i).unwrap();
+            gen.builder.set_value_name(val, name);
             let var = gen.builder.build_alloca(cilk_ty);
+            gen.builder.set_value_name(var, &format!("{}.addr", name));
             gen.builder.build_store(val, var);
             let p_cilk_ty = gen.builder.module().unwrap().types.new_pointer_ty(cilk_ty);
             let p_ty = gen.compound_types.pointer(ty);
@@ -241,6 +244,9 @@ impl<'a> FunctionCodeGenerator<'a> {
         let then_block = self.builder.append_basic_block();
         let else_block = self.builder.append_basic_block();
         let merge_block = self.builder.append_basic_block();
+        self.builder.set_block_name(then_block, "if.then");
+        self.builder.set_block_name(else_block, "if.else");
+        self.builder.set_block_name(merge_block, "if.end");
 
         self.builder.build_cond_br(cond, then_block, else_block);
 
@@ -269,6 +275,9 @@ impl<'a> FunctionCodeGenerator<'a> {
         let header_block = self.builder.append_basic_block();
         let body_block = self.builder.append_basic_block();
         let post_block = self.builder.append_basic_block();
+        self.builder.set_block_name(header_block, "while.cond");
+        self.builder.set_block_name(body_block, "while.body");
+        self.builder.set_block_name(post_block, "while.end");
 
         self.builder.build_br(header_block);
         self.builder.set_insert_point(header_block);
@@ -300,6 +309,10 @@ impl<'a> FunctionCodeGenerator<'a> {
         let loop_block = self.builder.append_basic_block();
         let step_block = self.builder.append_basic_block();
         let post_block = self.builder.append_basic_block();
+        self.builder.set_block_name(pre_block, "for.cond");
+        self.builder.set_block_name(loop_block, "for.body");
+        self.builder.set_block_name(step_block, "for.inc");
+        self.builder.set_block_name(post_block, "for.end");
 
         self.generate(init)?;
 
@@ -455,6 +468,7 @@ impl<'a> FunctionCodeGenerator<'a> {
             .unwrap_or(0);
         self.builder.set_insert_point_at(pt, entry);
         let alloca = self.builder.build_alloca(cilk_ty);
+        self.builder.set_value_name(alloca, name);
         let p_cilk_ty = self.builder.module().unwrap().types.new_pointer_ty(cilk_ty);
         let p_ty = self.compound_types.pointer(ty);
         self.variables
@@ -642,6 +656,9 @@ impl<'a> FunctionCodeGenerator<'a> {
         let then_block = self.builder.append_basic_block();
         let else_block = self.builder.append_basic_block();
         let merge_block = self.builder.append_basic_block();
+        self.builder.set_block_name(then_block, "cond.true");
+        self.builder.set_block_name(else_block, "cond.false");
+        self.builder.set_block_name(merge_block, "cond.end");
 
         self.builder.build_cond_br(cond, then_block, else_block);
 
@@ -63,6 +63,7 @@ pub fn compile(path: PathBuf) {
     // cilk::ir::mem2reg::Mem2Reg::new().run_on_module(&mut codegen.module);
     // cilk::ir::cse::CommonSubexprElimination::new().run_on_module(&mut codegen.module);
     // cilk::ir::licm::LoopInvariantCodeMotion::new().run_on_module(&mut codegen.module);
+    cilk::ir::global_dce::GlobalDeadCodeElimination::new().run_on_module(&mut codegen.module);
 
     let machine_module =
         cilk::codegen::x64::standard_conversion_into_machine_module(codegen.module);
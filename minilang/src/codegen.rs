@@ -221,6 +221,7 @@ impl<'a> CodeGeneratorForFunction<'a> {
     pub fn run(&mut self) {
         for (i, (name, ty)) in self.func.params.iter().enumerate() {
             let param = self.builder.get_param(i).unwrap();
+            self.builder.set_value_name(param, name);
             let ty = if matches!(ty, parser::Type::Struct(_)) {
                 parser::Type::Pointer(Box::new(ty.clone()))
             } else {
@@ -230,6 +231,7 @@ impl<'a> CodeGeneratorForFunction<'a> {
         }
 
         let entry = self.builder.append_basic_block();
+        self.builder.set_block_name(entry, "entry");
         self.builder.set_insert_point(entry);
 
         for stmt in &self.func.body {
@@ -242,6 +244,7 @@ impl<'a> CodeGeneratorForFunction<'a> {
             Node::VarDecl(name, ty) => {
                 let ty_ = ty.into_cilk_type(&self.types, &mut self.builder.func_ref_mut().types);
                 let alloca = self.builder.build_alloca(ty_);
+                self.builder.set_value_name(alloca, name);
                 self.create_var(
                     name.clone(),
                     false,
@@ -264,6 +267,11 @@ impl<'a> CodeGeneratorForFunction<'a> {
                 } else {
                     else_bb
                 };
+                self.builder.set_block_name(then_bb, "if.then");
+                if else_.is_some() {
+                    self.builder.set_block_name(else_bb, "if.else");
+                }
+                self.builder.set_block_name(merge_bb, "if.end");
                 self.builder.build_cond_br(cond, then_bb, else_bb);
                 self.builder.set_insert_point(then_bb);
                 for node in then_ {
@@ -288,6 +296,9 @@ impl<'a> CodeGeneratorForFunction<'a> {
                 let header_bb = self.builder.append_basic_block();
                 let body_bb = self.builder.append_basic_block();
                 let post_bb = self.builder.append_basic_block();
+                self.builder.set_block_name(header_bb, "while.cond");
+                self.builder.set_block_name(body_bb, "while.body");
+                self.builder.set_block_name(post_bb, "while.end");
                 self.builder.build_br(header_bb);
                 self.builder.set_insert_point(header_bb);
                 let (cond, _) = self.run_on_node(cond);
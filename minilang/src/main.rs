@@ -356,19 +356,19 @@ fn ray_tracing() {
     let mut codegen = codegen::CodeGenerator::new();
     codegen.run(input);
 
-    cilk::ir::mem2reg::Mem2Reg::new().run_on_module(&mut codegen.module);
-    cilk::ir::cse::CommonSubexprElimination::new().run_on_module(&mut codegen.module);
-    cilk::ir::licm::LoopInvariantCodeMotion::new().run_on_module(&mut codegen.module);
-
     println!("{:?}", codegen.module);
 
     // let mut jit = cilk::codegen::x64::exec::jit::JITExecutor::new(codegen.module);
     // let func = jit.find_function_by_name("main").unwrap();
     // println!("Result: {:?}", jit.run(func, vec![]));
 
+    use cilk::codegen::common::pipeline::{OptLevel, PipelineConfig};
     use cilk::codegen::x64::asm::print::MachineAsmPrinter;
-    use cilk::codegen::x64::standard_conversion_into_machine_module;
-    let machine_module = standard_conversion_into_machine_module(codegen.module);
+    use cilk::codegen::x64::standard_conversion_into_machine_module_with_config;
+    let machine_module = standard_conversion_into_machine_module_with_config(
+        codegen.module,
+        &PipelineConfig::new(OptLevel::O2),
+    );
     let mut printer = MachineAsmPrinter::new();
     // println!("{:?}", machine_module);
     printer.run_on_module(&machine_module);
@@ -415,15 +415,15 @@ fn eratosthenes_sieve() {
     let mut codegen = codegen::CodeGenerator::new();
     codegen.run(input);
 
-    cilk::ir::mem2reg::Mem2Reg::new().run_on_module(&mut codegen.module);
-    cilk::ir::cse::CommonSubexprElimination::new().run_on_module(&mut codegen.module);
-    cilk::ir::licm::LoopInvariantCodeMotion::new().run_on_module(&mut codegen.module);
-
     // println!("{:?}", codegen.module);
 
+    use cilk::codegen::common::pipeline::{OptLevel, PipelineConfig};
     use cilk::codegen::x64::asm::print::MachineAsmPrinter;
-    use cilk::codegen::x64::standard_conversion_into_machine_module;
-    let machine_module = standard_conversion_into_machine_module(codegen.module);
+    use cilk::codegen::x64::standard_conversion_into_machine_module_with_config;
+    let machine_module = standard_conversion_into_machine_module_with_config(
+        codegen.module,
+        &PipelineConfig::new(OptLevel::O2),
+    );
     let mut printer = MachineAsmPrinter::new();
     // println!("{:?}", machine_module);
     printer.run_on_module(&machine_module);
@@ -476,19 +476,19 @@ fn loop_licm() {
     let mut codegen = codegen::CodeGenerator::new();
     codegen.run(input);
 
-    cilk::ir::mem2reg::Mem2Reg::new().run_on_module(&mut codegen.module);
-    cilk::ir::cse::CommonSubexprElimination::new().run_on_module(&mut codegen.module);
-    cilk::ir::licm::LoopInvariantCodeMotion::new().run_on_module(&mut codegen.module);
-
     println!("{:?}", codegen.module);
 
     // let mut jit = cilk::codegen::x64::exec::jit::JITExecutor::new(codegen.module);
     // let func = jit.find_function_by_name("main").unwrap();
     // println!("Result: {:?}", jit.run(func, vec![]));
 
+    use cilk::codegen::common::pipeline::{OptLevel, PipelineConfig};
     use cilk::codegen::x64::asm::print::MachineAsmPrinter;
-    use cilk::codegen::x64::standard_conversion_into_machine_module;
-    let machine_module = standard_conversion_into_machine_module(codegen.module);
+    use cilk::codegen::x64::standard_conversion_into_machine_module_with_config;
+    let machine_module = standard_conversion_into_machine_module_with_config(
+        codegen.module,
+        &PipelineConfig::new(OptLevel::O2),
+    );
     let mut printer = MachineAsmPrinter::new();
     println!("{:?}", machine_module);
     printer.run_on_module(&machine_module);
@@ -530,14 +530,16 @@ fn pass_struct() {
     let mut codegen = codegen::CodeGenerator::new();
     codegen.run(input);
 
-    cilk::ir::mem2reg::Mem2Reg::new().run_on_module(&mut codegen.module);
-    cilk::ir::cse::CommonSubexprElimination::new().run_on_module(&mut codegen.module);
-
     println!("{:?}", codegen.module);
 
+    use cilk::codegen::common::pipeline::PipelineConfig;
     use cilk::codegen::x64::asm::print::MachineAsmPrinter;
-    use cilk::codegen::x64::standard_conversion_into_machine_module;
-    let machine_module = standard_conversion_into_machine_module(codegen.module);
+    use cilk::codegen::x64::standard_conversion_into_machine_module_with_config;
+    let config = PipelineConfig::default()
+        .with_ir_pipeline("mem2reg,cse,dce,constfold,instcombine")
+        .unwrap();
+    let machine_module =
+        standard_conversion_into_machine_module_with_config(codegen.module, &config);
     let mut printer = MachineAsmPrinter::new();
     printer.run_on_module(&machine_module);
     println!("{}", printer.output);
@@ -579,14 +581,16 @@ fn rand_mandelbrot() {
     let mut codegen = codegen::CodeGenerator::new();
     codegen.run(input);
 
-    cilk::ir::mem2reg::Mem2Reg::new().run_on_module(&mut codegen.module);
-    cilk::ir::cse::CommonSubexprElimination::new().run_on_module(&mut codegen.module);
-
     // println!("{:?}", codegen.module);
 
+    use cilk::codegen::common::pipeline::PipelineConfig;
     use cilk::codegen::x64::asm::print::MachineAsmPrinter;
-    use cilk::codegen::x64::standard_conversion_into_machine_module;
-    let machine_module = standard_conversion_into_machine_module(codegen.module);
+    use cilk::codegen::x64::standard_conversion_into_machine_module_with_config;
+    let config = PipelineConfig::default()
+        .with_ir_pipeline("mem2reg,cse,dce,constfold,instcombine")
+        .unwrap();
+    let machine_module =
+        standard_conversion_into_machine_module_with_config(codegen.module, &config);
     let mut printer = MachineAsmPrinter::new();
     printer.run_on_module(&machine_module);
     println!("{}", printer.output);
@@ -651,12 +655,14 @@ fn bubble_sort() {
     let mut codegen = codegen::CodeGenerator::new();
     codegen.run(input);
 
-    cilk::ir::mem2reg::Mem2Reg::new().run_on_module(&mut codegen.module);
-    cilk::ir::cse::CommonSubexprElimination::new().run_on_module(&mut codegen.module);
-
+    use cilk::codegen::common::pipeline::PipelineConfig;
     use cilk::codegen::x64::asm::print::MachineAsmPrinter;
-    use cilk::codegen::x64::standard_conversion_into_machine_module;
-    let machine_module = standard_conversion_into_machine_module(codegen.module);
+    use cilk::codegen::x64::standard_conversion_into_machine_module_with_config;
+    let config = PipelineConfig::default()
+        .with_ir_pipeline("mem2reg,cse,dce,constfold,instcombine")
+        .unwrap();
+    let machine_module =
+        standard_conversion_into_machine_module_with_config(codegen.module, &config);
     let mut printer = MachineAsmPrinter::new();
     printer.run_on_module(&machine_module);
     assemble_and_run(
@@ -716,12 +722,14 @@ fn quick_sort() {
     let mut codegen = codegen::CodeGenerator::new();
     codegen.run(input);
 
-    cilk::ir::mem2reg::Mem2Reg::new().run_on_module(&mut codegen.module);
-    cilk::ir::cse::CommonSubexprElimination::new().run_on_module(&mut codegen.module);
-
+    use cilk::codegen::common::pipeline::PipelineConfig;
     use cilk::codegen::x64::asm::print::MachineAsmPrinter;
-    use cilk::codegen::x64::standard_conversion_into_machine_module;
-    let machine_module = standard_conversion_into_machine_module(codegen.module);
+    use cilk::codegen::x64::standard_conversion_into_machine_module_with_config;
+    let config = PipelineConfig::default()
+        .with_ir_pipeline("mem2reg,cse,dce,constfold,instcombine")
+        .unwrap();
+    let machine_module =
+        standard_conversion_into_machine_module_with_config(codegen.module, &config);
     // println!("{:?}", machine_module);
     let mut printer = MachineAsmPrinter::new();
     printer.run_on_module(&machine_module);
@@ -0,0 +1,355 @@
+use crate::codegen::is_internal_function;
+use crate::ir::{
+    function::Function,
+    global_val::GlobalVariableId,
+    module::Module,
+    opcode::{Instruction, InstructionId, Opcode, Operand},
+    types::{Type, TypeSize},
+    value::{ArgumentValue, ImmediateValue, Value},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AliasResult {
+    NoAlias,
+    MayAlias,
+    MustAlias,
+}
+
+/// How an instruction may access a memory location.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModRef {
+    NoModRef,
+    Ref,
+    Mod,
+    ModRef,
+}
+
+/// Answers whether two pointers of a function may point to the same memory, and which
+/// instructions may read or write the memory a pointer points to.
+///
+/// Pointers are traced back through `GetElementPtr`s to the object they point into. Two
+/// pointers don't alias if their objects are known to be distinct, or if they point to
+/// disjoint ranges of the same object.
+pub struct AliasAnalysis<'a> {
+    func: &'a Function,
+
+    /// Needed to know what the callee of a call is. Without it, a call may access any
+    /// memory whose address escapes.
+    module: Option<&'a Module>,
+}
+
+/// The object a pointer points into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Object {
+    Alloca(InstructionId),
+
+    /// The result of `cilk.malloc.i32`
+    Malloc(InstructionId),
+
+    Global(GlobalVariableId),
+
+    Argument(usize),
+
+    /// Any other pointer, such as a loaded one
+    Unknown(Value),
+}
+
+struct Location {
+    object: Object,
+
+    /// Offset in bytes from the start of the object, if constant
+    offset: Option<i64>,
+}
+
+impl ModRef {
+    pub fn may_ref(&self) -> bool {
+        matches!(self, ModRef::Ref | ModRef::ModRef)
+    }
+
+    pub fn may_mod(&self) -> bool {
+        matches!(self, ModRef::Mod | ModRef::ModRef)
+    }
+}
+
+impl<'a> AliasAnalysis<'a> {
+    pub fn new(func: &'a Function) -> Self {
+        Self { func, module: None }
+    }
+
+    /// Lets calls to internal functions be told apart from other calls.
+    pub fn with_module(mut self, module: &'a Module) -> Self {
+        self.module = Some(module);
+        self
+    }
+
+    pub fn alias(&self, p1: &Value, p2: &Value) -> AliasResult {
+        if p1 == p2 {
+            return AliasResult::MustAlias;
+        }
+
+        let l1 = self.locate(p1);
+        let l2 = self.locate(p2);
+
+        if l1.object != l2.object {
+            return if self.is_distinct(l1.object, l2.object) {
+                AliasResult::NoAlias
+            } else {
+                AliasResult::MayAlias
+            };
+        }
+
+        let (o1, o2) = match (l1.offset, l2.offset) {
+            (Some(o1), Some(o2)) => (o1, o2),
+            _ => return AliasResult::MayAlias,
+        };
+        match (self.access_size(p1), self.access_size(p2)) {
+            (Some(s1), Some(s2)) if o1 == o2 && s1 == s2 => AliasResult::MustAlias,
+            (Some(s1), Some(s2)) if o1 + s1 <= o2 || o2 + s2 <= o1 => AliasResult::NoAlias,
+            _ => AliasResult::MayAlias,
+        }
+    }
+
+    /// Returns how the instruction `inst_id` may access the memory `ptr` points to.
+    pub fn mod_ref(&self, inst_id: InstructionId, ptr: &Value) -> ModRef {
+        let inst = &self.func.inst_table[inst_id];
+        match inst.opcode {
+            Opcode::Load
+                if self.alias(inst.operands[0].as_value(), ptr) != AliasResult::NoAlias =>
+            {
+                ModRef::Ref
+            }
+            Opcode::Store
+                if self.alias(inst.operands[1].as_value(), ptr) != AliasResult::NoAlias =>
+            {
+                ModRef::Mod
+            }
+            Opcode::Call => self.call_mod_ref(inst, ptr),
+            _ => ModRef::NoModRef,
+        }
+    }
+
+    /// Returns true if `ptr` points into an alloca whose address doesn't escape, so the
+    /// memory can't be accessed once the function returns.
+    pub fn is_local(&self, ptr: &Value) -> bool {
+        match self.locate(ptr).object {
+            Object::Alloca(id) => !self.escapes(id),
+            _ => false,
+        }
+    }
+
+    fn call_mod_ref(&self, call: &Instruction, ptr: &Value) -> ModRef {
+        match self.callee_name(call) {
+            Some("cilk.memset.p0i32.i32") => {
+                // memset may write past the pointee of its argument
+                let dst = self.locate(call.operands[1].as_value()).object;
+                let obj = self.locate(ptr).object;
+                if dst != obj && self.is_distinct(dst, obj) {
+                    ModRef::NoModRef
+                } else {
+                    ModRef::Mod
+                }
+            }
+            // The other internal functions don't touch memory visible to the caller.
+            Some(name) if is_internal_function(name) => ModRef::NoModRef,
+            _ => match self.locate(ptr).object {
+                Object::Alloca(id) | Object::Malloc(id) if !self.escapes(id) => ModRef::NoModRef,
+                _ => ModRef::ModRef,
+            },
+        }
+    }
+
+    fn callee_name(&self, call: &Instruction) -> Option<&'a str> {
+        match (self.module, call.operands[0].as_value()) {
+            (Some(module), Value::Function(f)) => Some(&module.function_ref(f.func_id).name),
+            _ => None,
+        }
+    }
+
+    fn locate(&self, ptr: &Value) -> Location {
+        let mut ptr = *ptr;
+        let mut offset = Some(0);
+
+        loop {
+            let object = match ptr {
+                Value::Instruction(iv) => {
+                    let inst = &self.func.inst_table[iv.id];
+                    match inst.opcode {
+                        Opcode::Alloca => Object::Alloca(iv.id),
+                        Opcode::Call if self.callee_name(inst) == Some("cilk.malloc.i32") => {
+                            Object::Malloc(iv.id)
+                        }
+                        Opcode::GetElementPtr => {
+                            let base = *inst.operands[0].as_value();
+                            offset = match (offset, self.gep_offset(&base, &inst.operands[1..])) {
+                                (Some(x), Some(y)) => Some(x + y),
+                                _ => None,
+                            };
+                            ptr = base;
+                            continue;
+                        }
+                        _ => Object::Unknown(ptr),
+                    }
+                }
+                Value::Global(g) => Object::Global(g.id),
+                Value::Argument(a) => Object::Argument(a.index),
+                _ => Object::Unknown(ptr),
+            };
+            return Location { object, offset };
+        }
+    }
+
+    // Follows the way `GetElementPtr` is lowered: a struct index selects a field and any
+    // other index steps over elements of the indexed type.
+    fn gep_offset(&self, base: &Value, indices: &[Operand]) -> Option<i64> {
+        let types = &self.func.types;
+        let mut ty = base.get_type();
+        let mut offset = 0;
+
+        for idx in indices {
+            let idx = idx.as_value();
+            let i = match idx {
+                Value::Immediate(ImmediateValue::Int32(i)) => *i as i64,
+                Value::Immediate(ImmediateValue::Int64(i)) => *i,
+                _ => return None,
+            };
+            let field_offset = match ty {
+                Type::Struct(id) => Some(
+                    *types
+                        .compound_ty(id)
+                        .as_struct()
+                        .get_elem_offset(i as usize)? as i64,
+                ),
+                _ => None,
+            };
+            ty = types.get_element_ty(ty, Some(idx))?;
+            offset += field_offset.unwrap_or(i * ty.size_in_byte(types) as i64);
+        }
+
+        Some(offset)
+    }
+
+    fn access_size(&self, ptr: &Value) -> Option<i64> {
+        match ptr.get_type() {
+            Type::Pointer(id) => match *self.func.types.compound_ty(id).as_pointer() {
+                Type::Void | Type::Function(_) => None,
+                ty => Some(ty.size_in_byte(&self.func.types) as i64),
+            },
+            _ => None,
+        }
+    }
+
+    // `o1` and `o2` must be different objects.
+    fn is_distinct(&self, o1: Object, o2: Object) -> bool {
+        let is_noalias = |o| match o {
+            // The function may access what a `noalias` argument points to through other
+            // pointers once it has copied the argument somewhere.
+            Object::Argument(i) => {
+                self.func.get_param_attr(i).map_or(false, |a| a.noalias)
+                    && !self.argument_escapes(i)
+            }
+            _ => false,
+        };
+        if is_noalias(o1) || is_noalias(o2) {
+            return true;
+        }
+
+        match (o1, o2) {
+            (Object::Unknown(_), Object::Unknown(_)) => false,
+            // An unknown pointer can only point into a local object whose address escaped.
+            (Object::Alloca(id), Object::Unknown(_))
+            | (Object::Malloc(id), Object::Unknown(_))
+            | (Object::Unknown(_), Object::Alloca(id))
+            | (Object::Unknown(_), Object::Malloc(id)) => !self.escapes(id),
+            (Object::Unknown(_), _) | (_, Object::Unknown(_)) => false,
+            // Arguments come from the caller, so they don't point into local objects.
+            (Object::Argument(_), Object::Alloca(_))
+            | (Object::Argument(_), Object::Malloc(_))
+            | (Object::Alloca(_), Object::Argument(_))
+            | (Object::Malloc(_), Object::Argument(_)) => true,
+            (Object::Argument(_), _) | (_, Object::Argument(_)) => false,
+            // Allocas, mallocs and globals are all distinct objects.
+            _ => true,
+        }
+    }
+
+    /// Returns true if the address of the local object `id` may be known outside the
+    /// function or be loaded from memory.
+    fn escapes(&self, id: InstructionId) -> bool {
+        self.pointer_escapes(self.func.get_inst_value(id))
+    }
+
+    /// Returns true if the argument `index` may be copied anywhere other than into the
+    /// pointers derived from it, e.g. stored to memory or passed to a call.
+    fn argument_escapes(&self, index: usize) -> bool {
+        let ty = self.func.get_param_type(index).unwrap();
+        self.pointer_escapes(Value::Argument(ArgumentValue {
+            func_id: self.func.id.unwrap(),
+            index,
+            ty,
+        }))
+    }
+
+    fn pointer_escapes(&self, ptr: Value) -> bool {
+        let mut worklist = vec![ptr];
+        let mut visited = vec![];
+
+        while let Some(ptr) = worklist.pop() {
+            if visited.contains(&ptr) {
+                continue;
+            }
+            visited.push(ptr);
+
+            for user_id in self.users_of(&ptr) {
+                let user = &self.func.inst_table[user_id];
+                let is_operand = |i: usize| match user.operands.get(i) {
+                    Some(Operand::Value(v)) => is_same_value(v, &ptr),
+                    _ => false,
+                };
+                match user.opcode {
+                    Opcode::Load | Opcode::ICmp => {}
+                    Opcode::Store if !is_operand(0) => {}
+                    Opcode::GetElementPtr if is_operand(0) => {
+                        worklist.push(self.func.get_inst_value(user_id))
+                    }
+                    Opcode::Call
+                        if !is_operand(0)
+                            && self.callee_name(user).map_or(false, is_internal_function) => {}
+                    _ => return true,
+                }
+            }
+        }
+
+        false
+    }
+
+    fn users_of(&self, val: &Value) -> Vec<InstructionId> {
+        match val {
+            Value::Instruction(iv) => self.func.inst_table[iv.id].users.borrow().clone(),
+            // Arguments don't keep their users.
+            _ => {
+                let mut users = vec![];
+                for &block in &self.func.basic_blocks.order {
+                    for v in &*self.func.basic_blocks.arena[block].iseq_ref() {
+                        let id = v.as_instruction().id;
+                        if self.func.inst_table[id].operands.iter().any(|op| match op {
+                            Operand::Value(v) => is_same_value(v, val),
+                            _ => false,
+                        }) {
+                            users.push(id)
+                        }
+                    }
+                }
+                users
+            }
+        }
+    }
+}
+
+// Compares instructions and arguments by identity rather than also by type.
+fn is_same_value(v1: &Value, v2: &Value) -> bool {
+    match (v1, v2) {
+        (Value::Instruction(i1), Value::Instruction(i2)) => i1.id == i2.id,
+        (Value::Argument(a1), Value::Argument(a2)) => a1.index == a2.index,
+        _ => false,
+    }
+}
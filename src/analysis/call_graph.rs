@@ -0,0 +1,221 @@
+use crate::ir::{
+    function::{Function, FunctionId},
+    module::Module,
+    opcode::{InstructionId, Opcode, Operand},
+    value::Value,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// A callee in the call graph. `Unknown` stands for whatever an indirect call or a
+/// function without a body may call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CallGraphNode {
+    Function(FunctionId),
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+    pub caller: FunctionId,
+    pub inst: InstructionId,
+    pub callee: CallGraphNode,
+}
+
+/// Functions calling each other directly or indirectly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallGraphSCC {
+    pub functions: Vec<FunctionId>,
+
+    /// True if a function of the SCC can call itself, i.e. the SCC has more than one
+    /// function or its only function calls itself.
+    pub is_recursive: bool,
+}
+
+/// Caller/callee edges between the functions of a module, built from `Opcode::Call`.
+pub struct CallGraph {
+    /// Every function of the module in module order
+    pub functions: Vec<FunctionId>,
+    call_sites: FxHashMap<FunctionId, Vec<CallSite>>,
+    callers: FxHashMap<FunctionId, Vec<FunctionId>>,
+    address_taken: FxHashSet<FunctionId>,
+    declarations: FxHashSet<FunctionId>,
+}
+
+struct SCCFinder<'a> {
+    graph: &'a CallGraph,
+    index: FxHashMap<FunctionId, usize>,
+    lowlink: FxHashMap<FunctionId, usize>,
+    stack: Vec<FunctionId>,
+    on_stack: FxHashSet<FunctionId>,
+    sccs: Vec<CallGraphSCC>,
+}
+
+impl CallGraph {
+    pub fn new(module: &Module) -> Self {
+        let mut graph = Self {
+            functions: vec![],
+            call_sites: FxHashMap::default(),
+            callers: FxHashMap::default(),
+            address_taken: FxHashSet::default(),
+            declarations: FxHashSet::default(),
+        };
+
+        for (id, func) in &module.functions {
+            graph.functions.push(id);
+            if !func.is_internal && func.is_empty() {
+                graph.declarations.insert(id);
+            }
+            graph.add_call_sites(id, func);
+        }
+
+        graph
+    }
+
+    fn add_call_sites(&mut self, caller: FunctionId, func: &Function) {
+        let mut sites = vec![];
+
+        for &block_id in &func.basic_blocks.order {
+            for val in &*func.basic_blocks.arena[block_id].iseq_ref() {
+                let id = val.as_instruction().id;
+                let inst = &func.inst_table[id];
+                let is_call = inst.opcode == Opcode::Call;
+
+                for (i, operand) in inst.operands.iter().enumerate() {
+                    if let Operand::Value(Value::Function(f)) = operand {
+                        if !is_call || i != 0 {
+                            self.address_taken.insert(f.func_id);
+                        }
+                    }
+                }
+
+                if !is_call {
+                    continue;
+                }
+
+                let callee = match &inst.operands[0] {
+                    Operand::Value(Value::Function(f)) => CallGraphNode::Function(f.func_id),
+                    _ => CallGraphNode::Unknown,
+                };
+                if let CallGraphNode::Function(callee) = callee {
+                    let callers = self.callers.entry(callee).or_default();
+                    if !callers.contains(&caller) {
+                        callers.push(caller)
+                    }
+                }
+                sites.push(CallSite {
+                    caller,
+                    inst: id,
+                    callee,
+                });
+            }
+        }
+
+        self.call_sites.insert(caller, sites);
+    }
+
+    pub fn call_sites(&self, func: FunctionId) -> &[CallSite] {
+        self.call_sites.get(&func).map_or(&[][..], Vec::as_slice)
+    }
+
+    /// Returns the callees of `func` without duplicates, in the order they are first
+    /// called.
+    pub fn callees(&self, func: FunctionId) -> Vec<CallGraphNode> {
+        let mut callees = vec![];
+        for site in self.call_sites(func) {
+            if !callees.contains(&site.callee) {
+                callees.push(site.callee)
+            }
+        }
+        callees
+    }
+
+    pub fn callers(&self, func: FunctionId) -> &[FunctionId] {
+        self.callers.get(&func).map_or(&[][..], Vec::as_slice)
+    }
+
+    /// True if `func` has an indirect call or is only declared, so it may call any
+    /// function whose address is taken.
+    pub fn calls_unknown(&self, func: FunctionId) -> bool {
+        self.declarations.contains(&func)
+            || self
+                .call_sites(func)
+                .iter()
+                .any(|site| site.callee == CallGraphNode::Unknown)
+    }
+
+    /// True if `func` is used other than as the callee of a call, so it may be called
+    /// from anywhere an unknown function is.
+    pub fn is_address_taken(&self, func: FunctionId) -> bool {
+        self.address_taken.contains(&func)
+    }
+
+    /// Returns the strongly connected components bottom-up: every SCC comes after the
+    /// SCCs it calls.
+    pub fn sccs(&self) -> Vec<CallGraphSCC> {
+        let mut finder = SCCFinder {
+            graph: self,
+            index: FxHashMap::default(),
+            lowlink: FxHashMap::default(),
+            stack: vec![],
+            on_stack: FxHashSet::default(),
+            sccs: vec![],
+        };
+        for &func in &self.functions {
+            if !finder.index.contains_key(&func) {
+                finder.visit(func)
+            }
+        }
+        finder.sccs
+    }
+}
+
+impl<'a> SCCFinder<'a> {
+    // Tarjan's algorithm finds an SCC only after all the SCCs reachable from it.
+    fn visit(&mut self, func: FunctionId) {
+        let index = self.index.len();
+        self.index.insert(func, index);
+        self.lowlink.insert(func, index);
+        self.stack.push(func);
+        self.on_stack.insert(func);
+
+        for callee in self.graph.callees(func) {
+            let callee = match callee {
+                CallGraphNode::Function(callee) => callee,
+                CallGraphNode::Unknown => continue,
+            };
+            if !self.index.contains_key(&callee) {
+                self.visit(callee);
+                let low = self.lowlink[&func].min(self.lowlink[&callee]);
+                self.lowlink.insert(func, low);
+            } else if self.on_stack.contains(&callee) {
+                let low = self.lowlink[&func].min(self.index[&callee]);
+                self.lowlink.insert(func, low);
+            }
+        }
+
+        if self.lowlink[&func] != self.index[&func] {
+            return;
+        }
+
+        let mut functions = vec![];
+        loop {
+            let f = self.stack.pop().unwrap();
+            self.on_stack.remove(&f);
+            functions.push(f);
+            if f == func {
+                break;
+            }
+        }
+        functions.reverse();
+
+        let is_recursive = functions.len() > 1
+            || self
+                .graph
+                .callees(func)
+                .contains(&CallGraphNode::Function(func));
+        self.sccs.push(CallGraphSCC {
+            functions,
+            is_recursive,
+        });
+    }
+}
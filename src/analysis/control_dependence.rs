@@ -0,0 +1,45 @@
+use super::post_dom_tree::PostDominatorTree;
+use crate::traits::basic_block::BasicBlockTrait;
+use id_arena::Id;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Which branches decide whether a block executes. A block `b` is control dependent on
+/// `x` if one successor of `x` always leads to `b` and another may avoid it, i.e. `x` is
+/// in the post-dominance frontier of `b`.
+#[derive(Clone, Debug)]
+pub struct ControlDependenceGraph<T: BasicBlockTrait> {
+    dependences: FxHashMap<Id<T>, FxHashSet<Id<T>>>,
+    dependents: FxHashMap<Id<T>, FxHashSet<Id<T>>>,
+}
+
+impl<T: BasicBlockTrait> ControlDependenceGraph<T> {
+    pub fn new(post_dom_tree: &PostDominatorTree<T>) -> Self {
+        let mut dependents: FxHashMap<Id<T>, FxHashSet<Id<T>>> = FxHashMap::default();
+        for (&block, frontier) in &post_dom_tree.frontier {
+            for &branch in frontier {
+                dependents.entry(branch).or_default().insert(block);
+            }
+        }
+
+        Self {
+            dependences: post_dom_tree.frontier.clone(),
+            dependents,
+        }
+    }
+
+    /// Returns the blocks whose branch decides whether `bb` executes.
+    pub fn dependences_of(&self, bb: Id<T>) -> Option<&FxHashSet<Id<T>>> {
+        self.dependences.get(&bb)
+    }
+
+    /// Returns the blocks whose execution depends on the branch at the end of `bb`.
+    pub fn dependents_of(&self, bb: Id<T>) -> Option<&FxHashSet<Id<T>>> {
+        self.dependents.get(&bb)
+    }
+
+    pub fn is_control_dependent(&self, bb: Id<T>, on: Id<T>) -> bool {
+        self.dependences
+            .get(&bb)
+            .map_or(false, |deps| deps.contains(&on))
+    }
+}
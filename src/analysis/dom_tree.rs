@@ -3,6 +3,7 @@
 use crate::{
     analysis::Analysis,
     traits::basic_block::{BasicBlockTrait, BasicBlocksTrait},
+    util::dot::Dot,
 };
 use id_arena::Id;
 use rustc_hash::{FxHashMap, FxHashSet};
@@ -89,6 +90,37 @@ impl<T: BasicBlockTrait> DominatorTree<T> {
         blocks.push(root);
         blocks
     }
+
+    /// Returns the tree in Graphviz DOT format, naming blocks with `label`.
+    pub fn to_dot<F: Fn(Id<T>) -> String>(&self, name: &str, label: F) -> String {
+        let mut dot = Dot::new(name);
+        let node = |id: Id<T>| format!("bb{}", id.index());
+
+        let mut blocks: Vec<Id<T>> = self.root.into_iter().collect();
+        for (&parent, children) in &self.tree {
+            blocks.push(parent);
+            blocks.extend(children.iter().copied());
+        }
+        blocks.sort_by_key(|id| id.index());
+        blocks.dedup();
+
+        for &id in &blocks {
+            dot.node(&node(id), &label(id), "");
+        }
+
+        for &parent in &blocks {
+            let mut children: Vec<Id<T>> = match self.tree.get(&parent) {
+                Some(children) => children.iter().copied().collect(),
+                None => continue,
+            };
+            children.sort_by_key(|id| id.index());
+            for child in children {
+                dot.edge(&node(parent), &node(child), "");
+            }
+        }
+
+        dot.finish()
+    }
 }
 
 macro_rules! cmp {
@@ -154,9 +186,11 @@ impl<'a, BBS: BasicBlocksTrait> DominatorTreeConstructor<'a, BBS> {
             }
         }
 
+        // `n` has the same dominator as `samedom[n]`, which comes earlier in DFS order
         for &n in &self.vertex[1..] {
             if let Some(s) = self.samedom.get(&n) {
-                self.idom.insert(n, *s);
+                let idom = self.idom[s];
+                self.idom.insert(n, idom);
             }
         }
     }
@@ -0,0 +1,317 @@
+use super::loops::{Loop, Loops};
+use crate::ir::{
+    basic_block::{BasicBlock, BasicBlockId},
+    function::Function,
+    opcode::{ICmpKind, InstructionId, Opcode, Operand},
+    value::Value,
+};
+use id_arena::Id;
+use rustc_hash::FxHashMap;
+
+/// Induction variables and trip counts of every loop of a function.
+#[derive(Debug, Clone)]
+pub struct InductionAnalysis {
+    pub loops: FxHashMap<Id<Loop<BasicBlock>>, LoopInduction>,
+}
+
+/// How a loop iterates.
+#[derive(Debug, Clone)]
+pub struct LoopInduction {
+    pub header: BasicBlockId,
+
+    /// The only predecessor of the header outside the loop, if it branches only to the
+    /// header
+    pub preheader: Option<BasicBlockId>,
+
+    /// The only predecessor of the header inside the loop
+    pub latch: Option<BasicBlockId>,
+
+    /// Blocks in the loop branching out of it
+    pub exiting: Vec<BasicBlockId>,
+
+    /// Blocks outside the loop branched to from `exiting`
+    pub exits: Vec<BasicBlockId>,
+
+    pub induction_vars: Vec<InductionVariable>,
+
+    /// How many times the back edge is taken, i.e. how many times the exit test keeps
+    /// the loop running. Only known for loops with a single exiting block, which is the
+    /// header or the latch, testing an induction variable against a loop invariant.
+    pub trip_count: Option<TripCount>,
+}
+
+/// A header phi which is `start` on entry and increased by `step` on every iteration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InductionVariable {
+    pub phi: InstructionId,
+    pub start: Value,
+    pub step: i64,
+
+    /// The `Add` or `Sub` computing the value of the next iteration
+    pub next: InstructionId,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TripCount {
+    Constant(i64),
+    Symbolic(SymbolicTripCount),
+}
+
+/// A trip count only known at run time: `max(0, ceil((bound - start + adjust) / step))`
+/// for a positive step, and `max(0, ceil((start - bound + adjust) / -step))` for a
+/// negative one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolicTripCount {
+    pub start: Value,
+    pub bound: Value,
+    pub step: i64,
+    pub adjust: i64,
+}
+
+impl InductionAnalysis {
+    pub fn new(func: &Function, loops: &Loops<BasicBlock>) -> Self {
+        let mut analysis = Self {
+            loops: FxHashMap::default(),
+        };
+        for (id, loop_) in &loops.arena {
+            analysis
+                .loops
+                .insert(id, LoopInductionAnalyzer { func, loop_ }.analyze());
+        }
+        analysis
+    }
+
+    pub fn get(&self, loop_id: Id<Loop<BasicBlock>>) -> Option<&LoopInduction> {
+        self.loops.get(&loop_id)
+    }
+
+    pub fn get_by_header(&self, header: BasicBlockId) -> Option<&LoopInduction> {
+        self.loops.values().find(|l| l.header == header)
+    }
+}
+
+impl LoopInduction {
+    pub fn induction_var_of(&self, phi: InstructionId) -> Option<&InductionVariable> {
+        self.induction_vars.iter().find(|iv| iv.phi == phi)
+    }
+
+    pub fn constant_trip_count(&self) -> Option<i64> {
+        match self.trip_count {
+            Some(TripCount::Constant(count)) => Some(count),
+            _ => None,
+        }
+    }
+}
+
+struct LoopInductionAnalyzer<'a> {
+    func: &'a Function,
+    loop_: &'a Loop<BasicBlock>,
+}
+
+impl<'a> LoopInductionAnalyzer<'a> {
+    fn analyze(&self) -> LoopInduction {
+        let header = self.loop_.header;
+        let blocks = &self.func.basic_blocks.arena;
+
+        let (inside, outside): (Vec<BasicBlockId>, Vec<BasicBlockId>) =
+            sorted(blocks[header].pred.iter().copied())
+                .into_iter()
+                .partition(|pred| self.loop_.contains(pred));
+        let latch = single(&inside).copied();
+        let preheader = single(&outside)
+            .copied()
+            .filter(|&pred| blocks[pred].succ.len() == 1);
+
+        let mut exiting = vec![];
+        let mut exits = vec![];
+        for &block in &self.func.basic_blocks.order {
+            if !self.loop_.contains(&block) {
+                continue;
+            }
+            for succ in sorted(blocks[block].succ.iter().copied()) {
+                if self.loop_.contains(&succ) {
+                    continue;
+                }
+                if !exiting.contains(&block) {
+                    exiting.push(block)
+                }
+                if !exits.contains(&succ) {
+                    exits.push(succ)
+                }
+            }
+        }
+
+        let induction_vars = match (single(&outside), latch) {
+            (Some(&entry), Some(latch)) => self.induction_vars(entry, latch),
+            _ => vec![],
+        };
+
+        let mut induction = LoopInduction {
+            header,
+            preheader,
+            latch,
+            exiting,
+            exits,
+            induction_vars,
+            trip_count: None,
+        };
+        induction.trip_count = self.trip_count(&induction);
+        induction
+    }
+
+    fn induction_vars(&self, entry: BasicBlockId, latch: BasicBlockId) -> Vec<InductionVariable> {
+        let mut vars = vec![];
+
+        for val in &*self.func.basic_blocks.arena[self.loop_.header].iseq_ref() {
+            let phi_id = val.as_instruction().id;
+            let phi = &self.func.inst_table[phi_id];
+            if phi.opcode != Opcode::Phi {
+                break;
+            }
+            if phi.operands.len() != 4 {
+                continue;
+            }
+
+            let incoming = |bb: BasicBlockId| {
+                phi.operands
+                    .chunks(2)
+                    .find(|pair| *pair[1].as_basic_block() == bb)
+                    .map(|pair| *pair[0].as_value())
+            };
+            let (start, next) = match (incoming(entry), incoming(latch)) {
+                (Some(start), Some(Value::Instruction(next))) => (start, next.id),
+                _ => continue,
+            };
+            if let Some(step) = self.step_of(phi_id, next) {
+                vars.push(InductionVariable {
+                    phi: phi_id,
+                    start,
+                    step,
+                    next,
+                })
+            }
+        }
+
+        vars
+    }
+
+    // Returns `step` if `next` is `phi + step` or `phi - (-step)`.
+    fn step_of(&self, phi: InstructionId, next: InstructionId) -> Option<i64> {
+        let inst = &self.func.inst_table[next];
+        if !matches!(inst.opcode, Opcode::Add | Opcode::Sub) || !self.loop_.contains(&inst.parent) {
+            return None;
+        }
+        let is_phi = |op: &Operand| op.as_value().get_inst_id() == Some(phi);
+        let (lhs, rhs) = (&inst.operands[0], &inst.operands[1]);
+
+        let step = match inst.opcode {
+            Opcode::Add if is_phi(lhs) => rhs.as_value().as_i64(),
+            Opcode::Add if is_phi(rhs) => lhs.as_value().as_i64(),
+            Opcode::Sub if is_phi(lhs) => rhs.as_value().as_i64().map(|step| -step),
+            _ => None,
+        };
+        step.filter(|&step| step != 0)
+    }
+
+    fn trip_count(&self, induction: &LoopInduction) -> Option<TripCount> {
+        let exiting = *single(&induction.exiting)?;
+        if Some(exiting) != induction.latch && exiting != induction.header {
+            return None;
+        }
+
+        let blocks = &self.func.basic_blocks.arena;
+        let br = blocks[exiting].iseq_ref().last()?.as_instruction().id;
+        let br = &self.func.inst_table[br];
+        if br.opcode != Opcode::CondBr {
+            return None;
+        }
+        let cond = br.operands[0].as_value().get_inst_id()?;
+        let cond = &self.func.inst_table[cond];
+        if cond.opcode != Opcode::ICmp {
+            return None;
+        }
+        let stays_if_true = self.loop_.contains(br.operands[1].as_basic_block());
+
+        // Bring the test into the form `iv kind bound` that keeps the loop running.
+        let mut kind = *cond.operands[0].as_icmp_kind();
+        let (mut lhs, mut rhs) = (*cond.operands[1].as_value(), *cond.operands[2].as_value());
+        if !self.is_invariant(&rhs) {
+            std::mem::swap(&mut lhs, &mut rhs);
+            kind = kind.swapped();
+        }
+        if !stays_if_true {
+            kind = kind.inverted();
+        }
+        let bound = rhs;
+        if !self.is_invariant(&bound) {
+            return None;
+        }
+
+        let lhs = lhs.get_inst_id()?;
+        let (iv, tests_next) = induction.induction_vars.iter().find_map(|iv| match lhs {
+            id if id == iv.phi => Some((iv, false)),
+            id if id == iv.next => Some((iv, true)),
+            _ => None,
+        })?;
+
+        // The test sees `start + k * step` (or `start + (k + 1) * step` if it tests the
+        // next value) in the k-th iteration. Count the iterations before it fails.
+        let step = iv.step;
+        let adjust = if tests_next { -step.abs() } else { 0 };
+        let (adjust, exact) = match kind {
+            ICmpKind::Lt if step > 0 => (adjust, false),
+            ICmpKind::Le if step > 0 => (adjust + 1, false),
+            ICmpKind::Gt if step < 0 => (adjust, false),
+            ICmpKind::Ge if step < 0 => (adjust + 1, false),
+            ICmpKind::Ne => (adjust, true),
+            _ => return None,
+        };
+
+        match (iv.start.as_i64(), bound.as_i64()) {
+            (Some(start), Some(bound)) => {
+                let distance = if step > 0 {
+                    bound - start
+                } else {
+                    start - bound
+                } + adjust;
+                let step = step.abs();
+                if exact {
+                    if distance < 0 || distance % step != 0 {
+                        // The induction variable steps over the bound
+                        return None;
+                    }
+                    return Some(TripCount::Constant(distance / step));
+                }
+                Some(TripCount::Constant(((distance + step - 1) / step).max(0)))
+            }
+            // `Ne` only keeps counting if every value is tested.
+            _ if exact && step.abs() != 1 => None,
+            _ => Some(TripCount::Symbolic(SymbolicTripCount {
+                start: iv.start,
+                bound,
+                step,
+                adjust,
+            })),
+        }
+    }
+
+    fn is_invariant(&self, val: &Value) -> bool {
+        match val {
+            Value::Instruction(iv) => !self.loop_.contains(&self.func.inst_table[iv.id].parent),
+            _ => true,
+        }
+    }
+}
+
+fn sorted(blocks: impl Iterator<Item = BasicBlockId>) -> Vec<BasicBlockId> {
+    let mut blocks: Vec<BasicBlockId> = blocks.collect();
+    blocks.sort_by_key(|id| id.index());
+    blocks
+}
+
+fn single<T>(items: &[T]) -> Option<&T> {
+    match items {
+        [item] => Some(item),
+        _ => None,
+    }
+}
@@ -1,5 +1,6 @@
 use super::dom_tree::DominatorTree;
 use crate::traits::basic_block::{BasicBlockTrait, BasicBlocksTrait};
+use crate::util::dot::Dot;
 use id_arena::{Arena, Id};
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::collections::VecDeque;
@@ -10,14 +11,14 @@ pub struct LoopsConstructor<'a, BBS: BasicBlocksTrait> {
     basic_blocks: &'a BBS,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Loops<BB: BasicBlockTrait> {
     pub arena: Arena<Loop<BB>>,
     pub bb_to_loop: FxHashMap<Id<BB>, Id<Loop<BB>>>,
     pub top_level_loops: Vec<Id<Loop<BB>>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Loop<BB: BasicBlockTrait> {
     pub parent: Option<Id<Loop<BB>>>,
     pub header: Id<BB>,
@@ -155,6 +156,56 @@ impl<BB: BasicBlockTrait> Loops<BB> {
     pub fn set_loop_for(&mut self, bb: Id<BB>, loop_id: Id<Loop<BB>>) {
         self.bb_to_loop.insert(bb, loop_id);
     }
+
+    /// Returns the loop nesting in Graphviz DOT format. Each loop is a cluster around
+    /// its blocks and sub loops; `blocks` outside any loop are drawn at the top level.
+    pub fn to_dot<F: Fn(Id<BB>) -> String>(
+        &self,
+        name: &str,
+        blocks: &[Id<BB>],
+        label: F,
+    ) -> String {
+        let mut dot = Dot::new(name);
+
+        for &id in blocks {
+            if self.get_loop_for(id).is_none() {
+                dot.node(&format!("bb{}", id.index()), &label(id), "");
+            }
+        }
+
+        for &loop_id in &self.top_level_loops {
+            self.loop_to_dot(&mut dot, blocks, &label, loop_id);
+        }
+
+        dot.finish()
+    }
+
+    fn loop_to_dot<F: Fn(Id<BB>) -> String>(
+        &self,
+        dot: &mut Dot,
+        blocks: &[Id<BB>],
+        label: &F,
+        loop_id: Id<Loop<BB>>,
+    ) {
+        let loop_ = &self.arena[loop_id];
+        dot.begin_cluster(
+            &format!("loop{}", loop_id.index()),
+            &format!("loop {} (header: {})", loop_id.index(), label(loop_.header)),
+        );
+
+        for &id in blocks {
+            if self.get_loop_for(id) == Some(loop_id) {
+                let attrs = if id == loop_.header { "style=bold" } else { "" };
+                dot.node(&format!("bb{}", id.index()), &label(id), attrs);
+            }
+        }
+
+        for &sub_loop in &loop_.sub_loops {
+            self.loop_to_dot(dot, blocks, label, sub_loop);
+        }
+
+        dot.end_cluster()
+    }
 }
 
 impl<BB: BasicBlockTrait> Loop<BB> {
@@ -0,0 +1,181 @@
+use super::{
+    dom_tree::{DominatorTree, DominatorTreeConstructor},
+    induction::InductionAnalysis,
+    loops::{Loops, LoopsConstructor},
+    post_dom_tree::{PostDominatorTree, PostDominatorTreeConstructor},
+    Analysis,
+};
+use crate::ir::{basic_block::BasicBlock, function::Function};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{any::Any, sync::Arc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnalysisKind {
+    DominatorTree,
+    PostDominatorTree,
+    Loops,
+    Induction,
+}
+
+/// The analyses a pass left valid.
+#[derive(Debug, Clone)]
+pub struct PreservedAnalyses {
+    all: bool,
+    kinds: FxHashSet<AnalysisKind>,
+}
+
+/// Computes analyses on demand and caches them in `Function::analyses` until a pass
+/// reports that it has invalidated them.
+pub struct AnalysisManager {
+    computed: FxHashMap<AnalysisKind, usize>,
+}
+
+// Cached analyses are shared with the passes using them, so they are kept behind `Arc`.
+impl<T: Send + Sync + 'static> Analysis for Arc<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl PreservedAnalyses {
+    pub fn all() -> Self {
+        Self {
+            all: true,
+            kinds: FxHashSet::default(),
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            all: false,
+            kinds: FxHashSet::default(),
+        }
+    }
+
+    /// Analyses that only depend on the control flow graph.
+    pub fn cfg() -> Self {
+        Self::none()
+            .preserve(AnalysisKind::DominatorTree)
+            .preserve(AnalysisKind::PostDominatorTree)
+            .preserve(AnalysisKind::Loops)
+    }
+
+    pub fn preserve(mut self, kind: AnalysisKind) -> Self {
+        self.kinds.insert(kind);
+        self
+    }
+
+    pub fn is_preserved(&self, kind: AnalysisKind) -> bool {
+        self.all || self.kinds.contains(&kind)
+    }
+
+    /// Returns true if the pass left the function as it was.
+    pub fn is_all(&self) -> bool {
+        self.all
+    }
+
+    /// Keeps only the analyses preserved by both `self` and `other`.
+    pub fn intersect(&mut self, other: &Self) {
+        if other.all {
+            return;
+        }
+        if self.all {
+            *self = other.clone();
+            return;
+        }
+        self.kinds.retain(|kind| other.kinds.contains(kind))
+    }
+}
+
+impl AnalysisManager {
+    pub fn new() -> Self {
+        Self {
+            computed: FxHashMap::default(),
+        }
+    }
+
+    pub fn get_dom_tree(&mut self, func: &mut Function) -> Arc<DominatorTree<BasicBlock>> {
+        if let Some(dom_tree) = func.get_analysis::<Arc<DominatorTree<BasicBlock>>>() {
+            return dom_tree.clone();
+        }
+
+        let dom_tree = Arc::new(DominatorTreeConstructor::new(&func.basic_blocks).construct());
+        *self
+            .computed
+            .entry(AnalysisKind::DominatorTree)
+            .or_insert(0) += 1;
+        func.add_analysis(dom_tree.clone());
+        dom_tree
+    }
+
+    pub fn get_post_dom_tree(&mut self, func: &mut Function) -> Arc<PostDominatorTree<BasicBlock>> {
+        if let Some(post_dom_tree) = func.get_analysis::<Arc<PostDominatorTree<BasicBlock>>>() {
+            return post_dom_tree.clone();
+        }
+
+        let post_dom_tree =
+            Arc::new(PostDominatorTreeConstructor::new(&func.basic_blocks).construct());
+        *self
+            .computed
+            .entry(AnalysisKind::PostDominatorTree)
+            .or_insert(0) += 1;
+        func.add_analysis(post_dom_tree.clone());
+        post_dom_tree
+    }
+
+    pub fn get_loops(&mut self, func: &mut Function) -> Arc<Loops<BasicBlock>> {
+        if let Some(loops) = func.get_analysis::<Arc<Loops<BasicBlock>>>() {
+            return loops.clone();
+        }
+
+        let dom_tree = self.get_dom_tree(func);
+        let loops = Arc::new(LoopsConstructor::new(&dom_tree, &func.basic_blocks).analyze());
+        *self.computed.entry(AnalysisKind::Loops).or_insert(0) += 1;
+        func.add_analysis(loops.clone());
+        loops
+    }
+
+    /// Induction variables and trip counts also depend on the instructions in the loops,
+    /// so passes preserving only `PreservedAnalyses::cfg()` drop them.
+    pub fn get_induction(&mut self, func: &mut Function) -> Arc<InductionAnalysis> {
+        if let Some(induction) = func.get_analysis::<Arc<InductionAnalysis>>() {
+            return induction.clone();
+        }
+
+        let loops = self.get_loops(func);
+        let induction = Arc::new(InductionAnalysis::new(func, &loops));
+        *self.computed.entry(AnalysisKind::Induction).or_insert(0) += 1;
+        func.add_analysis(induction.clone());
+        induction
+    }
+
+    /// Drops the cached analyses of `func` that are not in `preserved`.
+    pub fn invalidate(&mut self, func: &mut Function, preserved: &PreservedAnalyses) {
+        // Loops are computed from the dominator tree.
+        if !preserved.is_preserved(AnalysisKind::DominatorTree) {
+            func.remove_analysis::<Arc<DominatorTree<BasicBlock>>>();
+            func.remove_analysis::<Arc<Loops<BasicBlock>>>();
+        }
+        if !preserved.is_preserved(AnalysisKind::PostDominatorTree) {
+            func.remove_analysis::<Arc<PostDominatorTree<BasicBlock>>>();
+        }
+        if !preserved.is_preserved(AnalysisKind::Loops) {
+            func.remove_analysis::<Arc<Loops<BasicBlock>>>();
+        }
+        // Induction analysis refers to loops by their ids.
+        if func.get_analysis::<Arc<Loops<BasicBlock>>>().is_none()
+            || !preserved.is_preserved(AnalysisKind::Induction)
+        {
+            func.remove_analysis::<Arc<InductionAnalysis>>();
+        }
+    }
+
+    /// Returns how many times `kind` has been computed instead of taken from the cache.
+    pub fn num_computed(&self, kind: AnalysisKind) -> usize {
+        *self.computed.get(&kind).unwrap_or(&0)
+    }
+}
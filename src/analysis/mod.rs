@@ -1,10 +1,17 @@
+pub mod alias;
+pub mod call_graph;
+pub mod control_dependence;
 pub mod dom_tree;
+pub mod induction;
 pub mod loops;
+pub mod manager;
+pub mod post_dom_tree;
 
 use dyn_clone::{clone_trait_object, DynClone};
 use std::any::Any;
 
-pub trait Analysis: DynClone {
+/// Analyses are cached in `Function`, so they must be shareable for the function to be.
+pub trait Analysis: DynClone + Send + Sync {
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
@@ -0,0 +1,291 @@
+use crate::{
+    analysis::{dom_tree::DominatorTreeConstructor, Analysis},
+    traits::basic_block::{BasicBlockTrait, BasicBlocksTrait},
+    util::dot::Dot,
+};
+use id_arena::{Arena, Id};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::any::Any;
+
+/// Post-dominator tree rooted at a virtual exit block, which every block returning from
+/// the function flows into. Blocks that can't reach a return (e.g. infinite loops) are
+/// connected to the virtual exit too, so every block is in the tree.
+#[derive(Clone, Debug)]
+pub struct PostDominatorTree<T: BasicBlockTrait> {
+    /// Children of the virtual exit
+    pub roots: Vec<Id<T>>,
+    pub tree: FxHashMap<Id<T>, FxHashSet<Id<T>>>,
+    /// Post-dominance frontier
+    pub frontier: FxHashMap<Id<T>, FxHashSet<Id<T>>>,
+    ipdom: FxHashMap<Id<T>, Id<T>>,
+}
+
+pub struct PostDominatorTreeConstructor<'a, BBS: BasicBlocksTrait> {
+    basic_blocks: &'a BBS,
+}
+
+// The control flow graph with the edges reversed and a virtual exit as the entry. The
+// forward `DominatorTreeConstructor` computes post-dominators on it.
+struct ReverseBlocks {
+    arena: Arena<ReverseBlock>,
+    order: Vec<Id<ReverseBlock>>,
+}
+
+struct ReverseBlock {
+    preds: FxHashSet<Id<ReverseBlock>>,
+    succs: FxHashSet<Id<ReverseBlock>>,
+}
+
+impl BasicBlockTrait for ReverseBlock {
+    fn get_preds(&self) -> &FxHashSet<Id<Self>> {
+        &self.preds
+    }
+
+    fn get_succs(&self) -> &FxHashSet<Id<Self>> {
+        &self.succs
+    }
+}
+
+impl BasicBlocksTrait for ReverseBlocks {
+    type BB = ReverseBlock;
+
+    fn get_arena(&self) -> &Arena<Self::BB> {
+        &self.arena
+    }
+
+    fn get_order(&self) -> &Vec<Id<Self::BB>> {
+        &self.order
+    }
+}
+
+impl<T: BasicBlockTrait + Clone + 'static> Analysis for PostDominatorTree<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl<T: BasicBlockTrait> PostDominatorTree<T> {
+    /// Returns true if every path from `bb1` to the exit goes through `bb0`.
+    pub fn post_dominate_bb(&self, bb0: Id<T>, bb1: Id<T>) -> bool {
+        let mut bb = bb1;
+        loop {
+            if bb == bb0 {
+                return true;
+            }
+            match self.ipdom.get(&bb) {
+                Some(&ipdom) => bb = ipdom,
+                None => return false,
+            }
+        }
+    }
+
+    /// Returns the immediate post-dominator of `bb`, or `None` if it is the virtual exit.
+    pub fn ipdom_of(&self, bb: Id<T>) -> Option<Id<T>> {
+        self.ipdom.get(&bb).copied()
+    }
+
+    pub fn children_of(&self, bb: Id<T>) -> Option<&FxHashSet<Id<T>>> {
+        self.tree.get(&bb)
+    }
+
+    pub fn post_dominance_frontier_of(&self, bb: Id<T>) -> Option<&FxHashSet<Id<T>>> {
+        self.frontier.get(&bb)
+    }
+
+    /// Returns the tree in Graphviz DOT format, naming blocks with `label`.
+    pub fn to_dot<F: Fn(Id<T>) -> String>(&self, name: &str, label: F) -> String {
+        let mut dot = Dot::new(name);
+        let node = |id: Id<T>| format!("bb{}", id.index());
+
+        dot.node("exit", "<exit>", "style=dashed");
+        let mut blocks: Vec<Id<T>> = self.roots.clone();
+        blocks.extend(self.ipdom.keys().copied());
+        blocks.sort_by_key(|id| id.index());
+        blocks.dedup();
+
+        for &id in &blocks {
+            dot.node(&node(id), &label(id), "");
+        }
+        for &id in &blocks {
+            match self.ipdom_of(id) {
+                Some(parent) => dot.edge(&node(parent), &node(id), ""),
+                None => dot.edge("exit", &node(id), ""),
+            }
+        }
+
+        dot.finish()
+    }
+}
+
+impl<'a, BBS: BasicBlocksTrait> PostDominatorTreeConstructor<'a, BBS> {
+    pub fn new(basic_blocks: &'a BBS) -> Self {
+        Self { basic_blocks }
+    }
+
+    pub fn construct(self) -> PostDominatorTree<BBS::BB> {
+        let arena = self.basic_blocks.get_arena();
+        let order = self.basic_blocks.get_order();
+
+        // The virtual exit is allocated after the blocks but comes first in the order,
+        // which makes it the entry of the reversed graph.
+        let mut reversed = ReverseBlocks {
+            arena: Arena::new(),
+            order: vec![],
+        };
+        let mut to_reversed = FxHashMap::default();
+        for &id in order {
+            let rid = reversed.arena.alloc(ReverseBlock {
+                preds: FxHashSet::default(),
+                succs: FxHashSet::default(),
+            });
+            to_reversed.insert(id, rid);
+        }
+        let exit = reversed.arena.alloc(ReverseBlock {
+            preds: FxHashSet::default(),
+            succs: FxHashSet::default(),
+        });
+
+        for &id in order {
+            for succ in arena[id].get_succs() {
+                if let Some(&rsucc) = to_reversed.get(succ) {
+                    reversed.arena[rsucc].succs.insert(to_reversed[&id]);
+                    reversed.arena[to_reversed[&id]].preds.insert(rsucc);
+                }
+            }
+        }
+
+        // Successors are visited in `order` so that the result doesn't depend on how the
+        // sets are hashed.
+        let position: FxHashMap<Id<ReverseBlock>, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (to_reversed[id], i))
+            .collect();
+
+        // Blocks without successors return. Every block that still can't reach the exit
+        // is in a region without a return. Such a region ends in an SCC no edge leaves,
+        // e.g. an infinite loop, one block of which is treated as leaving the function.
+        let mut exits = vec![];
+        let mut reaches_exit = FxHashSet::default();
+        for &id in order {
+            if !arena[id]
+                .get_succs()
+                .iter()
+                .any(|succ| to_reversed.contains_key(succ))
+            {
+                exits.push(to_reversed[&id]);
+                mark_reachable(&reversed, to_reversed[&id], &mut reaches_exit);
+            }
+        }
+        loop {
+            // The block finishing last in a DFS of the reversed region is in such an SCC.
+            let mut visited = reaches_exit.clone();
+            let mut post_order = vec![];
+            for &id in order {
+                visit_post_order(
+                    &reversed,
+                    &position,
+                    to_reversed[&id],
+                    &mut visited,
+                    &mut post_order,
+                );
+            }
+            match post_order.pop() {
+                Some(pseudo_exit) => {
+                    exits.push(pseudo_exit);
+                    mark_reachable(&reversed, pseudo_exit, &mut reaches_exit);
+                }
+                None => break,
+            }
+        }
+        for &rid in &exits {
+            reversed.arena[exit].succs.insert(rid);
+            reversed.arena[rid].preds.insert(exit);
+        }
+
+        // Reverse post-order from the virtual exit
+        let mut visited = FxHashSet::default();
+        let mut post_order = vec![];
+        visit_post_order(&reversed, &position, exit, &mut visited, &mut post_order);
+        post_order.reverse();
+        reversed.order = post_order;
+
+        let from_reversed: FxHashMap<Id<ReverseBlock>, Id<BBS::BB>> =
+            to_reversed.iter().map(|(&id, &rid)| (rid, id)).collect();
+        let original = |rid: &Id<ReverseBlock>| from_reversed.get(rid).copied();
+
+        let dom_tree = DominatorTreeConstructor::new(&reversed).construct();
+
+        let mut roots: Vec<Id<BBS::BB>> = dom_tree.children_of(exit).map_or(vec![], |children| {
+            children.iter().filter_map(original).collect()
+        });
+        roots.sort_by_key(|id| order.iter().position(|x| x == id));
+
+        let mut tree = PostDominatorTree {
+            roots,
+            tree: FxHashMap::default(),
+            frontier: FxHashMap::default(),
+            ipdom: FxHashMap::default(),
+        };
+
+        for (parent, children) in &dom_tree.tree {
+            let parent = match original(parent) {
+                Some(parent) => parent,
+                None => continue,
+            };
+            for child in children {
+                let child = original(child).unwrap();
+                tree.ipdom.insert(child, parent);
+                tree.tree
+                    .entry(parent)
+                    .or_insert_with(FxHashSet::default)
+                    .insert(child);
+            }
+        }
+
+        for (block, frontier) in &dom_tree.frontier {
+            if let Some(block) = original(block) {
+                tree.frontier
+                    .insert(block, frontier.iter().filter_map(original).collect());
+            }
+        }
+
+        tree
+    }
+}
+
+fn mark_reachable(
+    reversed: &ReverseBlocks,
+    start: Id<ReverseBlock>,
+    visited: &mut FxHashSet<Id<ReverseBlock>>,
+) {
+    let mut worklist = vec![start];
+    while let Some(id) = worklist.pop() {
+        if visited.insert(id) {
+            worklist.extend(reversed.arena[id].succs.iter().copied());
+        }
+    }
+}
+
+fn visit_post_order(
+    reversed: &ReverseBlocks,
+    position: &FxHashMap<Id<ReverseBlock>, usize>,
+    id: Id<ReverseBlock>,
+    visited: &mut FxHashSet<Id<ReverseBlock>>,
+    post_order: &mut Vec<Id<ReverseBlock>>,
+) {
+    if !visited.insert(id) {
+        return;
+    }
+
+    let mut succs: Vec<Id<ReverseBlock>> = reversed.arena[id].succs.iter().copied().collect();
+    succs.sort_by_key(|succ| position[succ]);
+    for succ in succs {
+        visit_post_order(reversed, position, succ, visited, post_order);
+    }
+    post_order.push(id);
+}
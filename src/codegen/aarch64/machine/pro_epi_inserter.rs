@@ -165,7 +165,7 @@ impl PrologueEpilogueInserter {
         .with_def(vec![x29]);
         builder.insert(mov);
 
-        // self.insert_arg_copy(&tys.base.borrow(), &mut builder);
+        // self.insert_arg_copy(&tys.base.read().unwrap(), &mut builder);
     }
 
     // fn insert_arg_copy<'a>(&mut self, tys: &'a TypesBase, builder: &'a mut Builder<'a>) {
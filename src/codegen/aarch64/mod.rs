@@ -6,13 +6,15 @@ pub mod machine;
 
 use crate::{
     codegen::common::{
-        dag::{combine, convert},
+        dag::{combine, convert, module::DAGModule},
         machine::{branch_folding, module::MachineModule, phi_elimination},
+        pipeline::PipelineConfig,
     },
     ir,
     ir::module::Module,
+    ir::pipeline::PipelineError,
     ir::types::*,
-    traits::pass::ModulePassManager,
+    traits::pass::{ModulePassManager, ModulePassTrait},
 };
 
 impl TypeSize for Type {
@@ -88,14 +90,39 @@ impl TypeSize for StructType {
 }
 
 pub fn standard_conversion_into_machine_module(module: &mut Module) -> MachineModule {
-    ir::merge_ret::MergeReturns::new().run_on_module(module);
+    // By default, this backend runs no IR passes and combines the DAG only once.
+    let config = PipelineConfig::default()
+        .with_ir_pipeline("")
+        .unwrap()
+        .with_dag_combine_rounds(1);
+    standard_conversion_into_machine_module_with_config(module, &config).unwrap()
+}
+
+/// Constant folding is left out of the passes implied by the optimization level, and
+/// a pipeline given by `PipelineConfig::with_ir_pipeline` that asks for it is rejected.
+pub fn standard_conversion_into_machine_module_with_config(
+    module: &mut Module,
+    config: &PipelineConfig,
+) -> Result<MachineModule, PipelineError> {
     // Constant folding may generate Shl, but the backend for aarch64 doesn't support Shl now.
-    // ir::const_folding::ConstantFolding::new().run_on_module(module);
+    let mut passes = config.ir_passes();
+    if config.has_ir_pipeline() && passes.iter().any(|pass| pass == "constfold") {
+        return Err(PipelineError::UnsupportedPass("constfold".to_string()));
+    }
+    passes.retain(|pass| pass != "constfold");
+
+    ir::merge_ret::MergeReturns::new().run_on_module(module);
+    config.pass_manager_for(&passes).run_on_module(module);
+    ir::lower_select::LowerSelect::new().run_on_module(module);
 
     let mut dag_module = convert::ConvertToDAGModule::new(module).run();
 
     let mut pass_mgr = ModulePassManager::new();
-    pass_mgr.add_pass(combine::Combine::new());
+    pass_mgr.instrumentation = config.instrumentation.clone();
+    pass_mgr.dot_printer = Some(DAGModule::to_dot);
+    for _ in 0..config.dag_combine_rounds() {
+        pass_mgr.add_pass(combine::Combine::new());
+    }
     pass_mgr.add_pass(dag::legalize::Legalize::new());
     pass_mgr.add_pass(dag::isel::MISelector::new());
     pass_mgr.run_on_module(&mut dag_module);
@@ -103,15 +130,19 @@ pub fn standard_conversion_into_machine_module(module: &mut Module) -> MachineMo
     let mut machine_module = dag::mc_convert::convert_module(dag_module);
 
     let mut pass_mgr = ModulePassManager::new();
+    pass_mgr.instrumentation = config.instrumentation.clone();
+    pass_mgr.dot_printer = Some(MachineModule::to_dot);
     pass_mgr.add_pass(phi_elimination::PhiElimination::new());
     // pass_mgr.add_pass(machine::two_addr::TwoAddressConverter::new());
     pass_mgr.add_pass(machine::regalloc::RegisterAllocator::new());
-    pass_mgr.add_pass(branch_folding::BranchFolding::new());
+    if config.fold_branches() {
+        pass_mgr.add_pass(branch_folding::BranchFolding::new());
+    }
     // pass_mgr.add_pass(machine::validate_frame_index::ValidateFrameIndex::new());
     pass_mgr.add_pass(machine::pro_epi_inserter::PrologueEpilogueInserter::new());
     pass_mgr.add_pass(machine::replace_copy::ReplaceCopyWithProperMInst::new());
     // pass_mgr.add_pass(machine::replace_data::ReplaceConstFPWithMemoryRef::new());
     pass_mgr.run_on_module(&mut machine_module);
 
-    machine_module
+    Ok(machine_module)
 }
@@ -237,6 +237,7 @@ impl<'a> ConvertToDAGNode<'a> {
                     let indices: Vec<Value> =
                         inst.operands[1..].iter().map(|v| *v.as_value()).collect();
                     let gep = self.construct_node_for_gep(inst.operands[0].as_value(), &indices);
+                    let gep = self.alloc_node_as_necessary(inst_id, (*gep).clone());
                     if self.block.liveness.borrow().live_out.contains(&inst_id) {
                         let gep = self.make_chain_with_copying(gep);
                         self.inst_to_node.insert(inst_id, gep);
@@ -445,6 +446,7 @@ impl<'a> ConvertToDAGNode<'a> {
                         self.inst_to_node.insert(inst_id, id);
                     }
                 }
+                Opcode::Select => unreachable!("selects must be lowered by LowerSelect"),
                 Opcode::Ret => {
                     let v = self.get_node_from_value(inst.operands[0].as_value());
                     let ret = self.alloc_node(DAGNode::new(
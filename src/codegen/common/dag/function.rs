@@ -1,8 +1,9 @@
 use crate::codegen::arch::{dag::node::*, frame_object::*, machine::register::*};
 use crate::codegen::common::dag::basic_block::*;
 use crate::ir::{function::*, types::*};
-use crate::util::allocator::*;
+use crate::util::{allocator::*, dot::Dot};
 use id_arena::*;
+use rustc_hash::FxHashMap;
 use std::fmt;
 
 pub type DAGFunctionId = Id<DAGFunction>;
@@ -32,6 +33,11 @@ pub struct DAGFunction {
     pub types: Types,
 }
 
+// SAFETY: Every `Raw` pointer of a function points into its own `dag_heap`, which is
+// freed only when the function is dropped. Moving the function to another thread moves
+// the whole graph with it.
+unsafe impl Send for DAGFunction {}
+
 pub struct DAGHeap {
     // TODO: -> NodeHeap
     heap: RawAllocator<DAGNode>,
@@ -76,6 +82,76 @@ impl DAGFunction {
 
         fmt::Result::Ok(())
     }
+
+    /// Returns the DAG of every basic block in Graphviz DOT format. Solid edges go to
+    /// operands, dashed ones follow the chain and dotted ones link a node to the next.
+    pub fn to_dot(&self) -> String {
+        let mut dot = Dot::new(&self.name);
+        let mut ids = FxHashMap::default();
+
+        for bb_id in &self.dag_basic_blocks {
+            let bb = &self.dag_basic_block_arena[*bb_id];
+            let succ = bb
+                .succ
+                .iter()
+                .map(|s| format!("BB#{}", s.index()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            dot.begin_cluster(
+                &format!("bb{}", bb_id.index()),
+                &format!("BB#{} (succ: {})", bb_id.index(), succ),
+            );
+            if let Some(entry) = bb.entry {
+                self.node_to_dot(&mut dot, &mut ids, entry);
+            }
+            dot.end_cluster();
+        }
+
+        dot.finish()
+    }
+
+    fn node_to_dot(
+        &self,
+        dot: &mut Dot,
+        ids: &mut FxHashMap<Raw<DAGNode>, usize>,
+        node: Raw<DAGNode>,
+    ) -> String {
+        if let Some(id) = ids.get(&node) {
+            return format!("n{}", id);
+        }
+        let id = format!("n{}", ids.len());
+        ids.insert(node, ids.len());
+
+        dot.node(
+            &id,
+            &format!("{:?}\n{}", node.kind, self.types.to_string(node.ty)),
+            if node.may_contain_children() {
+                ""
+            } else {
+                "style=rounded"
+            },
+        );
+
+        for (i, op) in node.operand.iter().enumerate() {
+            if op.kind == NodeKind::None {
+                continue;
+            }
+            let op = self.node_to_dot(dot, ids, *op);
+            dot.edge(&id, &op, &format!("label={}", i));
+        }
+
+        if let Some(chain) = node.chain {
+            let chain = self.node_to_dot(dot, ids, chain);
+            dot.edge(&id, &chain, "style=dashed, color=blue");
+        }
+
+        if let Some(next) = node.next {
+            let next = self.node_to_dot(dot, ids, next);
+            dot.edge(&id, &next, "style=dotted, color=gray");
+        }
+
+        id
+    }
 }
 
 impl DAGHeap {
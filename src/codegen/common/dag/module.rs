@@ -23,6 +23,15 @@ impl DAGModule {
     pub fn function_ref_mut(&mut self, id: DAGFunctionId) -> &mut DAGFunction {
         &mut self.functions[id]
     }
+
+    /// Returns one Graphviz digraph per function. See `DAGFunction::to_dot`.
+    pub fn to_dot(&self) -> String {
+        self.functions
+            .iter()
+            .filter(|(_, f)| !f.is_internal)
+            .map(|(_, f)| f.to_dot())
+            .collect()
+    }
 }
 
 impl fmt::Debug for DAGModule {
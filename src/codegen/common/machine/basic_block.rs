@@ -6,10 +6,7 @@ use crate::codegen::arch::machine::register::{
 use crate::traits::basic_block::*;
 use id_arena::*;
 use rustc_hash::FxHashSet;
-use std::{
-    cell::{Ref, RefCell, RefMut},
-    rc::Rc,
-};
+use std::cell::{Ref, RefCell, RefMut};
 
 pub type MachineBasicBlockId = Id<MachineBasicBlock>;
 
@@ -22,7 +19,7 @@ pub struct MachineBasicBlocks {
 #[derive(Clone, Debug)]
 pub struct MachineBasicBlock {
     /// Information for liveness analysis
-    pub liveness: Rc<RefCell<LivenessInfo>>,
+    pub liveness: RefCell<LivenessInfo>,
 
     /// Predecessors
     pub pred: FxHashSet<MachineBasicBlockId>,
@@ -106,7 +103,7 @@ impl MachineBasicBlocks {
         dst_block
             .liveness
             .borrow_mut()
-            .merge(Rc::try_unwrap(src_liveness).unwrap().into_inner());
+            .merge(src_liveness.into_inner());
         self.order.retain(|bb| bb != src);
     }
 
@@ -126,7 +123,7 @@ impl MachineBasicBlock {
             iseq: RefCell::new(vec![]),
             pred: FxHashSet::default(),
             succ: FxHashSet::default(),
-            liveness: Rc::new(RefCell::new(LivenessInfo::new())),
+            liveness: RefCell::new(LivenessInfo::new()),
         }
     }
 
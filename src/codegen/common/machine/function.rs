@@ -5,6 +5,7 @@ use crate::codegen::common::machine::const_data::ConstDataArena;
 use crate::codegen::common::{dag::function::*, machine::basic_block::*};
 use crate::ir::types::*;
 use crate::traits::function::FunctionTrait;
+use crate::util::dot::Dot;
 use id_arena::*;
 use std::cell::Ref;
 use std::fmt;
@@ -180,6 +181,34 @@ impl MachineFunction {
         self.body.basic_blocks.order.get(0)
     }
 
+    /// Returns the control flow graph in Graphviz DOT format. Each node lists the
+    /// machine instructions of a basic block.
+    pub fn to_dot(&self) -> String {
+        let mut dot = Dot::new(&self.name);
+        let order = &self.body.basic_blocks.order;
+
+        for (id, bb, iiter) in self.body.mbb_iter() {
+            let insts = iiter.fold("".to_string(), |s, (_, inst)| format!("{}{:?}\n", s, inst));
+            dot.node(
+                &format!("bb{}", id.index()),
+                &format!("MachineBasicBlock #{}:\n{}", id.index(), insts),
+                "",
+            );
+
+            let mut succ: Vec<MachineBasicBlockId> = bb.succ.iter().copied().collect();
+            succ.sort_by_key(|s| order.iter().position(|o| o == s));
+            for s in succ {
+                dot.edge(
+                    &format!("bb{}", id.index()),
+                    &format!("bb{}", s.index()),
+                    "",
+                );
+            }
+        }
+
+        dot.finish()
+    }
+
     // for more precise information (of type) than Debug trait
     pub fn debug(&self, f: &mut fmt::Formatter, tys: &Types) -> fmt::Result {
         writeln!(
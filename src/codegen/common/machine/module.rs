@@ -50,6 +50,15 @@ impl MachineModule {
         }
         None
     }
+
+    /// Returns one Graphviz digraph per function. See `MachineFunction::to_dot`.
+    pub fn to_dot(&self) -> String {
+        self.functions
+            .iter()
+            .filter(|(_, f)| !f.is_internal)
+            .map(|(_, f)| f.to_dot())
+            .collect()
+    }
 }
 
 impl fmt::Debug for MachineModule {
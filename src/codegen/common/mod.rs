@@ -2,3 +2,4 @@ pub mod asm;
 pub mod dag;
 #[macro_use]
 pub mod machine;
+pub mod pipeline;
@@ -0,0 +1,155 @@
+use crate::{
+    ir::pipeline::{build_pipeline, parse_pipeline, PipelineError},
+    traits::pass::{FunctionPassManager, PassInstrumentation},
+};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    Os,
+}
+
+/// Decides which passes `standard_conversion_into_machine_module` runs on each level:
+/// IR, DAG and machine.
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    pub opt_level: OptLevel,
+    ir_passes: Option<Vec<String>>,
+    dag_combine_rounds: Option<usize>,
+
+    /// Applied to every pass manager in the pipeline
+    pub instrumentation: PassInstrumentation,
+
+    /// How many threads lower functions from DAG to machine code. 1 lowers them on the
+    /// calling thread. The output doesn't depend on it.
+    pub threads: usize,
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "O0" | "0" => Ok(OptLevel::O0),
+            "O1" | "1" => Ok(OptLevel::O1),
+            "O2" | "2" => Ok(OptLevel::O2),
+            "Os" | "s" => Ok(OptLevel::Os),
+            _ => Err(format!("unknown optimization level '{}'", s)),
+        }
+    }
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self::new(OptLevel::O1)
+    }
+}
+
+impl PipelineConfig {
+    pub fn new(opt_level: OptLevel) -> Self {
+        Self {
+            opt_level,
+            ir_passes: None,
+            dag_combine_rounds: None,
+            instrumentation: PassInstrumentation::default(),
+            threads: 1,
+        }
+    }
+
+    /// Replaces the IR passes implied by the optimization level with `desc`,
+    /// e.g. `"mem2reg,cse,licm,dce"`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cilk::codegen::common::pipeline::{OptLevel, PipelineConfig};
+    /// let config = PipelineConfig::new(OptLevel::O0)
+    ///     .with_ir_pipeline("mem2reg,dce")
+    ///     .unwrap();
+    /// assert_eq!(config.ir_passes(), vec!["mem2reg", "dce"]);
+    /// assert!(PipelineConfig::default().with_ir_pipeline("foo").is_err());
+    /// ```
+    pub fn with_ir_pipeline(mut self, desc: &str) -> Result<Self, PipelineError> {
+        self.ir_passes = Some(parse_pipeline(desc)?);
+        Ok(self)
+    }
+
+    /// Replaces the number of DAG combiner rounds implied by the optimization level.
+    pub fn with_dag_combine_rounds(mut self, rounds: usize) -> Self {
+        self.dag_combine_rounds = Some(rounds.max(1));
+        self
+    }
+
+    /// Whether the IR passes were given by `with_ir_pipeline` rather than implied by the
+    /// optimization level.
+    pub fn has_ir_pipeline(&self) -> bool {
+        self.ir_passes.is_some()
+    }
+
+    pub fn ir_passes(&self) -> Vec<String> {
+        if let Some(passes) = &self.ir_passes {
+            return passes.clone();
+        }
+
+        let passes: &[&str] = match self.opt_level {
+            OptLevel::O0 => &[],
+            OptLevel::O1 => &["dce", "constfold", "instcombine"],
+            OptLevel::O2 => &[
+                "mem2reg",
+                "cse",
+                "licm",
+                "dce",
+                "constfold",
+                "instcombine",
+                "simplifycfg",
+            ],
+            // LICM inserts a pre-header for every loop
+            OptLevel::Os => &[
+                "mem2reg",
+                "cse",
+                "dce",
+                "constfold",
+                "instcombine",
+                "simplifycfg",
+            ],
+        };
+        passes.iter().map(|p| p.to_string()).collect()
+    }
+
+    pub fn ir_pass_manager(&self) -> FunctionPassManager {
+        self.pass_manager_for(&self.ir_passes())
+    }
+
+    /// Builds a pass manager running `passes`, which must be valid pass names.
+    pub fn pass_manager_for<S: AsRef<str>>(&self, passes: &[S]) -> FunctionPassManager {
+        let mut pass_mgr = build_pipeline(passes);
+        pass_mgr.instrumentation = self.instrumentation.clone();
+        pass_mgr
+    }
+
+    /// How many times the DAG combiner runs. At least once, since instruction selection
+    /// expects the combined forms (e.g. Brcc).
+    pub fn dag_combine_rounds(&self) -> usize {
+        if let Some(rounds) = self.dag_combine_rounds {
+            return rounds;
+        }
+
+        match self.opt_level {
+            OptLevel::O0 | OptLevel::Os => 1,
+            OptLevel::O1 | OptLevel::O2 => 3,
+        }
+    }
+
+    pub fn fold_branches(&self) -> bool {
+        self.opt_level != OptLevel::O0
+    }
+
+    /// Lowering runs in parallel only if there is more than one thread and no
+    /// instrumentation output to keep in order.
+    pub fn lowers_in_parallel(&self) -> bool {
+        self.threads > 1 && !self.instrumentation.has_output()
+    }
+}
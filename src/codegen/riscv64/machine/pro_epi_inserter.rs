@@ -160,7 +160,7 @@ impl PrologueEpilogueInserter {
             builder.insert(add);
         }
 
-        // self.insert_arg_copy(&tys.base.borrow(), &mut builder);
+        // self.insert_arg_copy(&tys.base.read().unwrap(), &mut builder);
     }
     //
     // fn insert_arg_copy<'a>(&mut self, tys: &'a TypesBase, builder: &'a mut Builder<'a>) {
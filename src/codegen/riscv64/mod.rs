@@ -6,13 +6,14 @@ pub mod machine;
 
 use crate::{
     codegen::common::{
-        dag::{combine, convert},
+        dag::{combine, convert, module::DAGModule},
         machine::{branch_folding, module::MachineModule, phi_elimination},
+        pipeline::PipelineConfig,
     },
     ir,
     ir::module::Module,
     ir::types::*,
-    traits::pass::ModulePassManager,
+    traits::pass::{ModulePassManager, ModulePassTrait},
 };
 
 impl TypeSize for Type {
@@ -23,10 +24,10 @@ impl TypeSize for Type {
             Type::i32 => 4,
             Type::i64 => 8,
             Type::f64 => 8,
-            Type::Array(id) => tys.base.borrow().non_primitive_types[*id]
+            Type::Array(id) => tys.base.read().unwrap().non_primitive_types[*id]
                 .as_array()
                 .size_in_byte(tys),
-            Type::Struct(id) => tys.base.borrow().non_primitive_types[*id]
+            Type::Struct(id) => tys.base.read().unwrap().non_primitive_types[*id]
                 .as_struct()
                 .size_in_byte(tys),
             Type::Pointer(_) => 8,
@@ -46,10 +47,10 @@ impl TypeSize for Type {
             Type::i32 => 4,
             Type::i64 => 8,
             Type::f64 => 8,
-            Type::Array(id) => tys.base.borrow().non_primitive_types[*id]
+            Type::Array(id) => tys.base.read().unwrap().non_primitive_types[*id]
                 .as_array()
                 .align_in_byte(tys),
-            Type::Struct(id) => tys.base.borrow().non_primitive_types[*id]
+            Type::Struct(id) => tys.base.read().unwrap().non_primitive_types[*id]
                 .as_struct()
                 .align_in_byte(tys),
             Type::Pointer(_) => 8,
@@ -96,12 +97,30 @@ impl TypeSize for StructType {
 }
 
 pub fn standard_conversion_into_machine_module(module: &mut Module) -> MachineModule {
+    // By default, this backend runs no IR passes and combines the DAG only once.
+    let config = PipelineConfig::default()
+        .with_ir_pipeline("")
+        .unwrap()
+        .with_dag_combine_rounds(1);
+    standard_conversion_into_machine_module_with_config(module, &config)
+}
+
+pub fn standard_conversion_into_machine_module_with_config(
+    module: &mut Module,
+    config: &PipelineConfig,
+) -> MachineModule {
     ir::merge_ret::MergeReturns::new().run_on_module(module);
+    config.ir_pass_manager().run_on_module(module);
+    ir::lower_select::LowerSelect::new().run_on_module(module);
 
     let mut dag_module = convert::ConvertToDAGModule::new(module).run();
 
     let mut pass_mgr = ModulePassManager::new();
-    pass_mgr.add_pass(combine::Combine::new());
+    pass_mgr.instrumentation = config.instrumentation.clone();
+    pass_mgr.dot_printer = Some(DAGModule::to_dot);
+    for _ in 0..config.dag_combine_rounds() {
+        pass_mgr.add_pass(combine::Combine::new());
+    }
     pass_mgr.add_pass(dag::legalize::Legalize::new());
     pass_mgr.add_pass(dag::isel::MISelector::new());
     pass_mgr.run_on_module(&mut dag_module);
@@ -110,10 +129,14 @@ pub fn standard_conversion_into_machine_module(module: &mut Module) -> MachineMo
     let mut machine_module = dag::mc_convert::convert_module(dag_module);
 
     let mut pass_mgr = ModulePassManager::new();
+    pass_mgr.instrumentation = config.instrumentation.clone();
+    pass_mgr.dot_printer = Some(MachineModule::to_dot);
     pass_mgr.add_pass(phi_elimination::PhiElimination::new());
     // pass_mgr.add_pass(machine::two_addr::TwoAddressConverter::new());
     pass_mgr.add_pass(machine::regalloc::RegisterAllocator::new());
-    pass_mgr.add_pass(branch_folding::BranchFolding::new());
+    if config.fold_branches() {
+        pass_mgr.add_pass(branch_folding::BranchFolding::new());
+    }
     pass_mgr.add_pass(machine::validate_frame_index::ValidateFrameIndex::new());
     pass_mgr.add_pass(machine::pro_epi_inserter::PrologueEpilogueInserter::new());
     pass_mgr.add_pass(machine::replace_copy::ReplaceCopyWithProperMInst::new());
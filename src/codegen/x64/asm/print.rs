@@ -14,6 +14,7 @@ use rustc_hash::FxHashMap;
 pub struct MachineAsmPrinter {
     pub output: String,
     cur_bb_id_base: usize,
+    cur_func_index: usize,
     id_to_global_name: FxHashMap<GlobalVariableId, String>,
 }
 
@@ -22,6 +23,7 @@ impl MachineAsmPrinter {
         Self {
             output: "".to_string(),
             cur_bb_id_base: 0,
+            cur_func_index: 0,
             id_to_global_name: FxHashMap::default(),
         }
     }
@@ -83,9 +85,12 @@ impl MachineAsmPrinter {
             return;
         }
 
+        // Constant labels are numbered by the position of the function, not by the id of
+        // its arena, which depends on the order the functions were lowered in.
+        self.cur_func_index += 1;
         for (id, data) in f.const_data.id_and_data() {
             self.output
-                .push_str(format!(".Lconst{}{}:\n", id.arena_id(), id.id()).as_str());
+                .push_str(format!(".Lconst{}_{}:\n", self.cur_func_index, id.id()).as_str());
             self.output.push_str(
                 format!("  .quad {}\n", unsafe {
                     ::std::mem::transmute::<f64, u64>(data.as_f64())
@@ -284,8 +289,8 @@ impl MachineAsmPrinter {
 
     fn data_id_to_label_id(&self, data_id: &DataId) -> String {
         format!(
-            "qword ptr [rip + .Lconst{}{}]",
-            data_id.arena_id(),
+            "qword ptr [rip + .Lconst{}_{}]",
+            self.cur_func_index,
             data_id.id()
         )
     }
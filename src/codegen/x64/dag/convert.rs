@@ -17,7 +17,7 @@ impl<'a> ConvertToDAGNode<'a> {
             let byval = self.func.get_param_attr(i).map_or(false, |attr| attr.byval);
             if let Some(ty) = self.func.get_param_type(i) {
                 if byval {
-                    let base = &self.func.types.base.borrow();
+                    let base = &self.func.types.base.read().unwrap();
                     let struct_ty = base
                         .as_struct_ty(base.get_element_ty(ty, None).unwrap())
                         .unwrap();
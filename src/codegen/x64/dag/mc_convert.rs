@@ -315,7 +315,8 @@ impl<'a> ScheduleByBlock<'a> {
             let byval = self
                 .types
                 .base
-                .borrow()
+                .read()
+                .unwrap()
                 .as_function_ty(f_ty)
                 .unwrap()
                 .params_attr
@@ -333,7 +334,7 @@ impl<'a> ScheduleByBlock<'a> {
 
         for (i, arg) in args.into_iter().enumerate() {
             let (ty, byval) = {
-                let base = self.types.base.borrow();
+                let base = self.types.base.read().unwrap();
                 let f = &base.as_function_ty(f_ty).unwrap();
                 (
                     *f.params_ty.get(i).unwrap(),
@@ -465,7 +466,7 @@ impl<'a> ScheduleByBlock<'a> {
     {
         let mut arg_regs = vec![];
         let struct_ty = self.cur_func.types.get_element_ty(ty, None).unwrap();
-        let base = &self.cur_func.types.base.borrow();
+        let base = &self.cur_func.types.base.read().unwrap();
         let struct_ty = base.as_struct_ty(struct_ty).unwrap();
         let sz = struct_ty.size();
         let mov8 = sz / 8;
@@ -142,7 +142,8 @@ impl Assembler {
             .module
             .types
             .base
-            .borrow()
+            .read()
+            .unwrap()
             .as_function_ty(self.module.function_ref(func_id).ty)
             .unwrap()
             .ret_ty
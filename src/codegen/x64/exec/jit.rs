@@ -159,7 +159,8 @@ impl JITCompiler {
         match module
             .types
             .base
-            .borrow()
+            .read()
+            .unwrap()
             .as_function_ty(module.function_ref(id).ty)
             .unwrap()
             .ret_ty
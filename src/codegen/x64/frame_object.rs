@@ -27,7 +27,7 @@ impl FrameObjectsInfo {
         let saved_regs_byte = saved_regs.len() * 8;
         let padding = |off, align| -> i32 { (align - off % align) % align };
 
-        let base = &tys.base.borrow();
+        let base = &tys.base.read().unwrap();
         let f_ty = base.as_function_ty(f.ty).unwrap();
 
         let abi = SystemV::new();
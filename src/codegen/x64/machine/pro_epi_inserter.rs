@@ -144,7 +144,7 @@ impl PrologueEpilogueInserter {
         }
 
         self.insert_arg_copy(
-            &tys.base.borrow(),
+            &tys.base.read().unwrap(),
             &mut builder,
             saved_regs.len() as i32 * 8,
         );
@@ -271,7 +271,7 @@ impl<'a> CopyArgs<'a> {
         ABI: CallingConv,
     {
         let base = self.builder.function.types.base.clone();
-        let base = base.borrow();
+        let base = base.read().unwrap();
         let struct_ty = base.as_struct_ty(ty).unwrap();
         let sz = struct_ty.size();
         let mov8 = sz / 8;
@@ -6,13 +6,21 @@ pub mod machine;
 
 use crate::{
     codegen::common::{
-        dag::{combine, convert},
+        dag::{combine, convert, module::DAGModule},
         machine::{branch_folding, eliminate_fi, module::MachineModule, phi_elimination},
+        pipeline::PipelineConfig,
     },
     ir,
     ir::module::Module,
     ir::types::*,
-    traits::pass::ModulePassManager,
+    traits::pass::{ModulePassManager, ModulePassTrait},
+};
+use id_arena::Arena;
+use std::{
+    collections::VecDeque,
+    panic,
+    sync::{Arc, Mutex},
+    thread,
 };
 
 impl TypeSize for Type {
@@ -87,20 +95,36 @@ impl TypeSize for StructType {
     }
 }
 
-pub fn standard_conversion_into_machine_module(mut module: Module) -> MachineModule {
-    ir::dce::DeadCodeElimination::new().run_on_module(&mut module);
+pub fn standard_conversion_into_machine_module(module: Module) -> MachineModule {
+    standard_conversion_into_machine_module_with_config(module, &PipelineConfig::default())
+}
+
+pub fn standard_conversion_into_machine_module_with_config(
+    mut module: Module,
+    config: &PipelineConfig,
+) -> MachineModule {
     ir::merge_ret::MergeReturns::new().run_on_module(&mut module);
-    ir::const_folding::ConstantFolding::new().run_on_module(&mut module);
-    ir::inst_combine::InstructionCombine::new().run_on_module(&mut module);
+    config.ir_pass_manager().run_on_module(&mut module);
+    ir::lower_select::LowerSelect::new().run_on_module(&mut module);
     ir::codegen_prepare::CodegenPrepare::new().run_on_module(&mut module);
 
-    let mut dag_module = convert::convert_to_dag_module(module);
+    let dag_module = convert::convert_to_dag_module(module);
 
+    if config.lowers_in_parallel() {
+        lower_dag_module_in_parallel(dag_module, config)
+    } else {
+        lower_dag_module(dag_module, config)
+    }
+}
+
+/// Runs the DAG passes, converts the DAG into machine code and runs the machine passes.
+fn lower_dag_module(mut dag_module: DAGModule, config: &PipelineConfig) -> MachineModule {
     let mut pass_mgr = ModulePassManager::new();
-    // TODO: This is so nonsense
-    pass_mgr.add_pass(combine::Combine::new());
-    pass_mgr.add_pass(combine::Combine::new());
-    pass_mgr.add_pass(combine::Combine::new());
+    pass_mgr.instrumentation = config.instrumentation.clone();
+    pass_mgr.dot_printer = Some(DAGModule::to_dot);
+    for _ in 0..config.dag_combine_rounds() {
+        pass_mgr.add_pass(combine::Combine::new());
+    }
     pass_mgr.add_pass(dag::legalize::Legalize::new());
     pass_mgr.add_pass(dag::isel::MISelector::new());
     pass_mgr.run_on_module(&mut dag_module);
@@ -108,10 +132,14 @@ pub fn standard_conversion_into_machine_module(mut module: Module) -> MachineMod
     let mut machine_module = dag::mc_convert::convert_module(dag_module);
 
     let mut pass_mgr = ModulePassManager::new();
+    pass_mgr.instrumentation = config.instrumentation.clone();
+    pass_mgr.dot_printer = Some(MachineModule::to_dot);
     pass_mgr.add_pass(phi_elimination::PhiElimination::new());
     pass_mgr.add_pass(machine::two_addr::TwoAddressConverter::new());
     pass_mgr.add_pass(machine::regalloc::RegisterAllocator::new());
-    pass_mgr.add_pass(branch_folding::BranchFolding::new());
+    if config.fold_branches() {
+        pass_mgr.add_pass(branch_folding::BranchFolding::new());
+    }
     pass_mgr.add_pass(machine::pro_epi_inserter::PrologueEpilogueInserter::new());
     pass_mgr.add_pass(machine::replace_copy::ReplaceCopyWithProperMInst::new());
     pass_mgr.add_pass(machine::replace_data::ReplaceConstFPWithMemoryRef::new());
@@ -120,3 +148,73 @@ pub fn standard_conversion_into_machine_module(mut module: Module) -> MachineMod
 
     machine_module
 }
+
+/// Does the same as `lower_dag_module` with every function lowered on one of
+/// `config.threads` threads. The functions keep their order in the module, so the result
+/// is the same as the sequential one.
+fn lower_dag_module_in_parallel(dag_module: DAGModule, config: &PipelineConfig) -> MachineModule {
+    // Every type the backend needs has been created while converting into DAG. Freezing
+    // the table makes sure no type id depends on how the threads are scheduled.
+    dag_module.types.freeze();
+
+    let DAGModule {
+        name,
+        functions,
+        types,
+        global_vars,
+        const_pool,
+    } = dag_module;
+
+    let queue: VecDeque<(usize, DAGModule)> = functions
+        .into_iter()
+        .enumerate()
+        .map(|(i, (_, func))| {
+            let mut single = DAGModule {
+                name: name.clone(),
+                functions: Arena::new(),
+                types: types.clone(),
+                global_vars: global_vars.clone(),
+                const_pool: const_pool.clone(),
+            };
+            single.add_function(func);
+            (i, single)
+        })
+        .collect();
+    let num_threads = config.threads.min(queue.len());
+    let queue = Arc::new(Mutex::new(queue));
+
+    let workers: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let queue = queue.clone();
+            let config = config.clone();
+            thread::spawn(move || {
+                let mut lowered = vec![];
+                loop {
+                    let next = queue.lock().unwrap().pop_front();
+                    match next {
+                        Some((i, single)) => lowered.push((i, lower_dag_module(single, &config))),
+                        None => return lowered,
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let mut lowered: Vec<(usize, MachineModule)> = workers
+        .into_iter()
+        .flat_map(|worker| worker.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+        .collect();
+    lowered.sort_by_key(|(i, _)| *i);
+
+    // The types are only frozen while the threads share them.
+    types.unfreeze();
+
+    let mut machine_module = MachineModule::new(name, Arena::new(), types, global_vars, const_pool);
+    for (_, single) in lowered {
+        for (_, func) in single.functions {
+            machine_module.add_function(func);
+        }
+    }
+
+    machine_module
+}
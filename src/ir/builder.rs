@@ -1,5 +1,31 @@
 use super::{basic_block::*, function::*, module::Module, opcode::*, types::*, value::*};
 
+/// An error reported by the `try_build_*` family of [IRBuilder](trait.IRBuilder.html) when the
+/// instruction to be built would be ill-typed or placed where it can't be.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// No basic block to insert into has been set
+    NoInsertPoint,
+    /// An instruction would follow the terminator of the block
+    InstAfterTerminator(Opcode, BasicBlockId),
+    /// A terminator would be followed by other instructions of the block
+    TerminatorInMiddle(Opcode, BasicBlockId),
+    /// The operand types of the instruction don't agree with each other
+    OperandTypeMismatch(Opcode, Type, Type),
+    /// The operand type is not allowed for the instruction
+    InvalidOperandType(Opcode, Type),
+    /// The operand must be a pointer
+    NotPointer(Opcode, Type),
+    /// The index can't be used to step into the type
+    InvalidGEPIndex(Type, Value),
+    /// The callee is not a function
+    NotFunction(Type),
+    /// The number of arguments doesn't match the number of parameters (expected, found)
+    ArgumentCountMismatch(usize, usize),
+    /// A phi needs at least one incoming value
+    EmptyPhi,
+}
+
 pub struct IRBuilderWithFunction<'a> {
     func: &'a mut Function,
     block: Option<BasicBlockId>,
@@ -116,6 +142,26 @@ pub trait IRBuilder {
         self.func_ref().get_param_value(idx)
     }
 
+    /// Gives `v` a name shown in dumps. Values other than instructions and arguments are left
+    /// unnamed. Returns `v` so that it can wrap a `build_*` call.
+    fn set_value_name(&mut self, v: Value, name: &str) -> Value {
+        let names = &mut self.func_ref_mut().names;
+        match v {
+            Value::Instruction(InstructionValue { id, .. }) => {
+                names.set_inst(id, name);
+            }
+            Value::Argument(ArgumentValue { index, .. }) => {
+                names.set_param(index, name);
+            }
+            _ => {}
+        }
+        v
+    }
+
+    fn set_block_name(&mut self, id: BasicBlockId, name: &str) {
+        self.func_ref_mut().names.set_block(id, name);
+    }
+
     fn append_basic_block(&mut self) -> BasicBlockId {
         self.func_ref_mut().append_basic_block()
     }
@@ -392,6 +438,20 @@ pub trait IRBuilder {
         inst
     }
 
+    fn build_select(&mut self, cond: Value, then: Value, else_: Value) -> Value {
+        let inst = self.create_inst_value(
+            Opcode::Select,
+            vec![
+                Operand::Value(cond),
+                Operand::Value(then),
+                Operand::Value(else_),
+            ],
+            then.get_type(),
+        );
+        self.append_inst_to_current_block(inst);
+        inst
+    }
+
     fn build_call(&mut self, f: Value, args: Vec<Value>) -> Value {
         let ret_ty = self
             .func_ref()
@@ -412,6 +472,264 @@ pub trait IRBuilder {
         inst
     }
 
+    // ----- checked builders ------
+
+    fn try_build_alloca(&mut self, ty: Type) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Alloca)?;
+        if ty == Type::Void || matches!(ty, Type::Function(_)) {
+            return Err(BuildError::InvalidOperandType(Opcode::Alloca, ty));
+        }
+        Ok(self.build_alloca(ty))
+    }
+
+    fn try_build_gep(&mut self, v: Value, indices: Vec<Value>) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::GetElementPtr)?;
+        let ty = v.get_type();
+        if !matches!(ty, Type::Pointer(_)) {
+            return Err(BuildError::NotPointer(Opcode::GetElementPtr, ty));
+        }
+        check_gep_indices(&self.func_ref().types, ty, &indices)?;
+        Ok(self.build_gep(v, indices))
+    }
+
+    fn try_build_load(&mut self, v: Value) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Load)?;
+        let ty = v.get_type();
+        if !matches!(ty, Type::Pointer(_)) {
+            return Err(BuildError::NotPointer(Opcode::Load, ty));
+        }
+        Ok(self.build_load(v))
+    }
+
+    fn try_build_store(&mut self, src: Value, dst: Value) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Store)?;
+        let dst_ty = dst.get_type();
+        if !matches!(dst_ty, Type::Pointer(_)) {
+            return Err(BuildError::NotPointer(Opcode::Store, dst_ty));
+        }
+        let elem_ty = self.func_ref().types.get_element_ty(dst_ty, None).unwrap();
+        if elem_ty != src.get_type() {
+            return Err(BuildError::OperandTypeMismatch(
+                Opcode::Store,
+                elem_ty,
+                src.get_type(),
+            ));
+        }
+        Ok(self.build_store(src, dst))
+    }
+
+    fn try_build_add(&mut self, v1: Value, v2: Value) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Add)?;
+        check_binary_op(Opcode::Add, v1, v2)?;
+        Ok(self.build_add(v1, v2))
+    }
+
+    fn try_build_sub(&mut self, v1: Value, v2: Value) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Sub)?;
+        check_binary_op(Opcode::Sub, v1, v2)?;
+        Ok(self.build_sub(v1, v2))
+    }
+
+    fn try_build_mul(&mut self, v1: Value, v2: Value) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Mul)?;
+        check_binary_op(Opcode::Mul, v1, v2)?;
+        Ok(self.build_mul(v1, v2))
+    }
+
+    fn try_build_div(&mut self, v1: Value, v2: Value) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Div)?;
+        check_binary_op(Opcode::Div, v1, v2)?;
+        Ok(self.build_div(v1, v2))
+    }
+
+    fn try_build_rem(&mut self, v1: Value, v2: Value) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Rem)?;
+        check_binary_op(Opcode::Rem, v1, v2)?;
+        Ok(self.build_rem(v1, v2))
+    }
+
+    fn try_build_shl(&mut self, v1: Value, v2: Value) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Shl)?;
+        for ty in &[v1.get_type(), v2.get_type()] {
+            if !ty.is_integer() {
+                return Err(BuildError::InvalidOperandType(Opcode::Shl, *ty));
+            }
+        }
+        Ok(self.build_shl(v1, v2))
+    }
+
+    fn try_build_sitofp(&mut self, v: Value, ty: Type) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::SIToFP)?;
+        check_conversion(
+            Opcode::SIToFP,
+            v.get_type().is_integer(),
+            ty.is_float(),
+            v,
+            ty,
+        )?;
+        Ok(self.build_sitofp(v, ty))
+    }
+
+    fn try_build_fptosi(&mut self, v: Value, ty: Type) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::FPToSI)?;
+        check_conversion(
+            Opcode::FPToSI,
+            v.get_type().is_float(),
+            ty.is_integer(),
+            v,
+            ty,
+        )?;
+        Ok(self.build_fptosi(v, ty))
+    }
+
+    fn try_build_sext(&mut self, v: Value, ty: Type) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Sext)?;
+        check_conversion(
+            Opcode::Sext,
+            v.get_type().is_integer(),
+            ty.is_integer(),
+            v,
+            ty,
+        )?;
+        Ok(self.build_sext(v, ty))
+    }
+
+    fn try_build_icmp(
+        &mut self,
+        kind: ICmpKind,
+        v1: Value,
+        v2: Value,
+    ) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::ICmp)?;
+        let (ty1, ty2) = (v1.get_type(), v2.get_type());
+        if !ty1.is_integer() && !matches!(ty1, Type::Pointer(_)) {
+            return Err(BuildError::InvalidOperandType(Opcode::ICmp, ty1));
+        }
+        if ty1 != ty2 {
+            return Err(BuildError::OperandTypeMismatch(Opcode::ICmp, ty1, ty2));
+        }
+        Ok(self.build_icmp(kind, v1, v2))
+    }
+
+    fn try_build_fcmp(
+        &mut self,
+        kind: FCmpKind,
+        v1: Value,
+        v2: Value,
+    ) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::FCmp)?;
+        let (ty1, ty2) = (v1.get_type(), v2.get_type());
+        if !ty1.is_float() {
+            return Err(BuildError::InvalidOperandType(Opcode::FCmp, ty1));
+        }
+        if ty1 != ty2 {
+            return Err(BuildError::OperandTypeMismatch(Opcode::FCmp, ty1, ty2));
+        }
+        Ok(self.build_fcmp(kind, v1, v2))
+    }
+
+    fn try_build_br(&mut self, dst_id: BasicBlockId) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Br)?;
+        Ok(self.build_br(dst_id))
+    }
+
+    fn try_build_cond_br(
+        &mut self,
+        cond: Value,
+        bb1: BasicBlockId,
+        bb2: BasicBlockId,
+    ) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::CondBr)?;
+        if cond.get_type() != Type::i1 {
+            return Err(BuildError::OperandTypeMismatch(
+                Opcode::CondBr,
+                Type::i1,
+                cond.get_type(),
+            ));
+        }
+        Ok(self.build_cond_br(cond, bb1, bb2))
+    }
+
+    fn try_build_phi(&mut self, pairs: Vec<(Value, BasicBlockId)>) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Phi)?;
+        let ty = pairs.get(0).ok_or(BuildError::EmptyPhi)?.0.get_type();
+        for (v, _) in &pairs {
+            if v.get_type() != ty {
+                return Err(BuildError::OperandTypeMismatch(
+                    Opcode::Phi,
+                    ty,
+                    v.get_type(),
+                ));
+            }
+        }
+        Ok(self.build_phi(pairs))
+    }
+
+    fn try_build_call(&mut self, f: Value, args: Vec<Value>) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Call)?;
+        let f_ty = f.get_type();
+        let params_ty = match f_ty {
+            Type::Function(_) => self
+                .func_ref()
+                .types
+                .compound_ty(f_ty)
+                .as_function()
+                .params_ty
+                .clone(),
+            _ => return Err(BuildError::NotFunction(f_ty)),
+        };
+        if params_ty.len() != args.len() {
+            return Err(BuildError::ArgumentCountMismatch(
+                params_ty.len(),
+                args.len(),
+            ));
+        }
+        for (param_ty, arg) in params_ty.iter().zip(args.iter()) {
+            if *param_ty != arg.get_type() {
+                return Err(BuildError::OperandTypeMismatch(
+                    Opcode::Call,
+                    *param_ty,
+                    arg.get_type(),
+                ));
+            }
+        }
+        Ok(self.build_call(f, args))
+    }
+
+    fn try_build_ret(&mut self, v: Value) -> Result<Value, BuildError> {
+        self.check_insert_point(Opcode::Ret)?;
+        let ret_ty = self.func_ref().get_return_type();
+        if ret_ty != v.get_type() {
+            return Err(BuildError::OperandTypeMismatch(
+                Opcode::Ret,
+                ret_ty,
+                v.get_type(),
+            ));
+        }
+        Ok(self.build_ret(v))
+    }
+
+    /// Checks if an instruction of `opcode` can be inserted at the current insert point.
+    fn check_insert_point(&self, opcode: Opcode) -> Result<(), BuildError> {
+        let block = self.block().ok_or(BuildError::NoInsertPoint)?;
+        let func = self.func_ref();
+        let iseq = func.basic_block_ref(block).iseq_ref();
+        let pt = self.insert_point();
+        let after_terminator = pt > 0
+            && iseq.get(pt - 1).map_or(false, |v| {
+                func.inst_table[v.as_instruction().id]
+                    .opcode
+                    .is_terminator()
+            });
+        if after_terminator {
+            return Err(BuildError::InstAfterTerminator(opcode, block));
+        }
+        if opcode.is_terminator() && pt < iseq.len() {
+            return Err(BuildError::TerminatorInMiddle(opcode, block));
+        }
+        Ok(())
+    }
+
     fn is_last_inst_terminator(&self) -> bool {
         let bb = self.func_ref().basic_block_ref(self.block().unwrap());
         bb.iseq_ref().last().map_or(false, |i| {
@@ -453,3 +771,65 @@ pub trait IRBuilder {
         f(function)
     }
 }
+
+fn check_binary_op(opcode: Opcode, v1: Value, v2: Value) -> Result<(), BuildError> {
+    let (ty1, ty2) = (v1.get_type(), v2.get_type());
+    let allowed = match opcode {
+        Opcode::Rem => ty1.is_integer(),
+        _ => ty1.is_integer() || ty1.is_float(),
+    };
+    if !allowed {
+        return Err(BuildError::InvalidOperandType(opcode, ty1));
+    }
+    if ty1 != ty2 {
+        return Err(BuildError::OperandTypeMismatch(opcode, ty1, ty2));
+    }
+    Ok(())
+}
+
+fn check_conversion(
+    opcode: Opcode,
+    from_ok: bool,
+    to_ok: bool,
+    v: Value,
+    to: Type,
+) -> Result<(), BuildError> {
+    if !from_ok {
+        return Err(BuildError::InvalidOperandType(opcode, v.get_type()));
+    }
+    if !to_ok {
+        return Err(BuildError::InvalidOperandType(opcode, to));
+    }
+    Ok(())
+}
+
+// Follows the same rules as Types::get_element_ty_with_indices, but reports what it can't handle
+// instead of panicking.
+fn check_gep_indices(types: &Types, mut ty: Type, indices: &[Value]) -> Result<(), BuildError> {
+    for idx in indices {
+        if !idx.get_type().is_integer() {
+            return Err(BuildError::InvalidGEPIndex(ty, *idx));
+        }
+        ty = match ty {
+            Type::Pointer(_) | Type::Array(_) => types.get_element_ty(ty, None).unwrap(),
+            Type::Struct(_) => {
+                let n = match idx {
+                    Value::Immediate(ImmediateValue::Int32(n)) if *n >= 0 => *n as usize,
+                    _ => return Err(BuildError::InvalidGEPIndex(ty, *idx)),
+                };
+                let field_ty = types
+                    .base
+                    .read()
+                    .unwrap()
+                    .as_struct_ty(ty)
+                    .unwrap()
+                    .fields_ty()
+                    .get(n)
+                    .copied();
+                field_ty.ok_or(BuildError::InvalidGEPIndex(ty, *idx))?
+            }
+            _ => return Err(BuildError::InvalidGEPIndex(ty, *idx)),
+        };
+    }
+    Ok(())
+}
@@ -65,11 +65,17 @@ impl<'a> CodegenPrepareOnFunction<'a> {
     }
 
     fn able_to_be_sunk(&self, inst: &Instruction) -> bool {
-        inst.has_one_use() || {
-            inst.users
-                .borrow()
-                .windows(2)
-                .all(|us| self.func.inst_table[us[0]].parent == self.func.inst_table[us[1]].parent)
-        }
+        // A phi uses the value at the end of a predecessor, so the GEP must stay there.
+        let used_by_phi = inst
+            .users
+            .borrow()
+            .iter()
+            .any(|&u| self.func.inst_table[u].opcode == Opcode::Phi);
+        !used_by_phi
+            && (inst.has_one_use() || {
+                inst.users.borrow().windows(2).all(|us| {
+                    self.func.inst_table[us[0]].parent == self.func.inst_table[us[1]].parent
+                })
+            })
     }
 }
@@ -1,15 +1,19 @@
-use crate::ir::{
-    function::Function,
-    module::Module,
-    opcode::{Instruction, Opcode, Operand},
-    value::Value,
+use crate::{
+    analysis::manager::{AnalysisManager, PreservedAnalyses},
+    ir::{
+        function::Function,
+        module::Module,
+        opcode::{Instruction, Opcode, Operand},
+        value::Value,
+    },
+    traits::pass::FunctionPassTrait,
 };
 use std::collections::VecDeque;
 // use rustc_hash::FxHashMap;
 
 pub struct ConstantFolding {}
 
-struct ConstantFoldingOnFunction<'a> {
+pub struct ConstantFoldingOnFunction<'a> {
     cur_func: &'a mut Function,
 }
 
@@ -25,6 +29,21 @@ impl ConstantFolding {
     }
 }
 
+impl FunctionPassTrait for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "ConstantFolding"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        _am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        ConstantFoldingOnFunction::new(func).run();
+        PreservedAnalyses::cfg()
+    }
+}
+
 impl<'a> ConstantFoldingOnFunction<'a> {
     pub fn new(cur_func: &'a mut Function) -> Self {
         Self { cur_func }
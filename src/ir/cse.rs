@@ -1,4 +1,7 @@
-use crate::analysis::dom_tree::{DominatorTree, DominatorTreeConstructor};
+use crate::analysis::{
+    dom_tree::DominatorTree,
+    manager::{AnalysisManager, PreservedAnalyses},
+};
 use crate::ir::{
     basic_block::{BasicBlock, BasicBlockId},
     function::Function,
@@ -6,6 +9,7 @@ use crate::ir::{
     opcode::{Instruction, InstructionId, Opcode, Operand},
     value::{InstructionValue, Value},
 };
+use crate::traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait};
 // use crate::traits::basic_block::*;
 use id_arena::Arena;
 use rustc_hash::{FxHashMap, FxHashSet};
@@ -25,19 +29,41 @@ impl CommonSubexprElimination {
     }
 
     pub fn run_on_module(&mut self, module: &mut Module) {
-        for (_, func) in &mut module.functions {
-            if func.is_internal || func.is_empty() {
-                continue;
-            }
+        run_function_pass_on_module(self, module)
+    }
+}
 
-            GlobalCommonSubexprEliminationOnFunction {
-                func,
-                bb_avails: AvailsInBB::default(),
-                dom_frontiers: FxHashSet::default(),
-                removal_list: vec![],
-            }
-            .run()
+impl ModulePassTrait for CommonSubexprElimination {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "CommonSubexprElimination"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for CommonSubexprElimination {
+    fn name(&self) -> &'static str {
+        "CommonSubexprElimination"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let dom_tree = am.get_dom_tree(func);
+        GlobalCommonSubexprEliminationOnFunction {
+            func,
+            bb_avails: AvailsInBB::default(),
+            dom_frontiers: FxHashSet::default(),
+            removal_list: vec![],
         }
+        .run(&dom_tree);
+        PreservedAnalyses::cfg()
     }
 }
 
@@ -214,11 +240,9 @@ impl<'a> GlobalCommonSubexprEliminationOnFunction<'a> {
         }
     }
 
-    pub fn run(mut self) {
-        let dom_tree = DominatorTreeConstructor::new(&self.func.basic_blocks).construct();
-
+    pub fn run(mut self, dom_tree: &DominatorTree<BasicBlock>) {
         self.run_sub(
-            &dom_tree,
+            dom_tree,
             self.func.basic_blocks.order[0],
             FxHashMap::default(),
         );
@@ -1,8 +1,12 @@
-use crate::ir::{
-    function::Function,
-    module::Module,
-    opcode::{Instruction, InstructionId, Opcode, Operand},
-    value::{InstructionValue, Value},
+use crate::{
+    analysis::manager::{AnalysisManager, PreservedAnalyses},
+    ir::{
+        function::Function,
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        value::{InstructionValue, Value},
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
 };
 
 pub struct DeadCodeElimination {}
@@ -17,13 +21,34 @@ impl DeadCodeElimination {
     }
 
     pub fn run_on_module(&mut self, module: &mut Module) {
-        for (_, func) in &mut module.functions {
-            if func.is_internal || func.is_empty() {
-                continue;
-            }
+        run_function_pass_on_module(self, module)
+    }
+}
 
-            DeadCodeEliminationOnFunction { func }.run()
-        }
+impl ModulePassTrait for DeadCodeElimination {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "DeadCodeElimination"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for DeadCodeElimination {
+    fn name(&self) -> &'static str {
+        "DeadCodeElimination"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        _am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        DeadCodeEliminationOnFunction { func }.run();
+        PreservedAnalyses::cfg()
     }
 }
 
@@ -0,0 +1,254 @@
+use crate::{
+    analysis::{
+        alias::{AliasAnalysis, AliasResult},
+        dom_tree::DominatorTree,
+        manager::{AnalysisManager, PreservedAnalyses},
+    },
+    ir::{
+        basic_block::{BasicBlock, BasicBlockId},
+        function::Function,
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        value::Value,
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Removes redundant memory accesses that `Mem2Reg` leaves, such as those to allocas
+/// whose address is taken and to globals.
+///
+/// A load is replaced by the value of a dominating store to the same address if no
+/// instruction on any path in between may write to it. Then a store is removed if it's
+/// overwritten later in its block before being read, or if it's to a local object that
+/// is never read again before the function returns.
+pub struct DeadStoreElimination {}
+
+struct DeadStoreEliminationOnFunction<'a> {
+    func: &'a Function,
+    aa: AliasAnalysis<'a>,
+}
+
+impl DeadStoreElimination {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for DeadStoreElimination {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "DeadStoreElimination"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for DeadStoreElimination {
+    fn name(&self) -> &'static str {
+        "DeadStoreElimination"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let dom_tree = am.get_dom_tree(func);
+        let replacements = DeadStoreEliminationOnFunction::new(func).forward_stores(&dom_tree);
+
+        // A stored value may be a load replaced itself.
+        let map: FxHashMap<InstructionId, Value> = replacements.iter().copied().collect();
+        let resolve = |mut val: Value| {
+            while let Some(&to) = val.get_inst_id().and_then(|id| map.get(&id)) {
+                val = to
+            }
+            val
+        };
+        for &(id, val) in &replacements {
+            Instruction::replace_all_uses(&mut func.inst_table, id, Operand::Value(resolve(val)));
+        }
+        for &(id, _) in &replacements {
+            func.remove_inst(id);
+        }
+
+        let dead_stores = DeadStoreEliminationOnFunction::new(func).find_dead_stores();
+        for &id in &dead_stores {
+            func.remove_inst(id);
+        }
+
+        if replacements.is_empty() && dead_stores.is_empty() {
+            PreservedAnalyses::all()
+        } else {
+            PreservedAnalyses::cfg()
+        }
+    }
+}
+
+impl<'a> DeadStoreEliminationOnFunction<'a> {
+    fn new(func: &'a Function) -> Self {
+        Self {
+            func,
+            aa: AliasAnalysis::new(func),
+        }
+    }
+
+    /// Returns the loads to replace with stored values, in dominator tree order.
+    fn forward_stores(&self, dom_tree: &DominatorTree<BasicBlock>) -> Vec<(InstructionId, Value)> {
+        let mut replacements = vec![];
+        if let Some(entry) = self.func.get_entry_block() {
+            self.forward_stores_in_block(entry, vec![], dom_tree, &mut replacements);
+        }
+        replacements
+    }
+
+    // `stores` are the pointers and the values stored to them available at the start of
+    // `block`.
+    fn forward_stores_in_block(
+        &self,
+        block: BasicBlockId,
+        mut stores: Vec<(Value, Value)>,
+        dom_tree: &DominatorTree<BasicBlock>,
+        replacements: &mut Vec<(InstructionId, Value)>,
+    ) {
+        for val in &*self.func.basic_blocks.arena[block].iseq_ref() {
+            let id = val.as_instruction().id;
+            let inst = &self.func.inst_table[id];
+            match inst.opcode {
+                Opcode::Load => {
+                    let ptr = inst.operands[0].as_value();
+                    let stored = stores.iter().rev().find(|(p, v)| {
+                        v.get_type() == inst.ty && self.aa.alias(p, ptr) == AliasResult::MustAlias
+                    });
+                    if let Some(&(_, stored)) = stored {
+                        replacements.push((id, stored))
+                    }
+                }
+                Opcode::Store => {
+                    self.kill(id, &mut stores);
+                    stores.push((*inst.operands[1].as_value(), *inst.operands[0].as_value()));
+                }
+                Opcode::Call => self.kill(id, &mut stores),
+                _ => {}
+            }
+        }
+
+        let mut children: Vec<BasicBlockId> = dom_tree
+            .children_of(block)
+            .map_or(vec![], |children| children.iter().copied().collect());
+        children.sort_by_key(|id| id.index());
+
+        for child in children {
+            let mut child_stores = stores.clone();
+            self.kill_on_paths(block, child, &mut child_stores);
+            self.forward_stores_in_block(child, child_stores, dom_tree, replacements);
+        }
+    }
+
+    // Kills `stores` that may be overwritten on a path from the end of `idom` to the
+    // start of `block`, which `idom` immediately dominates.
+    fn kill_on_paths(
+        &self,
+        idom: BasicBlockId,
+        block: BasicBlockId,
+        stores: &mut Vec<(Value, Value)>,
+    ) {
+        let blocks = &self.func.basic_blocks.arena;
+        let mut worklist: Vec<BasicBlockId> = blocks[block].pred.iter().copied().collect();
+        let mut visited = FxHashSet::default();
+
+        while let Some(pred) = worklist.pop() {
+            if stores.is_empty() {
+                return;
+            }
+            if pred == idom || !visited.insert(pred) {
+                continue;
+            }
+            for val in &*blocks[pred].iseq_ref() {
+                self.kill(val.as_instruction().id, stores);
+            }
+            worklist.extend(blocks[pred].pred.iter().copied());
+        }
+    }
+
+    fn kill(&self, id: InstructionId, stores: &mut Vec<(Value, Value)>) {
+        stores.retain(|(ptr, _)| !self.aa.mod_ref(id, ptr).may_mod());
+    }
+
+    fn find_dead_stores(&self) -> Vec<InstructionId> {
+        let mut dead_stores = vec![];
+
+        for &block in &self.func.basic_blocks.order {
+            let iseq: Vec<InstructionId> = self.func.basic_blocks.arena[block]
+                .iseq_ref()
+                .iter()
+                .map(|v| v.as_instruction().id)
+                .collect();
+            for (pos, &id) in iseq.iter().enumerate() {
+                let inst = &self.func.inst_table[id];
+                if inst.opcode != Opcode::Store {
+                    continue;
+                }
+                let ptr = inst.operands[1].as_value();
+                let rest = &iseq[pos + 1..];
+                if self.is_overwritten(rest, ptr)
+                    || (self.aa.is_local(ptr) && !self.may_be_read(block, rest, ptr))
+                {
+                    dead_stores.push(id)
+                }
+            }
+        }
+
+        dead_stores
+    }
+
+    // Returns true if `ptr` is written by a store in `insts` before anything may read it.
+    fn is_overwritten(&self, insts: &[InstructionId], ptr: &Value) -> bool {
+        for &id in insts {
+            if self.aa.mod_ref(id, ptr).may_ref() {
+                return false;
+            }
+            let inst = &self.func.inst_table[id];
+            if inst.opcode == Opcode::Store
+                && self.aa.alias(inst.operands[1].as_value(), ptr) == AliasResult::MustAlias
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Returns true if `ptr` may be read by `insts`, the end of `block`, or by any block
+    // reachable from `block`.
+    fn may_be_read(&self, block: BasicBlockId, insts: &[InstructionId], ptr: &Value) -> bool {
+        let reads = |id: &InstructionId| self.aa.mod_ref(*id, ptr).may_ref();
+        if insts.iter().any(reads) {
+            return true;
+        }
+
+        let blocks = &self.func.basic_blocks.arena;
+        let mut worklist: Vec<BasicBlockId> = blocks[block].succ.iter().copied().collect();
+        let mut visited = FxHashSet::default();
+        while let Some(block) = worklist.pop() {
+            if !visited.insert(block) {
+                continue;
+            }
+            if blocks[block]
+                .iseq_ref()
+                .iter()
+                .any(|v| reads(&v.as_instruction().id))
+            {
+                return true;
+            }
+            worklist.extend(blocks[block].succ.iter().copied());
+        }
+        false
+    }
+}
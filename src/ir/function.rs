@@ -1,8 +1,12 @@
-use super::{basic_block::*, module::Module, opcode::*, types::*, value::*, DumpToString};
+use super::{
+    basic_block::*, module::Module, names::Names, opcode::*, types::*, value::*, DumpToString,
+};
 use crate::analysis::Analysis;
 use crate::codegen::is_internal_function;
 use crate::traits::function::FunctionTrait;
+use crate::util::dot::Dot;
 use id_arena::*;
+use rustc_hash::FxHashSet;
 
 pub type FunctionId = Id<Function>;
 
@@ -41,6 +45,21 @@ pub struct Function {
     pub types: Types,
 
     pub is_internal: bool,
+
+    /// Optional names of instructions, parameters and basic blocks
+    pub names: Names,
+
+    pub attributes: FunctionAttributes,
+}
+
+/// Hints for interprocedural passes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FunctionAttributes {
+    /// Never inline calls to the function
+    pub noinline: bool,
+
+    /// Inline calls to the function regardless of its size
+    pub alwaysinline: bool,
 }
 
 impl Function {
@@ -55,6 +74,8 @@ impl Function {
             analyses: vec![],
             types: module.types.clone(),
             is_internal: is_internal_function(name),
+            names: Names::new(),
+            attributes: FunctionAttributes::default(),
         })
     }
 
@@ -136,6 +157,12 @@ impl Function {
             .map_or(None, |&a| Some(a))
     }
 
+    /// Replaces the attribute of the `idx`th parameter. This changes the type of the
+    /// function, so it should be done before any call to the function is built.
+    pub fn set_param_attr(&mut self, idx: usize, attr: ParamAttribute) {
+        self.ty = self.types.with_param_attr(self.ty, idx, attr);
+    }
+
     pub fn get_params_len(&self) -> usize {
         self.types
             .compound_ty(self.ty)
@@ -144,6 +171,27 @@ impl Function {
             .len()
     }
 
+    /// Returns the name used to refer to the instruction in dumps: its given name or its index.
+    pub fn inst_name(&self, id: InstructionId) -> String {
+        self.names
+            .get_inst(id)
+            .map_or_else(|| id.index().to_string(), |name| name.clone())
+    }
+
+    /// Returns the name used to refer to the `idx`-th parameter in dumps.
+    pub fn param_name(&self, idx: usize) -> String {
+        self.names
+            .get_param(idx)
+            .map_or_else(|| format!("arg.{}", idx), |name| name.clone())
+    }
+
+    /// Returns the label used to refer to the basic block in dumps.
+    pub fn block_label(&self, id: BasicBlockId) -> String {
+        self.names
+            .get_block(id)
+            .map_or_else(|| format!("label.{}", id.index()), |name| name.clone())
+    }
+
     pub fn find_inst_pos(&self, inst_id: InstructionId) -> Option<(BasicBlockId, usize)> {
         let parent = self.inst_table[inst_id].parent;
         self.basic_blocks.arena[parent]
@@ -178,6 +226,42 @@ impl Function {
         id
     }
 
+    pub fn get_inst_value(&self, id: InstructionId) -> Value {
+        Value::Instruction(InstructionValue {
+            func_id: self.id.unwrap(),
+            id,
+            ty: self.inst_table[id].ty,
+        })
+    }
+
+    /// Returns the phis at the start of `block`.
+    pub fn get_phis(&self, block: BasicBlockId) -> Vec<InstructionId> {
+        self.basic_blocks.arena[block]
+            .iseq_ref()
+            .iter()
+            .map(|v| v.as_instruction().id)
+            .take_while(|&id| self.inst_table[id].opcode == Opcode::Phi)
+            .collect()
+    }
+
+    /// Returns the value `phi` takes when control comes from `pred`.
+    pub fn get_incoming(&self, phi: InstructionId, pred: BasicBlockId) -> Option<Value> {
+        self.inst_table[phi]
+            .operands
+            .chunks(2)
+            .find(|pair| *pair[1].as_basic_block() == pred)
+            .map(|pair| *pair[0].as_value())
+    }
+
+    pub fn get_terminator(&self, block: BasicBlockId) -> Option<InstructionId> {
+        let id = self.basic_blocks.arena[block]
+            .iseq_ref()
+            .last()?
+            .as_instruction()
+            .id;
+        Some(id).filter(|&id| self.inst_table[id].opcode.is_terminator())
+    }
+
     pub fn change_inst(&mut self, id: InstructionId, mut inst: Instruction) {
         inst.set_id(id);
         let users = self.inst_table[id].users.clone();
@@ -231,10 +315,10 @@ impl FunctionTrait for Function {
 
 impl DumpToString for &Function {
     fn dump(&self, module: &Module) -> String {
-        let base = module.types.base.borrow();
+        let base = module.types.base.read().unwrap();
         let ty = base.as_function_ty(self.ty).unwrap();
         format!(
-            "define {} {}({}) {}",
+            "define {} {}({}){}{} {}",
             base.to_string(ty.ret_ty),
             self.name,
             ty.params_ty
@@ -242,65 +326,117 @@ impl DumpToString for &Function {
                 .enumerate()
                 .fold("".to_string(), |mut s, (i, p)| {
                     s += &(base.to_string(*p)
-                        + ty.params_attr
-                            .get(&i)
-                            .map_or("", |a| if a.byval { " byval" } else { "" })
+                        + &ty.params_attr.get(&i).map_or("".to_string(), |a| {
+                            format!(
+                                "{}{}",
+                                if a.byval { " byval" } else { "" },
+                                if a.noalias { " noalias" } else { "" }
+                            )
+                        })
+                        + &self
+                            .names
+                            .get_param(i)
+                            .map_or("".to_string(), |name| format!(" %{}", name))
                         + ", ");
                     s
                 })
                 .trim_matches(&[',', ' '][0..]),
+            if self.attributes.noinline {
+                " noinline"
+            } else {
+                ""
+            },
+            if self.attributes.alwaysinline {
+                " alwaysinline"
+            } else {
+                ""
+            },
             if self.is_internal {
                 "internal;".to_owned()
             } else {
-                format!("{{\n{}}}", self.basic_blocks.dump(module))
+                format!("{{\n{}}}", self.dump_basic_blocks(module))
             },
         )
     }
 }
 
-impl DumpToString for FunctionId {
-    fn dump(&self, module: &Module) -> String {
-        module.function_ref(*self).dump(module)
+impl Function {
+    /// Returns the control flow graph in Graphviz DOT format. Each node lists the
+    /// instructions of a basic block.
+    pub fn to_dot(&self, module: &Module) -> String {
+        let mut dot = Dot::new(&self.name);
+        let node = |id: BasicBlockId| format!("bb{}", id.index());
+
+        for &id in &self.basic_blocks.order {
+            let block = &self.basic_blocks.arena[id];
+            dot.node(
+                &node(id),
+                &format!("{}:\n{}\n", self.block_label(id), block.dump(module)),
+                "",
+            );
+
+            let term = match block.iseq_ref().last() {
+                Some(term) => &self.inst_table[term.as_instruction().id],
+                None => continue,
+            };
+            let targets = term.operands.iter().filter_map(|op| match op {
+                Operand::BasicBlock(id) => Some(*id),
+                _ => None,
+            });
+            for (i, target) in targets.enumerate() {
+                let attrs = match (term.opcode, i) {
+                    (Opcode::CondBr, 0) => "label=T",
+                    (Opcode::CondBr, _) => "label=F",
+                    _ => "",
+                };
+                dot.edge(&node(id), &node(target), attrs);
+            }
+        }
+
+        dot.finish()
+    }
+
+    fn dump_basic_blocks(&self, module: &Module) -> String {
+        let labels = |set: &FxHashSet<BasicBlockId>| {
+            set.iter()
+                .fold("".to_string(), |s, &x| {
+                    format!("{}{},", s, self.block_label(x))
+                })
+                .trim_matches(',')
+                .to_string()
+        };
+        let insts = |set: &FxHashSet<InstructionId>| {
+            set.iter()
+                .fold("".to_string(), |s, &x| {
+                    format!("{}{},", s, self.inst_name(x))
+                })
+                .trim_matches(',')
+                .to_string()
+        };
+        self.basic_blocks
+            .order
+            .iter()
+            .fold("".to_string(), |s, &id| {
+                let b = &self.basic_blocks.arena[id];
+                let liveness = b.liveness.borrow();
+                format!(
+                    "{}{}:\t// pred({}), succ({}), def({}), in({}), out({})\n{}\n",
+                    s,
+                    self.block_label(id),
+                    labels(&b.pred),
+                    labels(&b.succ),
+                    insts(&liveness.def),
+                    insts(&liveness.live_in),
+                    insts(&liveness.live_out),
+                    b.dump(module)
+                )
+            })
     }
 }
 
-impl DumpToString for BasicBlocks {
+impl DumpToString for FunctionId {
     fn dump(&self, module: &Module) -> String {
-        self.order.iter().fold("".to_string(), |s, &id| {
-            let b = &self.arena[id];
-            format!(
-                "{}label.{}:\t// pred({}), succ({}), def({}), in({}), out({})\n{}\n",
-                s,
-                id.index(),
-                &b.pred
-                    .iter()
-                    .fold("".to_string(), |s, x| format!("{}{},", s, x.index()))
-                    .trim_matches(','),
-                &b.succ
-                    .iter()
-                    .fold("".to_string(), |s, x| format!("{}{},", s, x.index()))
-                    .trim_matches(','),
-                &b.liveness
-                    .borrow()
-                    .def
-                    .iter()
-                    .fold("".to_string(), |s, x| format!("{}{},", s, x.index()))
-                    .trim_matches(','),
-                &b.liveness
-                    .borrow()
-                    .live_in
-                    .iter()
-                    .fold("".to_string(), |s, x| format!("{}{},", s, x.index()))
-                    .trim_matches(','),
-                &b.liveness
-                    .borrow()
-                    .live_out
-                    .iter()
-                    .fold("".to_string(), |s, x| format!("{}{},", s, x.index()))
-                    .trim_matches(','),
-                b.dump(module)
-            )
-        })
+        module.function_ref(*self).dump(module)
     }
 }
 
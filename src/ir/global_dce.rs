@@ -0,0 +1,220 @@
+use crate::{
+    ir::{
+        constant_pool::{ConstantArrayElement, ConstantId, ConstantKind, ConstantPool},
+        function::FunctionId,
+        global_val::{GlobalVariableId, Linkage},
+        module::Module,
+        opcode::Operand,
+        value::Value,
+    },
+    traits::pass::ModulePassTrait,
+};
+use id_arena::Arena;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::mem;
+
+/// Removes the functions, global variables and constants that nothing reachable from
+/// the roots of a module refers to. The roots are `main`, global variables with
+/// `External` linkage and the functions and global variables named in `exports`.
+///
+/// Arenas can't free their entries, so the live ones are moved into new arenas and
+/// every id referring to them is remapped. Cached analyses of the functions are
+/// dropped, since they may hold old ids.
+pub struct GlobalDeadCodeElimination {
+    pub exports: Vec<String>,
+}
+
+/// Everything a module needs to keep
+#[derive(Default)]
+struct LiveSymbols {
+    functions: FxHashSet<FunctionId>,
+    global_vars: FxHashSet<GlobalVariableId>,
+    constants: FxHashSet<ConstantId>,
+}
+
+/// New ids by old ones
+#[derive(Default)]
+struct IdMap {
+    functions: FxHashMap<FunctionId, FunctionId>,
+    global_vars: FxHashMap<GlobalVariableId, GlobalVariableId>,
+    constants: FxHashMap<ConstantId, ConstantId>,
+}
+
+impl GlobalDeadCodeElimination {
+    pub fn new() -> Self {
+        Self { exports: vec![] }
+    }
+
+    /// Keeps the function or global variable named `name`, e.g. one called from outside
+    /// the module.
+    pub fn with_export(mut self, name: &str) -> Self {
+        self.exports.push(name.to_string());
+        self
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        let live = self.find_live_symbols(module);
+        if live.functions.len() == module.functions.len()
+            && live.global_vars.len() == module.global_vars.arena.len()
+            && live.constants.len() == module.const_pool.arena.len()
+        {
+            return;
+        }
+        remove_dead_symbols(module, &live);
+    }
+
+    fn find_live_symbols(&self, module: &Module) -> LiveSymbols {
+        let mut live = LiveSymbols::default();
+        let is_exported = |name: &String| self.exports.contains(name);
+
+        for (id, g) in &module.global_vars.arena {
+            if g.linkage == Linkage::External || is_exported(&g.name) {
+                live.global_vars.insert(id);
+            }
+        }
+
+        let mut worklist: Vec<FunctionId> = module
+            .functions
+            .iter()
+            .filter(|(_, f)| f.name == "main" || is_exported(&f.name))
+            .map(|(id, _)| id)
+            .collect();
+        while let Some(id) = worklist.pop() {
+            if !live.functions.insert(id) {
+                continue;
+            }
+
+            let func = &module.functions[id];
+            for &block in &func.basic_blocks.order {
+                for val in &*func.basic_blocks.arena[block].iseq_ref() {
+                    let inst = &func.inst_table[val.as_instruction().id];
+                    for op in &inst.operands {
+                        match op {
+                            Operand::Value(Value::Function(f)) => worklist.push(f.func_id),
+                            Operand::Value(Value::Global(g)) => {
+                                live.global_vars.insert(g.id);
+                            }
+                            Operand::Value(Value::Constant(c)) => {
+                                mark_constant(&module.const_pool, c.id, &mut live.constants)
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+
+        live
+    }
+}
+
+impl ModulePassTrait for GlobalDeadCodeElimination {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "GlobalDeadCodeElimination"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+// Marks the constant `id` and the strings its elements point to.
+fn mark_constant(pool: &ConstantPool, id: ConstantId, live: &mut FxHashSet<ConstantId>) {
+    fn mark_elements(
+        pool: &ConstantPool,
+        elems: &[ConstantArrayElement],
+        live: &mut FxHashSet<ConstantId>,
+    ) {
+        for elem in elems {
+            match elem {
+                ConstantArrayElement::String(id) => mark_constant(pool, *id, live),
+                ConstantArrayElement::Array(elems) => mark_elements(pool, elems, live),
+                ConstantArrayElement::Immediate(_) => {}
+            }
+        }
+    }
+
+    if !live.insert(id) {
+        return;
+    }
+    if let ConstantKind::Array(elems) = &pool.arena[id].kind {
+        mark_elements(pool, elems, live)
+    }
+}
+
+fn remove_dead_symbols(module: &mut Module, live: &LiveSymbols) {
+    let mut map = IdMap::default();
+
+    // Entries keep their order in the new arenas.
+    for (id, func) in mem::replace(&mut module.functions, Arena::new()) {
+        if live.functions.contains(&id) {
+            map.functions.insert(id, module.functions.alloc(func));
+        }
+    }
+    for (id, g) in mem::replace(&mut module.global_vars.arena, Arena::new()) {
+        if live.global_vars.contains(&id) {
+            map.global_vars
+                .insert(id, module.global_vars.arena.alloc(g));
+        }
+    }
+    for (id, c) in mem::replace(&mut module.const_pool.arena, Arena::new()) {
+        if live.constants.contains(&id) {
+            map.constants.insert(id, module.const_pool.arena.alloc(c));
+        }
+    }
+
+    for (_, c) in &mut module.const_pool.arena {
+        if let ConstantKind::Array(elems) = &mut c.kind {
+            map.remap_elements(elems)
+        }
+    }
+    for (id, func) in &mut module.functions {
+        func.id = Some(id);
+        func.analyses.clear();
+        // Removed instructions are remapped too, though they may refer to removed
+        // symbols, which keep their old ids.
+        for (_, inst) in &mut func.inst_table {
+            for op in &mut inst.operands {
+                if let Operand::Value(val) = op {
+                    map.remap_value(val)
+                }
+            }
+        }
+        for (_, block) in &mut func.basic_blocks.arena {
+            for val in &mut *block.iseq_ref_mut() {
+                map.remap_value(val)
+            }
+        }
+    }
+}
+
+impl IdMap {
+    fn remap_value(&self, val: &mut Value) {
+        fn remap<T: Copy + Eq + std::hash::Hash>(map: &FxHashMap<T, T>, id: &mut T) {
+            if let Some(&new) = map.get(id) {
+                *id = new
+            }
+        }
+
+        match val {
+            Value::Argument(arg) => remap(&self.functions, &mut arg.func_id),
+            Value::Instruction(inst) => remap(&self.functions, &mut inst.func_id),
+            Value::Function(f) => remap(&self.functions, &mut f.func_id),
+            Value::Global(g) => remap(&self.global_vars, &mut g.id),
+            Value::Constant(c) => remap(&self.constants, &mut c.id),
+            Value::Immediate(_) | Value::None => {}
+        }
+    }
+
+    fn remap_elements(&self, elems: &mut [ConstantArrayElement]) {
+        for elem in elems {
+            match elem {
+                ConstantArrayElement::String(id) => *id = self.constants[&*id],
+                ConstantArrayElement::Array(elems) => self.remap_elements(elems),
+                ConstantArrayElement::Immediate(_) => {}
+            }
+        }
+    }
+}
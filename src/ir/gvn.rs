@@ -0,0 +1,343 @@
+use crate::{
+    analysis::{
+        alias::AliasAnalysis,
+        dom_tree::DominatorTree,
+        manager::{AnalysisManager, PreservedAnalyses},
+    },
+    ir::{
+        basic_block::{BasicBlock, BasicBlockId},
+        function::Function,
+        module::Module,
+        opcode::{ICmpKind, Instruction, InstructionId, Opcode, Operand},
+        types::Type,
+        value::Value,
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+use rustc_hash::FxHashMap;
+
+/// Global value numbering. Instructions computing the same value, modulo commutativity
+/// and simple algebraic identities, get the same number and are replaced by the one
+/// dominating them. A load is replaced by an earlier load of the same pointer in the
+/// same extended basic block if no store or call in between may write to it.
+pub struct GlobalValueNumbering {}
+
+struct GlobalValueNumberingOnFunction<'a> {
+    func: &'a Function,
+    dom_tree: &'a DominatorTree<BasicBlock>,
+    aa: AliasAnalysis<'a>,
+    next_number: u32,
+    numbers: FxHashMap<Value, u32>,
+    expressions: FxHashMap<Expression, u32>,
+    replacements: Vec<(InstructionId, Value)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Expression {
+    Binary(Opcode, Type, u32, u32),
+    Cast(Opcode, Type, u32),
+    ICmp(ICmpKind, u32, u32),
+    GetElementPtr(Type, Vec<u32>),
+    Phi(BasicBlockId, Vec<(u32, BasicBlockId)>),
+}
+
+/// Values available in the block being numbered, inherited from its dominators
+#[derive(Clone, Default)]
+struct Scope {
+    leaders: FxHashMap<u32, Value>,
+
+    /// Pointer and the value loaded from it
+    loads: Vec<(Value, Value)>,
+}
+
+impl GlobalValueNumbering {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for GlobalValueNumbering {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "GlobalValueNumbering"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for GlobalValueNumbering {
+    fn name(&self) -> &'static str {
+        "GlobalValueNumbering"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let dom_tree = am.get_dom_tree(func);
+        let replacements = GlobalValueNumberingOnFunction::new(func, &dom_tree).run();
+
+        // Replacements may refer to instructions replaced themselves.
+        let map: FxHashMap<InstructionId, Value> = replacements.iter().copied().collect();
+        let resolve = |mut val: Value| {
+            while let Some(&to) = val.get_inst_id().and_then(|id| map.get(&id)) {
+                val = to
+            }
+            val
+        };
+        for &(id, val) in &replacements {
+            Instruction::replace_all_uses(&mut func.inst_table, id, Operand::Value(resolve(val)));
+        }
+        for (id, _) in replacements {
+            func.remove_inst(id);
+        }
+
+        PreservedAnalyses::cfg()
+    }
+}
+
+impl<'a> GlobalValueNumberingOnFunction<'a> {
+    fn new(func: &'a Function, dom_tree: &'a DominatorTree<BasicBlock>) -> Self {
+        Self {
+            func,
+            dom_tree,
+            aa: AliasAnalysis::new(func),
+            next_number: 0,
+            numbers: FxHashMap::default(),
+            expressions: FxHashMap::default(),
+            replacements: vec![],
+        }
+    }
+
+    /// Returns the instructions to replace, in dominator tree order.
+    fn run(mut self) -> Vec<(InstructionId, Value)> {
+        if let Some(entry) = self.func.get_entry_block() {
+            self.run_on_block(entry, Scope::default());
+        }
+        self.replacements
+    }
+
+    fn run_on_block(&mut self, block: BasicBlockId, mut scope: Scope) {
+        let func = self.func;
+        for val in &*func.basic_blocks.arena[block].iseq_ref() {
+            let id = val.as_instruction().id;
+            if let Some(to) = self.number_inst(id, &mut scope) {
+                self.replacements.push((id, to));
+            }
+        }
+
+        let mut children: Vec<BasicBlockId> = self
+            .dom_tree
+            .children_of(block)
+            .map_or(vec![], |children| children.iter().copied().collect());
+        children.sort_by_key(|id| id.index());
+
+        for child in children {
+            let mut child_scope = scope.clone();
+            // Loads stay available only along the edge to a block with no other
+            // predecessor.
+            if func.basic_blocks.arena[child].pred.len() != 1 {
+                child_scope.loads.clear();
+            }
+            self.run_on_block(child, child_scope);
+        }
+    }
+
+    // Numbers the instruction `id` and returns the value to replace it with, if any.
+    fn number_inst(&mut self, id: InstructionId, scope: &mut Scope) -> Option<Value> {
+        let func = self.func;
+        let inst = &func.inst_table[id];
+        let val = self.func.get_inst_value(id);
+
+        match inst.opcode {
+            Opcode::Load => return self.number_load(inst, val, scope),
+            Opcode::Store | Opcode::Call => {
+                scope
+                    .loads
+                    .retain(|(ptr, _)| !self.aa.mod_ref(id, ptr).may_mod());
+            }
+            _ => {}
+        }
+
+        if let Some(simplified) = self.simplify(inst) {
+            let number = self.number_of(&simplified);
+            self.numbers.entry(val).or_insert(number);
+            return Some(scope.leaders.get(&number).copied().unwrap_or(simplified));
+        }
+
+        let expr = match self.expression_of(inst) {
+            Some(expr) => expr,
+            None => {
+                // Unique value such as an alloca or a call
+                self.number_of(&val);
+                return None;
+            }
+        };
+        let number = match self.expressions.get(&expr) {
+            Some(&number) => number,
+            None => {
+                let number = self.new_number();
+                self.expressions.insert(expr, number);
+                number
+            }
+        };
+        // Users in phis may have numbered this instruction already.
+        let number = *self.numbers.entry(val).or_insert(number);
+
+        match scope.leaders.get(&number) {
+            Some(&leader) if leader != val => Some(leader),
+            _ => {
+                scope.leaders.insert(number, val);
+                None
+            }
+        }
+    }
+
+    fn number_load(&mut self, load: &Instruction, val: Value, scope: &mut Scope) -> Option<Value> {
+        let ptr = *load.operands[0].as_value();
+        let ptr_number = self.number_of(&ptr);
+
+        let available = scope.loads.iter().find(|(p, loaded)| {
+            self.numbers.get(p) == Some(&ptr_number) && loaded.get_type() == load.ty
+        });
+        if let Some(&(_, loaded)) = available {
+            let number = self.number_of(&loaded);
+            self.numbers.insert(val, number);
+            return Some(loaded);
+        }
+
+        self.number_of(&val);
+        scope.loads.push((ptr, val));
+        None
+    }
+
+    fn expression_of(&mut self, inst: &Instruction) -> Option<Expression> {
+        let ops = &inst.operands;
+        let expr = match inst.opcode {
+            Opcode::Add | Opcode::Mul => {
+                let mut x = self.number_of(ops[0].as_value());
+                let mut y = self.number_of(ops[1].as_value());
+                if x > y {
+                    std::mem::swap(&mut x, &mut y)
+                }
+                Expression::Binary(inst.opcode, inst.ty, x, y)
+            }
+            Opcode::Sub | Opcode::Div | Opcode::Rem | Opcode::Shl => Expression::Binary(
+                inst.opcode,
+                inst.ty,
+                self.number_of(ops[0].as_value()),
+                self.number_of(ops[1].as_value()),
+            ),
+            Opcode::Sext | Opcode::SIToFP | Opcode::FPToSI => {
+                Expression::Cast(inst.opcode, inst.ty, self.number_of(ops[0].as_value()))
+            }
+            Opcode::ICmp => {
+                let mut kind = *ops[0].as_icmp_kind();
+                let mut x = self.number_of(ops[1].as_value());
+                let mut y = self.number_of(ops[2].as_value());
+                if x > y {
+                    std::mem::swap(&mut x, &mut y);
+                    kind = kind.swapped();
+                }
+                Expression::ICmp(kind, x, y)
+            }
+            Opcode::GetElementPtr => Expression::GetElementPtr(
+                inst.ty,
+                ops.iter().map(|op| self.number_of(op.as_value())).collect(),
+            ),
+            Opcode::Phi => {
+                let mut incomings: Vec<(u32, BasicBlockId)> = ops
+                    .chunks(2)
+                    .map(|pair| {
+                        (
+                            self.number_of(pair[0].as_value()),
+                            *pair[1].as_basic_block(),
+                        )
+                    })
+                    .collect();
+                incomings.sort_by_key(|(_, block)| block.index());
+                Expression::Phi(inst.parent, incomings)
+            }
+            _ => return None,
+        };
+        Some(expr)
+    }
+
+    // Returns the value `inst` is known to be equal to by an algebraic identity.
+    fn simplify(&mut self, inst: &Instruction) -> Option<Value> {
+        let is_int = |val: &Value, i: i64| val.as_i64() == Some(i);
+        let ops = &inst.operands;
+
+        match inst.opcode {
+            Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div | Opcode::Shl => {
+                let (x, y) = (*ops[0].as_value(), *ops[1].as_value());
+                match inst.opcode {
+                    Opcode::Add if is_int(&y, 0) => Some(x),
+                    Opcode::Add if is_int(&x, 0) => Some(y),
+                    Opcode::Sub if is_int(&y, 0) => Some(x),
+                    Opcode::Sub if self.number_of(&x) == self.number_of(&y) => zero_of(inst.ty),
+                    Opcode::Mul if is_int(&y, 1) => Some(x),
+                    Opcode::Mul if is_int(&x, 1) => Some(y),
+                    Opcode::Mul if is_int(&x, 0) || is_int(&y, 0) => zero_of(inst.ty),
+                    Opcode::Div if is_int(&y, 1) => Some(x),
+                    Opcode::Shl if is_int(&y, 0) => Some(x),
+                    _ => None,
+                }
+            }
+            // A phi whose incoming values are all the same value, except for itself
+            Opcode::Phi => {
+                let this = self.func.get_inst_value(inst.id.unwrap());
+                let mut incomings = ops
+                    .chunks(2)
+                    .map(|pair| *pair[0].as_value())
+                    .filter(|val| *val != this);
+                let first = incomings.next()?;
+                if !incomings.all(|val| val == first) {
+                    return None;
+                }
+                match first {
+                    Value::Instruction(iv) => {
+                        let block = self.func.inst_table[iv.id].parent;
+                        let dominates =
+                            block != inst.parent && self.dom_tree.dominate_bb(block, inst.parent);
+                        Some(first).filter(|_| dominates)
+                    }
+                    _ => Some(first),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn number_of(&mut self, val: &Value) -> u32 {
+        match self.numbers.get(val) {
+            Some(&number) => number,
+            None => {
+                let number = self.new_number();
+                self.numbers.insert(*val, number);
+                number
+            }
+        }
+    }
+
+    fn new_number(&mut self) -> u32 {
+        self.next_number += 1;
+        self.next_number - 1
+    }
+}
+
+fn zero_of(ty: Type) -> Option<Value> {
+    match ty {
+        Type::i8 => Some(Value::new_imm_int8(0)),
+        Type::i32 => Some(Value::new_imm_int32(0)),
+        _ => None,
+    }
+}
@@ -0,0 +1,384 @@
+use crate::{
+    analysis::call_graph::{CallGraph, CallGraphSCC},
+    ir::{
+        basic_block::BasicBlockId,
+        function::{Function, FunctionId},
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        types::Type,
+        value::{InstructionValue, Value},
+    },
+    traits::pass::{ModulePassTrait, SCCPassTrait},
+};
+use rustc_hash::FxHashMap;
+
+/// Inlines calls to small functions. Call graph SCCs are visited bottom-up, so a callee
+/// has already had its own calls inlined when its size is measured.
+///
+/// A callee is inlined if it has the `alwaysinline` attribute or at most `threshold`
+/// instructions, unless it has the `noinline` attribute. Calls between functions of
+/// the same SCC are never inlined.
+pub struct Inliner {
+    pub threshold: usize,
+}
+
+struct InlineCall<'a> {
+    caller: &'a mut Function,
+    callee: &'a Function,
+    call: InstructionId,
+    args: Vec<Value>,
+    blocks: FxHashMap<BasicBlockId, BasicBlockId>,
+    insts: FxHashMap<InstructionId, InstructionId>,
+}
+
+impl Inliner {
+    pub fn new() -> Self {
+        Self { threshold: 50 }
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        let call_graph = CallGraph::new(module);
+        for scc in call_graph.sccs() {
+            self.run_on_scc(module, &scc, &call_graph);
+        }
+    }
+
+    fn should_inline(&self, caller: FunctionId, callee: &Function, scc: &CallGraphSCC) -> bool {
+        let callee_id = callee.id.unwrap();
+        if callee.is_internal
+            || callee.is_empty()
+            || callee.attributes.noinline
+            || callee_id == caller
+            || scc.functions.contains(&callee_id)
+        {
+            return false;
+        }
+
+        let has_ret = callee
+            .inst_table
+            .iter()
+            .any(|(_, i)| i.opcode == Opcode::Ret);
+        // A `byval` argument would need a copy in the caller.
+        let has_byval = (0..callee.get_params_len())
+            .any(|i| callee.get_param_attr(i).map_or(false, |a| a.byval));
+        if !has_ret || has_byval {
+            return false;
+        }
+
+        callee.attributes.alwaysinline || size_of(callee) <= self.threshold
+    }
+}
+
+impl ModulePassTrait for Inliner {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "Inliner"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl SCCPassTrait for Inliner {
+    fn name(&self) -> &'static str {
+        "Inliner"
+    }
+
+    fn run_on_scc(
+        &mut self,
+        module: &mut Module,
+        scc: &CallGraphSCC,
+        call_graph: &CallGraph,
+    ) -> bool {
+        let mut changed = false;
+
+        for &caller_id in &scc.functions {
+            // Calls brought in by inlining were already rejected when the callee was
+            // visited, so only the original call sites are considered.
+            for site in call_graph.call_sites(caller_id) {
+                let callee = match module.function_ref(caller_id).inst_table[site.inst].operands[0]
+                {
+                    Operand::Value(Value::Function(f)) => module.function_ref(f.func_id),
+                    _ => continue,
+                };
+                if !self.should_inline(caller_id, callee, scc) {
+                    continue;
+                }
+
+                let callee = callee.clone();
+                let caller = module.function_ref_mut(caller_id);
+                InlineCall::new(caller, &callee, site.inst).run();
+                changed = true;
+            }
+
+            if changed {
+                // Cached analyses describe the body before inlining.
+                module.function_ref_mut(caller_id).analyses.clear();
+            }
+        }
+
+        changed
+    }
+}
+
+impl<'a> InlineCall<'a> {
+    fn new(caller: &'a mut Function, callee: &'a Function, call: InstructionId) -> Self {
+        let args = caller.inst_table[call].operands[1..]
+            .iter()
+            .map(|op| *op.as_value())
+            .collect();
+        Self {
+            caller,
+            callee,
+            call,
+            args,
+            blocks: FxHashMap::default(),
+            insts: FxHashMap::default(),
+        }
+    }
+
+    fn run(mut self) {
+        let block = self.caller.inst_table[self.call].parent;
+        let after = self.split_block_after_call(block);
+
+        self.clone_blocks(after);
+        let returns = self.clone_insts(after);
+        self.hoist_allocas();
+
+        // Returned values flow into the block after the call.
+        let ret_ty = self.caller.inst_table[self.call].ty;
+        if ret_ty != Type::Void {
+            let ret_val = if returns.len() == 1 {
+                returns[0].0
+            } else {
+                let incomings = returns
+                    .iter()
+                    .flat_map(|&(val, block)| vec![Operand::Value(val), Operand::BasicBlock(block)])
+                    .collect();
+                let phi =
+                    self.caller
+                        .alloc_inst(Instruction::new(Opcode::Phi, incomings, ret_ty, after));
+                let phi = self.inst_value(phi);
+                self.caller.basic_blocks.arena[after]
+                    .iseq_ref_mut()
+                    .insert(0, phi);
+                phi
+            };
+            Instruction::replace_all_uses(
+                &mut self.caller.inst_table,
+                self.call,
+                Operand::Value(ret_val),
+            );
+        }
+
+        // Replace the call with a branch to the inlined body.
+        self.caller.remove_inst(self.call);
+        let entry = self.blocks[&self.callee.basic_blocks.order[0]];
+        let br = self.caller.alloc_inst(Instruction::new(
+            Opcode::Br,
+            vec![Operand::BasicBlock(entry)],
+            Type::Void,
+            block,
+        ));
+        let br = self.inst_value(br);
+        self.caller.basic_blocks.arena[block]
+            .iseq_ref_mut()
+            .push(br);
+        self.caller.basic_blocks.arena[block].succ.insert(entry);
+        self.caller.basic_blocks.arena[entry].pred.insert(block);
+    }
+
+    // Moves the instructions after the call into a new block, which takes over the
+    // successors of `block`.
+    fn split_block_after_call(&mut self, block: BasicBlockId) -> BasicBlockId {
+        let order = &self.caller.basic_blocks.order;
+        let next = order.get(order.iter().position(|&b| b == block).unwrap() + 1);
+        let after = match next.copied() {
+            Some(next) => self.caller.append_basic_block_before(next),
+            None => self.caller.append_basic_block(),
+        };
+
+        let (_, pos) = self.caller.find_inst_pos(self.call).unwrap();
+        let moved: Vec<Value> = self.caller.basic_blocks.arena[block]
+            .iseq_ref_mut()
+            .drain(pos + 1..)
+            .collect();
+        for val in &moved {
+            self.caller.inst_table[val.as_instruction().id].parent = after;
+        }
+        *self.caller.basic_blocks.arena[after].iseq_ref_mut() = moved;
+
+        let succs = std::mem::take(&mut self.caller.basic_blocks.arena[block].succ);
+        for &succ in &succs {
+            let succ_ = &mut self.caller.basic_blocks.arena[succ];
+            succ_.pred.remove(&block);
+            succ_.pred.insert(after);
+            self.replace_block_in_phis(succ, block, after);
+        }
+        self.caller.basic_blocks.arena[after].succ = succs;
+
+        after
+    }
+
+    fn replace_block_in_phis(&mut self, block: BasicBlockId, from: BasicBlockId, to: BasicBlockId) {
+        let iseq = self.caller.basic_blocks.arena[block].iseq_ref().clone();
+        for val in iseq {
+            let id = val.as_instruction().id;
+            if self.caller.inst_table[id].opcode != Opcode::Phi {
+                break;
+            }
+            Instruction::replace_operand(
+                &mut self.caller.inst_table,
+                id,
+                &Operand::BasicBlock(from),
+                Operand::BasicBlock(to),
+            );
+        }
+    }
+
+    fn clone_blocks(&mut self, after: BasicBlockId) {
+        for &id in &self.callee.basic_blocks.order {
+            let new = self.caller.append_basic_block_before(after);
+            if let Some(name) = self.callee.names.get_block(id) {
+                self.caller.names.set_block(new, name);
+            }
+            self.blocks.insert(id, new);
+        }
+
+        for &id in &self.callee.basic_blocks.order {
+            let block = &self.callee.basic_blocks.arena[id];
+            let pred = block.pred.iter().map(|b| self.blocks[b]).collect();
+            let succ = block.succ.iter().map(|b| self.blocks[b]).collect();
+            let new = &mut self.caller.basic_blocks.arena[self.blocks[&id]];
+            new.pred = pred;
+            new.succ = succ;
+        }
+    }
+
+    // Returns the returned values and the blocks returning them. Returns become branches
+    // to `after`.
+    fn clone_insts(&mut self, after: BasicBlockId) -> Vec<(Value, BasicBlockId)> {
+        let callee = self.callee;
+
+        // Instructions are allocated first, since operands may refer to ones that come
+        // later, such as in phis.
+        for &id in &callee.basic_blocks.order {
+            for val in &*callee.basic_blocks.arena[id].iseq_ref() {
+                let old = val.as_instruction().id;
+                let inst = &callee.inst_table[old];
+                let new = self.caller.alloc_inst(Instruction::new(
+                    inst.opcode,
+                    vec![],
+                    inst.ty,
+                    self.blocks[&id],
+                ));
+                if let Some(name) = callee.names.get_inst(old) {
+                    self.caller.names.set_inst(new, name);
+                }
+                self.insts.insert(old, new);
+            }
+        }
+
+        let mut returns = vec![];
+        for &id in &callee.basic_blocks.order {
+            let block = self.blocks[&id];
+            for val in &*callee.basic_blocks.arena[id].iseq_ref() {
+                let old = &callee.inst_table[val.as_instruction().id];
+                let new = self.insts[&old.id.unwrap()];
+
+                if old.opcode == Opcode::Ret {
+                    returns.push((self.map_value(old.operands[0].as_value()), block));
+                    let inst = &mut self.caller.inst_table[new];
+                    inst.opcode = Opcode::Br;
+                    inst.operands = vec![Operand::BasicBlock(after)];
+                    self.caller.basic_blocks.arena[block].succ.insert(after);
+                    self.caller.basic_blocks.arena[after].pred.insert(block);
+                } else {
+                    let operands = old
+                        .operands
+                        .iter()
+                        .map(|op| match op {
+                            Operand::Value(v) => Operand::Value(self.map_value(v)),
+                            Operand::BasicBlock(b) => Operand::BasicBlock(self.blocks[b]),
+                            op => *op,
+                        })
+                        .collect();
+                    self.caller.inst_table[new].operands = operands;
+                }
+
+                self.caller.inst_table[new].set_users(&self.caller.inst_table);
+                let new = self.inst_value(new);
+                self.caller.basic_blocks.arena[block]
+                    .iseq_ref_mut()
+                    .push(new);
+            }
+        }
+
+        returns
+    }
+
+    // Allocas in the entry block of the callee are moved to the entry block of the
+    // caller, so the stack doesn't grow when the call is in a loop.
+    fn hoist_allocas(&mut self) {
+        let entry = self.caller.basic_blocks.order[0];
+        let inlined_entry = self.blocks[&self.callee.basic_blocks.order[0]];
+
+        let iseq = self.caller.basic_blocks.arena[inlined_entry]
+            .iseq_ref()
+            .clone();
+        let mut pos = self.caller.basic_blocks.arena[entry]
+            .iseq_ref()
+            .iter()
+            .take_while(|v| self.caller.inst_table[v.as_instruction().id].opcode == Opcode::Alloca)
+            .count();
+
+        for val in iseq {
+            let id = val.as_instruction().id;
+            if self.caller.inst_table[id].opcode != Opcode::Alloca {
+                continue;
+            }
+            self.caller.remove_inst_from_block(id);
+            self.caller.inst_table[id].parent = entry;
+            self.caller.basic_blocks.arena[entry]
+                .iseq_ref_mut()
+                .insert(pos, val);
+            pos += 1;
+        }
+    }
+
+    fn map_value(&self, val: &Value) -> Value {
+        match val {
+            Value::Instruction(iv) => Value::Instruction(InstructionValue {
+                func_id: self.caller.id.unwrap(),
+                id: self.insts[&iv.id],
+                ty: iv.ty,
+            }),
+            Value::Argument(a) => self.args[a.index],
+            _ => *val,
+        }
+    }
+
+    fn inst_value(&self, id: InstructionId) -> Value {
+        Value::Instruction(InstructionValue {
+            func_id: self.caller.id.unwrap(),
+            id,
+            ty: self.caller.inst_table[id].ty,
+        })
+    }
+}
+
+// The cost of inlining `func`
+fn size_of(func: &Function) -> usize {
+    func.basic_blocks
+        .order
+        .iter()
+        .map(|&id| func.basic_blocks.arena[id].iseq_ref().len())
+        .sum()
+}
@@ -1,8 +1,12 @@
-use crate::ir::{
-    function::Function,
-    module::Module,
-    opcode::{Instruction, Opcode, Operand},
-    value::Value,
+use crate::{
+    analysis::manager::{AnalysisManager, PreservedAnalyses},
+    ir::{
+        function::Function,
+        module::Module,
+        opcode::{Instruction, Opcode, Operand},
+        value::Value,
+    },
+    traits::pass::FunctionPassTrait,
 };
 use std::collections::VecDeque;
 
@@ -28,6 +32,21 @@ impl InstructionCombine {
     }
 }
 
+impl FunctionPassTrait for InstructionCombine {
+    fn name(&self) -> &'static str {
+        "InstructionCombine"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        _am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        InstructionCombineOnFunction { func }.run();
+        PreservedAnalyses::cfg()
+    }
+}
+
 impl<'a> InstructionCombineOnFunction<'a> {
     pub fn run(&mut self) {
         let mut worklist = VecDeque::new();
@@ -0,0 +1,347 @@
+use crate::{
+    analysis::{
+        call_graph::{CallGraph, CallGraphNode, CallSite},
+        dom_tree::DominatorTreeConstructor,
+        loops::LoopsConstructor,
+    },
+    ir::{
+        const_folding::ConstantFoldingOnFunction,
+        function::{Function, FunctionId},
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        types::Type,
+        value::{ArgumentValue, FunctionValue, Value},
+    },
+    traits::pass::ModulePassTrait,
+};
+use rustc_hash::FxHashMap;
+
+/// Propagates constant arguments into the functions they are passed to.
+///
+/// A parameter of a function whose calls are all known, i.e. one that is not `main`,
+/// not in `exports` and whose address is not taken, is replaced by a constant every
+/// call site passes to it and dropped from the function and its calls. Callers are
+/// visited before their callees, so constants they get are passed on.
+///
+/// Calls in loops passing constants to a function of at most `threshold` instructions
+/// call a clone of it instead, named `<callee>.specialized.<n>`, in which the
+/// parameters are replaced by the constants the same way. Calls passing the same
+/// constants share a clone. Both kinds of function are then constant-folded, and a
+/// clone is only kept if folding removes an instruction from it. A function gets at
+/// most `max_clones` clones. Callees left without callers are removed by
+/// `GlobalDeadCodeElimination`.
+pub struct InterproceduralConstantPropagation {
+    pub exports: Vec<String>,
+    pub threshold: usize,
+    pub max_clones: usize,
+}
+
+/// Arguments replaced by constants, by parameter index
+type ConstantArgs = Vec<(usize, Value)>;
+
+impl InterproceduralConstantPropagation {
+    pub fn new() -> Self {
+        Self {
+            exports: vec![],
+            threshold: 50,
+            max_clones: 4,
+        }
+    }
+
+    /// Keeps the parameters of the function named `name`, e.g. one called from outside
+    /// the module.
+    pub fn with_export(mut self, name: &str) -> Self {
+        self.exports.push(name.to_string());
+        self
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_max_clones(mut self, max_clones: usize) -> Self {
+        self.max_clones = max_clones;
+        self
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        self.propagate_constant_args(module);
+        self.specialize_hot_calls(module);
+    }
+
+    fn propagate_constant_args(&self, module: &mut Module) {
+        let call_graph = CallGraph::new(module);
+
+        for scc in call_graph.sccs().into_iter().rev() {
+            for &id in &scc.functions {
+                if !self.are_all_calls_known(module, &call_graph, id) {
+                    continue;
+                }
+
+                let sites: Vec<&CallSite> = call_graph
+                    .callers(id)
+                    .iter()
+                    .flat_map(|&caller| call_graph.call_sites(caller))
+                    .filter(|site| site.callee == CallGraphNode::Function(id))
+                    .collect();
+                let consts = constant_args(module, id, &sites);
+                if consts.is_empty() {
+                    continue;
+                }
+
+                drop_params(module.function_ref_mut(id), &consts);
+                for site in sites {
+                    redirect_call(module, site.caller, site.inst, id, &consts);
+                }
+                ConstantFoldingOnFunction::new(module.function_ref_mut(id)).run();
+            }
+        }
+    }
+
+    fn specialize_hot_calls(&self, module: &mut Module) {
+        let call_graph = CallGraph::new(module);
+        // `None` for constants that fold nothing away
+        let mut clones: FxHashMap<(FunctionId, ConstantArgs), Option<FunctionId>> =
+            FxHashMap::default();
+
+        for &caller in &call_graph.functions {
+            let func = module.function_ref(caller);
+            if func.is_internal || func.is_empty() {
+                continue;
+            }
+
+            for site in sites_in_loops(func, call_graph.call_sites(caller)) {
+                let callee = match site.callee {
+                    CallGraphNode::Function(callee) if callee != caller => callee,
+                    _ => continue,
+                };
+                let callee_func = module.function_ref(callee);
+                if callee_func.is_internal
+                    || callee_func.is_empty()
+                    || size_of(callee_func) > self.threshold
+                {
+                    continue;
+                }
+
+                let consts: ConstantArgs = module.function_ref(caller).inst_table[site.inst]
+                    .operands[1..]
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, op)| match op {
+                        Operand::Value(val @ Value::Immediate(_)) => Some((i, *val)),
+                        _ => None,
+                    })
+                    .collect();
+                if consts.is_empty() {
+                    continue;
+                }
+
+                let key = (callee, consts);
+                let clone = match clones.get(&key) {
+                    Some(&clone) => clone,
+                    None => {
+                        let n = clones
+                            .iter()
+                            .filter(|((f, _), clone)| *f == callee && clone.is_some())
+                            .count();
+                        if n >= self.max_clones {
+                            continue;
+                        }
+                        let clone = specialize(module, callee, &key.1, n + 1);
+                        clones.insert(key.clone(), clone);
+                        clone
+                    }
+                };
+                if let Some(clone) = clone {
+                    redirect_call(module, caller, site.inst, clone, &key.1);
+                }
+            }
+        }
+    }
+
+    fn are_all_calls_known(&self, module: &Module, call_graph: &CallGraph, id: FunctionId) -> bool {
+        let func = module.function_ref(id);
+        !func.is_internal
+            && !func.is_empty()
+            && func.name != "main"
+            && !self.exports.contains(&func.name)
+            && !call_graph.is_address_taken(id)
+    }
+}
+
+impl ModulePassTrait for InterproceduralConstantPropagation {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "InterproceduralConstantPropagation"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+// Returns the parameters of `id` to which every call in `sites` passes the same
+// constant.
+fn constant_args(module: &Module, id: FunctionId, sites: &[&CallSite]) -> ConstantArgs {
+    let params_len = module.function_ref(id).get_params_len();
+    (0..params_len)
+        .filter_map(|i| {
+            let mut konst = None;
+            for site in sites {
+                let inst = &module.function_ref(site.caller).inst_table[site.inst];
+                let arg = match inst.operands.get(i + 1) {
+                    Some(Operand::Value(arg)) => *arg,
+                    _ => return None,
+                };
+                match arg {
+                    // A recursive call passing the parameter on leaves it the same.
+                    Value::Argument(a) if a.func_id == id && a.index == i => {}
+                    Value::Immediate(_) if konst.map_or(true, |k| k == arg) => konst = Some(arg),
+                    _ => return None,
+                }
+            }
+            konst.map(|konst| (i, konst))
+        })
+        .collect()
+}
+
+// Replaces the parameters in `consts` with their constants in the body of `func`, and
+// removes them from its type.
+fn drop_params(func: &mut Function, consts: &[(usize, Value)]) {
+    let func_id = func.id.unwrap();
+    let params_len = func.get_params_len();
+    let kept: Vec<usize> = (0..params_len)
+        .filter(|i| !consts.iter().any(|(j, _)| j == i))
+        .collect();
+
+    for (_, inst) in &mut func.inst_table {
+        for op in &mut inst.operands {
+            let arg = match op {
+                Operand::Value(Value::Argument(arg)) if arg.func_id == func_id => *arg,
+                _ => continue,
+            };
+            let new = match consts.iter().find(|(i, _)| *i == arg.index) {
+                Some(&(_, konst)) => konst,
+                None => Value::Argument(ArgumentValue {
+                    index: kept.iter().position(|&i| i == arg.index).unwrap(),
+                    ..arg
+                }),
+            };
+            *op = Operand::Value(new);
+        }
+    }
+
+    let ret_ty = func.get_return_type();
+    let params_ty: Vec<Type> = kept
+        .iter()
+        .map(|&i| func.get_param_type(i).unwrap())
+        .collect();
+    let params_attr: Vec<_> = kept.iter().map(|&i| func.get_param_attr(i)).collect();
+    // Struct parameters are already pointers, so they are given their `byval` back.
+    func.ty = func.types.new_function_ty(ret_ty, params_ty);
+    for (i, attr) in params_attr.into_iter().enumerate() {
+        if let Some(attr) = attr {
+            func.set_param_attr(i, attr)
+        }
+    }
+
+    let names: Vec<Option<String>> = (0..params_len)
+        .map(|i| func.names.remove_param(i))
+        .collect();
+    for (new, &old) in kept.iter().enumerate() {
+        if let Some(name) = &names[old] {
+            func.names.set_param(new, name);
+        }
+    }
+}
+
+// Makes the call `call` in `caller` call `callee` without the arguments in `consts`.
+fn redirect_call(
+    module: &mut Module,
+    caller: FunctionId,
+    call: InstructionId,
+    callee: FunctionId,
+    consts: &[(usize, Value)],
+) {
+    let ty = module.function_ref(callee).ty;
+    let caller = module.function_ref_mut(caller);
+    let inst = &caller.inst_table[call];
+
+    let mut operands = vec![Operand::Value(Value::Function(FunctionValue {
+        func_id: callee,
+        ty,
+    }))];
+    operands.extend(
+        inst.operands[1..]
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !consts.iter().any(|(j, _)| j == i))
+            .map(|(_, op)| *op),
+    );
+    let inst = Instruction::new(Opcode::Call, operands, inst.ty, inst.parent);
+    caller.change_inst(call, inst);
+}
+
+// Clones `callee` with the parameters in `consts` replaced by their constants, unless
+// constant folding removes nothing from the clone.
+fn specialize(
+    module: &mut Module,
+    callee: FunctionId,
+    consts: &[(usize, Value)],
+    n: usize,
+) -> Option<FunctionId> {
+    // The clone is folded before it is added, while its values still belong to `callee`.
+    let mut func = module.function_ref(callee).clone();
+    func.analyses.clear();
+    drop_params(&mut func, consts);
+    ConstantFoldingOnFunction::new(&mut func).run();
+    if size_of(&func) >= size_of(module.function_ref(callee)) {
+        return None;
+    }
+    func.name = format!("{}.specialized.{}", func.name, n);
+    let id = module.add_function(func);
+
+    // The values in the clone still belong to `callee`.
+    let func = module.function_ref_mut(id);
+    let remap = |val: &mut Value| match val {
+        Value::Argument(arg) if arg.func_id == callee => arg.func_id = id,
+        Value::Instruction(inst) if inst.func_id == callee => inst.func_id = id,
+        _ => {}
+    };
+    for (_, inst) in &mut func.inst_table {
+        for op in &mut inst.operands {
+            if let Operand::Value(val) = op {
+                remap(val)
+            }
+        }
+    }
+    for (_, block) in &func.basic_blocks.arena {
+        for val in &mut *block.iseq_ref_mut() {
+            remap(val)
+        }
+    }
+    Some(id)
+}
+
+// Returns the call sites in `sites` that are in a loop of `func`.
+fn sites_in_loops<'a>(func: &Function, sites: &'a [CallSite]) -> Vec<&'a CallSite> {
+    let dom_tree = DominatorTreeConstructor::new(&func.basic_blocks).construct();
+    let loops = LoopsConstructor::new(&dom_tree, &func.basic_blocks).analyze();
+    sites
+        .iter()
+        .filter(|site| {
+            let block = func.inst_table[site.inst].parent;
+            loops.get_loop_for(block).is_some()
+        })
+        .collect()
+}
+
+fn size_of(func: &Function) -> usize {
+    func.basic_blocks
+        .order
+        .iter()
+        .map(|&id| func.basic_blocks.arena[id].iseq_ref().len())
+        .sum()
+}
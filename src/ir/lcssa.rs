@@ -0,0 +1,296 @@
+use crate::{
+    analysis::{
+        dom_tree::DominatorTree,
+        loops::{Loop, Loops},
+        manager::{AnalysisManager, PreservedAnalyses},
+    },
+    ir::{
+        basic_block::{BasicBlock, BasicBlockId},
+        function::Function,
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        value::Value,
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+use rustc_hash::FxHashMap;
+use std::cmp::Reverse;
+
+/// Puts loops into loop-closed SSA form, where a value defined in a loop is used
+/// outside of it only by phis in its exit blocks. A pass changing a loop then only has
+/// to fix those phis instead of every use after the loop.
+///
+/// Other uses are rewritten to use the phis, with new phis where the values from
+/// several exits meet.
+pub struct LoopClosedSSA {}
+
+pub struct LoopClosedSSAOnFunction<'a> {
+    func: &'a mut Function,
+}
+
+impl LoopClosedSSA {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for LoopClosedSSA {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "LoopClosedSSA"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for LoopClosedSSA {
+    fn name(&self) -> &'static str {
+        "LoopClosedSSA"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let dom_tree = am.get_dom_tree(func);
+        let loops = am.get_loops(func);
+        if LoopClosedSSAOnFunction::new(func).run(&loops, &dom_tree) {
+            PreservedAnalyses::cfg()
+        } else {
+            PreservedAnalyses::all()
+        }
+    }
+}
+
+impl<'a> LoopClosedSSAOnFunction<'a> {
+    pub fn new(func: &'a mut Function) -> Self {
+        Self { func }
+    }
+
+    /// Returns true if any phi is inserted. Inner loops are closed first, so a value
+    /// leaving several loops goes through the phis of each.
+    pub fn run(&mut self, loops: &Loops<BasicBlock>, dom_tree: &DominatorTree<BasicBlock>) -> bool {
+        let depth = |mut id| {
+            let mut depth = 0;
+            while let Some(parent) = loops.arena[id].parent {
+                depth += 1;
+                id = parent;
+            }
+            depth
+        };
+        let mut ids: Vec<_> = loops.arena.iter().map(|(id, _)| id).collect();
+        ids.sort_by_key(|&id| (Reverse(depth(id)), loops.arena[id].header.index()));
+
+        let mut changed = false;
+        for id in ids {
+            changed |= self.close_loop(&loops.arena[id], dom_tree);
+        }
+        changed
+    }
+
+    fn close_loop(
+        &mut self,
+        loop_: &Loop<BasicBlock>,
+        dom_tree: &DominatorTree<BasicBlock>,
+    ) -> bool {
+        let blocks: Vec<BasicBlockId> = self
+            .func
+            .basic_blocks
+            .order
+            .iter()
+            .copied()
+            .filter(|block| loop_.contains(block))
+            .collect();
+        let mut exits = vec![];
+        for &block in &blocks {
+            for succ in sorted(self.func.basic_blocks.arena[block].succ.iter().copied()) {
+                if !loop_.contains(&succ) && !exits.contains(&succ) {
+                    exits.push(succ)
+                }
+            }
+        }
+
+        let mut changed = false;
+        for &block in &blocks {
+            let iseq = self.func.basic_blocks.arena[block].iseq_ref().clone();
+            for val in iseq {
+                let id = val.as_instruction().id;
+                let users = self.outside_users_of(loop_, id);
+                if !users.is_empty() {
+                    self.close_value(loop_, id, users, &exits, dom_tree);
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    // Returns the users of `id` that use it outside the loop. A phi in an exit block
+    // uses it at the end of the block it comes from.
+    fn outside_users_of(&self, loop_: &Loop<BasicBlock>, id: InstructionId) -> Vec<InstructionId> {
+        let val = self.func.get_inst_value(id);
+        let mut users = self.func.inst_table[id].users.borrow().clone();
+        users.sort_by_key(|id| id.index());
+        users.dedup();
+        users.retain(|&user| {
+            let inst = &self.func.inst_table[user];
+            match inst.opcode {
+                Opcode::Phi => inst.operands.chunks(2).any(|pair| {
+                    *pair[0].as_value() == val && !loop_.contains(pair[1].as_basic_block())
+                }),
+                _ => !loop_.contains(&inst.parent),
+            }
+        });
+        users
+    }
+
+    fn close_value(
+        &mut self,
+        loop_: &Loop<BasicBlock>,
+        id: InstructionId,
+        users: Vec<InstructionId>,
+        exits: &[BasicBlockId],
+        dom_tree: &DominatorTree<BasicBlock>,
+    ) {
+        let val = self.func.get_inst_value(id);
+        let def = self.func.inst_table[id].parent;
+        let name = self
+            .func
+            .names
+            .get_inst(id)
+            .map(|name| format!("{}.lcssa", name));
+        let mut values: FxHashMap<BasicBlockId, Value> = FxHashMap::default();
+
+        for &exit in exits {
+            let preds = sorted(self.func.basic_blocks.arena[exit].pred.iter().copied());
+            if !dom_tree.dominate_bb(def, exit) || preds.iter().any(|p| !loop_.contains(p)) {
+                continue;
+            }
+            let phi = self.insert_phi(exit, val);
+            for pred in preds {
+                self.add_incoming(phi, val, pred);
+            }
+            if let Some(name) = &name {
+                self.func.names.set_inst(phi, name);
+            }
+            values.insert(exit, self.func.get_inst_value(phi));
+        }
+
+        for user in users {
+            let parent = self.func.inst_table[user].parent;
+            if self.func.inst_table[user].opcode != Opcode::Phi {
+                let new = self.value_in(loop_, val, parent, &mut values);
+                Instruction::replace_operand(
+                    &mut self.func.inst_table,
+                    user,
+                    &Operand::Value(val),
+                    Operand::Value(new),
+                );
+                continue;
+            }
+
+            let inst = &self.func.inst_table[user];
+            let pairs: Vec<(Value, BasicBlockId)> = inst
+                .operands
+                .chunks(2)
+                .map(|pair| (*pair[0].as_value(), *pair[1].as_basic_block()))
+                .collect();
+            let ty = inst.ty;
+            let mut operands = vec![];
+            for (v, block) in pairs {
+                let v = if v == val && !loop_.contains(&block) {
+                    self.value_in(loop_, val, block, &mut values)
+                } else {
+                    v
+                };
+                operands.push(Operand::Value(v));
+                operands.push(Operand::BasicBlock(block));
+            }
+            self.func
+                .change_inst(user, Instruction::new(Opcode::Phi, operands, ty, parent));
+        }
+    }
+
+    // Returns the value `val` has in `block`, inserting phis where the values from
+    // several predecessors meet.
+    fn value_in(
+        &mut self,
+        loop_: &Loop<BasicBlock>,
+        val: Value,
+        block: BasicBlockId,
+        values: &mut FxHashMap<BasicBlockId, Value>,
+    ) -> Value {
+        if let Some(&v) = values.get(&block) {
+            return v;
+        }
+        if loop_.contains(&block) {
+            return val;
+        }
+
+        let preds = sorted(self.func.basic_blocks.arena[block].pred.iter().copied());
+        if preds.len() == 1 {
+            let v = self.value_in(loop_, val, preds[0], values);
+            values.insert(block, v);
+            return v;
+        }
+
+        // The phi is in place before its incoming values are looked up, since they may
+        // come around a cycle back to `block`.
+        let phi = self.insert_phi(block, val);
+        let phi_val = self.func.get_inst_value(phi);
+        values.insert(block, phi_val);
+        let incomings: Vec<Value> = preds
+            .iter()
+            .map(|&pred| {
+                let v = self.value_in(loop_, val, pred, values);
+                self.add_incoming(phi, v, pred);
+                v
+            })
+            .collect();
+
+        let mut others = incomings.into_iter().filter(|&v| v != phi_val);
+        let first = match others.next() {
+            Some(first) if others.all(|v| v == first) => first,
+            _ => return phi_val,
+        };
+        // The values from all predecessors are the same.
+        Instruction::replace_all_uses(&mut self.func.inst_table, phi, Operand::Value(first));
+        self.func.remove_inst(phi);
+        for v in values.values_mut() {
+            if *v == phi_val {
+                *v = first
+            }
+        }
+        first
+    }
+
+    fn insert_phi(&mut self, block: BasicBlockId, val: Value) -> InstructionId {
+        let phi =
+            self.func
+                .alloc_inst(Instruction::new(Opcode::Phi, vec![], val.get_type(), block));
+        let phi_val = self.func.get_inst_value(phi);
+        self.func.basic_blocks.arena[block]
+            .iseq_ref_mut()
+            .insert(0, phi_val);
+        phi
+    }
+
+    fn add_incoming(&mut self, phi: InstructionId, val: Value, block: BasicBlockId) {
+        Instruction::add_operand(&mut self.func.inst_table, phi, Operand::Value(val));
+        Instruction::add_operand(&mut self.func.inst_table, phi, Operand::BasicBlock(block));
+    }
+}
+
+fn sorted(blocks: impl Iterator<Item = BasicBlockId>) -> Vec<BasicBlockId> {
+    let mut blocks: Vec<BasicBlockId> = blocks.collect();
+    blocks.sort_by_key(|id| id.index());
+    blocks
+}
@@ -1,7 +1,7 @@
 use crate::{
     analysis::{
-        dom_tree::DominatorTreeConstructor,
-        loops::{Loop, Loops, LoopsConstructor},
+        loops::{Loop, Loops},
+        manager::{AnalysisManager, PreservedAnalyses},
     },
     ir::{
         basic_block::{BasicBlock, BasicBlockId},
@@ -9,9 +9,10 @@ use crate::{
         function::Function,
         module::Module,
         opcode::{Instruction, Opcode, Operand},
-        simplify_loop::SimplifyLoop,
+        simplify_loop::SimplifyLoopOnFunction,
         value::*,
     },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
 };
 use id_arena::Id;
 use rustc_hash::FxHashMap;
@@ -29,14 +30,50 @@ impl LoopInvariantCodeMotion {
     }
 
     pub fn run_on_module(&mut self, module: &mut Module) {
-        SimplifyLoop::new().run_on_module(module);
+        run_function_pass_on_module(self, module)
+    }
+}
 
-        for (_, func) in &mut module.functions {
-            if func.is_internal || func.is_empty() {
-                continue;
-            }
-            LoopInvariantCodeMotionOnFunction::new(func).run();
+impl ModulePassTrait for LoopInvariantCodeMotion {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "LoopInvariantCodeMotion"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for LoopInvariantCodeMotion {
+    fn name(&self) -> &'static str {
+        "LoopInvariantCodeMotion"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let dom_tree = am.get_dom_tree(func);
+        let changed = SimplifyLoopOnFunction::new(func).run_with_dom_tree(&dom_tree);
+        if changed {
+            am.invalidate(func, &PreservedAnalyses::none());
         }
+
+        let loops = am.get_loops(func);
+        if loops.arena.len() == 0 {
+            return if changed {
+                PreservedAnalyses::none()
+            } else {
+                PreservedAnalyses::all()
+            };
+        }
+
+        // Pre-headers are inserted for every loop.
+        LoopInvariantCodeMotionOnFunction::new(func).run(&loops);
+        PreservedAnalyses::none()
     }
 }
 
@@ -45,9 +82,8 @@ impl<'a> LoopInvariantCodeMotionOnFunction<'a> {
         Self { func }
     }
 
-    pub fn run(&mut self) {
-        let dom_tree = DominatorTreeConstructor::new(&self.func.basic_blocks).construct();
-        let mut loops = LoopsConstructor::new(&dom_tree, &self.func.basic_blocks).analyze();
+    pub fn run(&mut self, loops: &Loops<BasicBlock>) {
+        let mut loops = loops.clone();
 
         let pre_headers = self.insert_pre_headers(&mut loops);
 
@@ -0,0 +1,439 @@
+use crate::{
+    analysis::{
+        loops::{Loop, Loops},
+        manager::{AnalysisManager, PreservedAnalyses},
+    },
+    ir::{
+        basic_block::{BasicBlock, BasicBlockId},
+        builder::{IRBuilder, IRBuilderWithFunction},
+        function::Function,
+        lcssa::LoopClosedSSAOnFunction,
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        simplify_loop::SimplifyLoopOnFunction,
+        types::Type,
+        value::Value,
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::cmp::Reverse;
+
+/// Headers with more instructions than this are not copied into the guard.
+const MAX_HEADER_SIZE: usize = 16;
+
+/// Rotates loops testing their condition in the header, such as `while` loops, into a
+/// guard and a loop testing it at the bottom, such as `do`-`while` loops:
+///
+/// ```text
+/// preheader:                    preheader:
+///   br header                     (header copied)
+/// header:                         br %c.0, new_preheader, exit
+///   br %c, body, exit     =>    new_preheader:
+/// body:                           br body
+///   br header                   body:
+/// exit:                           br header
+///                               header:
+///                                 br %c, body, exit
+///                               exit:
+/// ```
+///
+/// The new preheader is reached only if the loop runs at least once, so it's safe to
+/// hoist code into. Loops are put into loop-closed SSA form first, so the values of the
+/// header are used after the loop only by phis in the exit, which take the copies in
+/// the guard when the loop is skipped.
+pub struct LoopRotate {}
+
+struct LoopRotateOnFunction<'a> {
+    func: &'a mut Function,
+}
+
+/// A loop whose header exits it and branches to `body` otherwise
+struct RotateShape {
+    blocks: FxHashSet<BasicBlockId>,
+    header: BasicBlockId,
+    preheader: BasicBlockId,
+    latch: BasicBlockId,
+    body: BasicBlockId,
+    exit: BasicBlockId,
+}
+
+impl LoopRotate {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for LoopRotate {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "LoopRotate"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for LoopRotate {
+    fn name(&self) -> &'static str {
+        "LoopRotate"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        // The headers of the rotated loops, which mustn't be rotated again
+        let mut rotated = FxHashSet::default();
+        let mut changed = false;
+
+        // Loops are found again after every rotation, since it changes the loops around.
+        loop {
+            let dom_tree = am.get_dom_tree(func);
+            if SimplifyLoopOnFunction::new(func).run_with_dom_tree(&dom_tree) {
+                am.invalidate(func, &PreservedAnalyses::none());
+                changed = true;
+                continue;
+            }
+
+            let loops = am.get_loops(func);
+            changed |= LoopClosedSSAOnFunction::new(func).run(&loops, &dom_tree);
+            let rotated_any = LoopRotateOnFunction { func }.run(&loops, &mut rotated);
+            if !rotated_any {
+                break;
+            }
+            am.invalidate(func, &PreservedAnalyses::none());
+            changed = true;
+        }
+
+        if changed {
+            PreservedAnalyses::none()
+        } else {
+            PreservedAnalyses::all()
+        }
+    }
+}
+
+impl<'a> LoopRotateOnFunction<'a> {
+    /// Rotates the innermost loop that can be. Returns true if any loop is rotated.
+    fn run(&mut self, loops: &Loops<BasicBlock>, rotated: &mut FxHashSet<BasicBlockId>) -> bool {
+        let depth = |mut id| {
+            let mut depth = 0;
+            while let Some(parent) = loops.arena[id].parent {
+                depth += 1;
+                id = parent;
+            }
+            depth
+        };
+        let mut ids: Vec<_> = loops.arena.iter().map(|(id, _)| id).collect();
+        ids.sort_by_key(|&id| (Reverse(depth(id)), loops.arena[id].header.index()));
+
+        for id in ids {
+            let loop_ = &loops.arena[id];
+            if rotated.contains(&loop_.header) {
+                continue;
+            }
+            if let Some(shape) = self.shape_of(loop_) {
+                self.rotate(&shape);
+                rotated.insert(shape.body);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn shape_of(&self, loop_: &Loop<BasicBlock>) -> Option<RotateShape> {
+        let header = loop_.header;
+        let blocks = &self.func.basic_blocks.arena;
+
+        let (inside, outside): (Vec<BasicBlockId>, Vec<BasicBlockId>) = blocks[header]
+            .pred
+            .iter()
+            .copied()
+            .partition(|pred| loop_.contains(pred));
+        let (latch, preheader) = match (&inside[..], &outside[..]) {
+            (&[latch], &[preheader]) if latch != header => (latch, preheader),
+            _ => return None,
+        };
+        let pre_br = self.func.get_terminator(preheader)?;
+        if self.func.inst_table[pre_br].opcode != Opcode::Br {
+            return None;
+        }
+
+        let br = &self.func.inst_table[self.func.get_terminator(header)?];
+        if br.opcode != Opcode::CondBr {
+            return None;
+        }
+        let (then, else_) = (
+            *br.operands[1].as_basic_block(),
+            *br.operands[2].as_basic_block(),
+        );
+        let (body, exit) = match (loop_.contains(&then), loop_.contains(&else_)) {
+            (true, false) => (then, else_),
+            (false, true) => (else_, then),
+            _ => return None,
+        };
+        if body == header || blocks[body].pred.len() != 1 {
+            return None;
+        }
+
+        let insts: Vec<InstructionId> = blocks[header]
+            .iseq_ref()
+            .iter()
+            .map(|v| v.as_instruction().id)
+            .collect();
+        let size = insts
+            .iter()
+            .filter(|&&id| self.func.inst_table[id].opcode != Opcode::Phi)
+            .count();
+        let has_alloca = insts
+            .iter()
+            .any(|&id| self.func.inst_table[id].opcode == Opcode::Alloca);
+        if size > MAX_HEADER_SIZE || has_alloca {
+            return None;
+        }
+
+        // Values of the header may be used outside the loop only by phis, as in
+        // loop-closed SSA form.
+        let closed = insts.iter().all(|&id| {
+            let val = self.func.get_inst_value(id);
+            self.func.inst_table[id].users.borrow().iter().all(|&user| {
+                let inst = &self.func.inst_table[user];
+                match inst.opcode {
+                    Opcode::Phi => inst.operands.chunks(2).all(|pair| {
+                        *pair[0].as_value() != val || loop_.contains(pair[1].as_basic_block())
+                    }),
+                    _ => loop_.contains(&inst.parent),
+                }
+            })
+        });
+        if !closed {
+            return None;
+        }
+
+        Some(RotateShape {
+            blocks: loop_.set.clone(),
+            header,
+            preheader,
+            latch,
+            body,
+            exit,
+        })
+    }
+
+    fn rotate(&mut self, shape: &RotateShape) {
+        let header = shape.header;
+        let insts: Vec<InstructionId> = self.func.basic_blocks.arena[header]
+            .iseq_ref()
+            .iter()
+            .map(|v| v.as_instruction().id)
+            .collect();
+        let (br, insts) = insts.split_last().unwrap();
+        let br = *br;
+        let phis: Vec<InstructionId> = insts
+            .iter()
+            .copied()
+            .take_while(|&id| self.func.inst_table[id].opcode == Opcode::Phi)
+            .collect();
+
+        // Uses of the header in the loop, which get the values through phis in `body`
+        let mut uses = vec![];
+        for &id in insts {
+            for &user in &*self.func.inst_table[id].users.borrow() {
+                if self.func.inst_table[user].parent != header && !uses.contains(&(user, id)) {
+                    uses.push((user, id))
+                }
+            }
+        }
+
+        // The guard computes the first run of the header in the preheader.
+        let mut guard: FxHashMap<InstructionId, Value> = FxHashMap::default();
+        for &phi in &phis {
+            guard.insert(phi, self.func.get_incoming(phi, shape.preheader).unwrap());
+        }
+        let pre_br = self.func.get_terminator(shape.preheader).unwrap();
+        for &id in &insts[phis.len()..] {
+            let inst = &self.func.inst_table[id];
+            let (opcode, ty) = (inst.opcode, inst.ty);
+            let operands = inst
+                .operands
+                .iter()
+                .map(|op| match op {
+                    Operand::Value(v) => Operand::Value(map_value(&guard, *v)),
+                    op => *op,
+                })
+                .collect();
+            let new = self
+                .func
+                .alloc_inst(Instruction::new(opcode, operands, ty, shape.preheader));
+            if let Some(name) = self.func.names.get_inst(id).cloned() {
+                self.func.names.set_inst(new, &name);
+            }
+            let new = self.func.get_inst_value(new);
+            let (_, pos) = self.func.find_inst_pos(pre_br).unwrap();
+            self.func.basic_blocks.arena[shape.preheader]
+                .iseq_ref_mut()
+                .insert(pos, new);
+            guard.insert(id, new);
+        }
+
+        // The guard skips the loop or enters it through a new preheader.
+        let new_preheader = self.func.append_basic_block_before(header);
+        let operands = self.func.inst_table[br]
+            .operands
+            .iter()
+            .map(|op| match op {
+                Operand::Value(v) => Operand::Value(map_value(&guard, *v)),
+                Operand::BasicBlock(b) if *b == shape.body => Operand::BasicBlock(new_preheader),
+                op => *op,
+            })
+            .collect();
+        self.func.change_inst(
+            pre_br,
+            Instruction::new(Opcode::CondBr, operands, Type::Void, shape.preheader),
+        );
+        let preheader = &mut self.func.basic_blocks.arena[shape.preheader];
+        preheader.succ.clear();
+        preheader.succ.insert(new_preheader);
+        preheader.succ.insert(shape.exit);
+        self.func.basic_blocks.arena[header]
+            .pred
+            .remove(&shape.preheader);
+        self.func.basic_blocks.arena[new_preheader]
+            .pred
+            .insert(shape.preheader);
+        self.func.basic_blocks.arena[shape.exit]
+            .pred
+            .insert(shape.preheader);
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point(new_preheader);
+        builder.build_br(shape.body);
+
+        // Phis in the exit and in the body get the values of the guard from the new
+        // edges.
+        for (block, from) in [(shape.exit, shape.preheader), (shape.body, new_preheader)] {
+            for phi in self.func.get_phis(block) {
+                if let Some(val) = self.func.get_incoming(phi, header) {
+                    let val = map_value(&guard, val);
+                    self.add_incoming(phi, val, from);
+                }
+            }
+        }
+
+        let mut body_phis = FxHashMap::default();
+        for (user, id) in uses {
+            let inst = &self.func.inst_table[user];
+            let val = self.func.get_inst_value(id);
+            if inst.opcode != Opcode::Phi {
+                let new = self.body_phi(shape, new_preheader, &guard, &mut body_phis, id);
+                Instruction::replace_operand(
+                    &mut self.func.inst_table,
+                    user,
+                    &Operand::Value(val),
+                    Operand::Value(new),
+                );
+                continue;
+            }
+
+            // Values coming from the header are still the ones computed there.
+            let pairs: Vec<(Value, BasicBlockId)> = inst
+                .operands
+                .chunks(2)
+                .map(|pair| (*pair[0].as_value(), *pair[1].as_basic_block()))
+                .collect();
+            let (ty, parent) = (inst.ty, inst.parent);
+            let mut operands = vec![];
+            for (v, block) in pairs {
+                let v = if v == val && block != header && shape.blocks.contains(&block) {
+                    self.body_phi(shape, new_preheader, &guard, &mut body_phis, id)
+                } else {
+                    v
+                };
+                operands.push(Operand::Value(v));
+                operands.push(Operand::BasicBlock(block));
+            }
+            self.func
+                .change_inst(user, Instruction::new(Opcode::Phi, operands, ty, parent));
+        }
+
+        // Now the header is reached only from the latch.
+        let from_latch: Vec<Value> = phis
+            .iter()
+            .map(|&phi| {
+                let val = self.func.get_incoming(phi, shape.latch).unwrap();
+                match val {
+                    Value::Instruction(iv) if insts.contains(&iv.id) => {
+                        self.body_phi(shape, new_preheader, &guard, &mut body_phis, iv.id)
+                    }
+                    v => v,
+                }
+            })
+            .collect();
+        for (&phi, val) in phis.iter().zip(from_latch) {
+            Instruction::replace_all_uses(&mut self.func.inst_table, phi, Operand::Value(val));
+            self.func.remove_inst(phi);
+        }
+
+        // The header goes to the bottom of the loop.
+        let order = &mut self.func.basic_blocks.order;
+        order.retain(|&b| b != header);
+        let latch_pos = order.iter().position(|&b| b == shape.latch).unwrap();
+        order.insert(latch_pos + 1, header);
+    }
+
+    // Returns the phi in `body` merging the value of `id` from the guard and from the
+    // header.
+    fn body_phi(
+        &mut self,
+        shape: &RotateShape,
+        new_preheader: BasicBlockId,
+        guard: &FxHashMap<InstructionId, Value>,
+        body_phis: &mut FxHashMap<InstructionId, Value>,
+        id: InstructionId,
+    ) -> Value {
+        if let Some(&phi) = body_phis.get(&id) {
+            return phi;
+        }
+
+        let val = self.func.get_inst_value(id);
+        let phi = self.func.alloc_inst(Instruction::new(
+            Opcode::Phi,
+            vec![
+                Operand::Value(guard[&id]),
+                Operand::BasicBlock(new_preheader),
+                Operand::Value(val),
+                Operand::BasicBlock(shape.header),
+            ],
+            val.get_type(),
+            shape.body,
+        ));
+        if let Some(name) = self.func.names.get_inst(id).cloned() {
+            self.func.names.set_inst(phi, &name);
+        }
+        let phi = self.func.get_inst_value(phi);
+        self.func.basic_blocks.arena[shape.body]
+            .iseq_ref_mut()
+            .insert(0, phi);
+        body_phis.insert(id, phi);
+        phi
+    }
+
+    fn add_incoming(&mut self, phi: InstructionId, val: Value, block: BasicBlockId) {
+        Instruction::add_operand(&mut self.func.inst_table, phi, Operand::Value(val));
+        Instruction::add_operand(&mut self.func.inst_table, phi, Operand::BasicBlock(block));
+    }
+}
+
+fn map_value(map: &FxHashMap<InstructionId, Value>, val: Value) -> Value {
+    match val.get_inst_id().and_then(|id| map.get(&id)) {
+        Some(&new) => new,
+        None => val,
+    }
+}
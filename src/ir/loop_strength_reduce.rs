@@ -0,0 +1,379 @@
+use crate::{
+    analysis::{
+        induction::{InductionAnalysis, InductionVariable, LoopInduction},
+        loops::{Loop, Loops},
+        manager::{AnalysisManager, PreservedAnalyses},
+    },
+    ir::{
+        basic_block::{BasicBlock, BasicBlockId},
+        builder::{IRBuilder, IRBuilderWithFunction},
+        function::Function,
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        simplify_loop::SimplifyLoopOnFunction,
+        types::Type,
+        value::Value,
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+use std::cmp::Reverse;
+
+/// Replaces expressions derived from induction variables with induction variables of
+/// their own, so that they are increased on every iteration instead of computed again:
+///
+/// ```text
+/// header:                                header:
+///   %i = phi [0, pre], [%i.next, latch]    %p = phi [%a.0, pre], [%p.next, latch]
+///   %p = gep %a, [0, %i]           =>      ...
+///   ...                                    %p.next = gep %p, [1]
+///   %i.next = add %i, 1
+/// ```
+///
+/// `Mul`s by a constant get integer phis, which are reduced again if they index
+/// `GetElementPtr`s. A counter left used only by the exit test is replaced by one of the
+/// new pointer phis in the test and removed.
+pub struct LoopStrengthReduction {}
+
+struct LoopStrengthReductionOnFunction<'a> {
+    func: &'a mut Function,
+}
+
+/// How an expression is computed from an induction variable `iv`
+enum Derivation {
+    /// `iv * factor`
+    Scale(i64),
+
+    /// `getelementptr base, indices.., iv`, holding `base` and `indices`
+    Pointer(Vec<Value>),
+}
+
+impl LoopStrengthReduction {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for LoopStrengthReduction {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "LoopStrengthReduction"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for LoopStrengthReduction {
+    fn name(&self) -> &'static str {
+        "LoopStrengthReduction"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let dom_tree = am.get_dom_tree(func);
+        let simplified = SimplifyLoopOnFunction::new(func).run_with_dom_tree(&dom_tree);
+        if simplified {
+            am.invalidate(func, &PreservedAnalyses::none());
+        }
+
+        let loops = am.get_loops(func);
+        let induction = am.get_induction(func);
+        let reduced = LoopStrengthReductionOnFunction { func }.run(&loops, &induction);
+        if simplified {
+            PreservedAnalyses::none()
+        } else if reduced {
+            PreservedAnalyses::cfg()
+        } else {
+            PreservedAnalyses::all()
+        }
+    }
+}
+
+impl<'a> LoopStrengthReductionOnFunction<'a> {
+    /// Returns true if any expression is reduced. Inner loops are reduced first, so an
+    /// outer loop sees the start values they compute in their preheaders.
+    fn run(&mut self, loops: &Loops<BasicBlock>, induction: &InductionAnalysis) -> bool {
+        let depth = |mut id| {
+            let mut depth = 0;
+            while let Some(parent) = loops.arena[id].parent {
+                depth += 1;
+                id = parent;
+            }
+            depth
+        };
+        let mut ids: Vec<_> = loops.arena.iter().map(|(id, _)| id).collect();
+        ids.sort_by_key(|&id| (Reverse(depth(id)), loops.arena[id].header.index()));
+
+        let mut changed = false;
+        for id in ids {
+            if let Some(induction) = induction.get(id) {
+                changed |= self.reduce_loop(&loops.arena[id], induction);
+            }
+        }
+        changed
+    }
+
+    fn reduce_loop(&mut self, loop_: &Loop<BasicBlock>, induction: &LoopInduction) -> bool {
+        let (preheader, latch) = match (induction.preheader, induction.latch) {
+            (Some(preheader), Some(latch)) => (preheader, latch),
+            _ => return false,
+        };
+
+        let mut changed = false;
+        let mut reduced = vec![];
+        for iv in &induction.induction_vars {
+            // The induction variables derived from `iv` itself, which may take its place
+            // in the exit test
+            let mut derived = vec![];
+            let mut worklist = vec![iv.clone()];
+            while let Some(var) = worklist.pop() {
+                for user in self.users_of(var.phi) {
+                    let derivation = match self.derivation_of(loop_, &var, user) {
+                        Some(derivation) => derivation,
+                        None => continue,
+                    };
+                    let new = self.reduce(loop_.header, preheader, latch, &var, user, &derivation);
+                    if let Derivation::Scale(_) = derivation {
+                        worklist.push(new.clone())
+                    }
+                    reduced.push(new.clone());
+                    if var.phi == iv.phi {
+                        derived.push((new, derivation))
+                    }
+                    changed = true;
+                }
+            }
+            changed |= self.remove_counter(loop_, preheader, iv, &derived);
+        }
+
+        // Integer induction variables may be left used only by the ones derived from
+        // them.
+        for var in reduced.iter().rev() {
+            if self.users_of(var.phi) == [var.next] && self.users_of(var.next) == [var.phi] {
+                self.func.remove_inst(var.next);
+                self.func.remove_inst(var.phi);
+            }
+        }
+        changed
+    }
+
+    fn derivation_of(
+        &self,
+        loop_: &Loop<BasicBlock>,
+        var: &InductionVariable,
+        user: InstructionId,
+    ) -> Option<Derivation> {
+        let inst = &self.func.inst_table[user];
+        if !loop_.contains(&inst.parent) {
+            return None;
+        }
+        let is_var = |op: &Operand| op.as_value().get_inst_id() == Some(var.phi);
+
+        match inst.opcode {
+            Opcode::Mul => {
+                let (lhs, rhs) = (&inst.operands[0], &inst.operands[1]);
+                let factor = if is_var(lhs) {
+                    rhs.as_value().as_i64()
+                } else if is_var(rhs) {
+                    lhs.as_value().as_i64()
+                } else {
+                    None
+                };
+                factor.filter(|&f| f != 0).map(Derivation::Scale)
+            }
+            // Only the last index may change, so that the address moves by whole
+            // elements.
+            Opcode::GetElementPtr if inst.operands.len() >= 2 => {
+                let (last, rest) = inst.operands.split_last().unwrap();
+                let invariant = rest
+                    .iter()
+                    .all(|op| self.is_invariant(loop_, op.as_value()));
+                if !is_var(last) || !invariant {
+                    return None;
+                }
+                Some(Derivation::Pointer(
+                    rest.iter().map(|op| *op.as_value()).collect(),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    // Replaces `user`, derived from `var`, with a new induction variable.
+    fn reduce(
+        &mut self,
+        header: BasicBlockId,
+        preheader: BasicBlockId,
+        latch: BasicBlockId,
+        var: &InductionVariable,
+        user: InstructionId,
+        derivation: &Derivation,
+    ) -> InductionVariable {
+        let name = self.func.names.get_inst(user).cloned();
+        let var_ty = self.func.inst_table[var.phi].ty;
+
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point_before_terminator(preheader);
+        let (start, step) = match derivation {
+            Derivation::Scale(factor) => (
+                builder.build_mul(var.start, Value::new_imm_int(var_ty, *factor)),
+                var.step * factor,
+            ),
+            Derivation::Pointer(base) => {
+                let mut indices = base[1..].to_vec();
+                indices.push(var.start);
+                (builder.build_gep(base[0], indices), var.step)
+            }
+        };
+
+        builder.set_insert_point_at(0, header);
+        let phi = builder.build_phi(vec![(start, preheader)]);
+
+        // The next value is computed right after the one of `var`, so it's ready
+        // wherever that is.
+        let (block, pos) = self.func.find_inst_pos(var.next).unwrap();
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point_at(pos + 1, block);
+        let next = match derivation {
+            Derivation::Scale(_) => builder.build_add(phi, Value::new_imm_int(var_ty, step)),
+            Derivation::Pointer(_) => {
+                builder.build_gep(phi, vec![Value::new_imm_int(var_ty, step)])
+            }
+        };
+        if let Some(name) = name {
+            builder.set_value_name(phi, &name);
+            builder.set_value_name(next, &format!("{}.next", name));
+        }
+
+        let phi_id = phi.get_inst_id().unwrap();
+        let next_id = next.get_inst_id().unwrap();
+        Instruction::add_operand(&mut self.func.inst_table, phi_id, Operand::Value(next));
+        Instruction::add_operand(
+            &mut self.func.inst_table,
+            phi_id,
+            Operand::BasicBlock(latch),
+        );
+        Instruction::replace_all_uses(&mut self.func.inst_table, user, Operand::Value(phi));
+        self.func.remove_inst(user);
+
+        InductionVariable {
+            phi: phi_id,
+            start,
+            step,
+            next: next_id,
+        }
+    }
+
+    // Removes `iv` if it's used only to test whether to leave the loop, testing one of
+    // the induction variables derived from it instead. Returns true if it's removed.
+    fn remove_counter(
+        &mut self,
+        loop_: &Loop<BasicBlock>,
+        preheader: BasicBlockId,
+        iv: &InductionVariable,
+        derived: &[(InductionVariable, Derivation)],
+    ) -> bool {
+        let phi_users = self.users_of(iv.phi);
+        let next_users = self.users_of(iv.next);
+        if !phi_users.contains(&iv.next) || !next_users.contains(&iv.phi) {
+            return false;
+        }
+        let others: Vec<(InstructionId, bool)> = phi_users
+            .into_iter()
+            .filter(|&user| user != iv.next)
+            .map(|user| (user, false))
+            .chain(
+                next_users
+                    .into_iter()
+                    .filter(|&user| user != iv.phi)
+                    .map(|user| (user, true)),
+            )
+            .collect();
+        if others.len() != 1 {
+            return false;
+        }
+        let (cmp, tests_next) = others[0];
+
+        // The test must decide only the branch right after it.
+        let inst = &self.func.inst_table[cmp];
+        let cmp_users = self.users_of(cmp);
+        if inst.opcode != Opcode::ICmp || cmp_users.len() != 1 {
+            return false;
+        }
+        let br = &self.func.inst_table[cmp_users[0]];
+        if br.opcode != Opcode::CondBr || br.parent != inst.parent {
+            return false;
+        }
+
+        let counter = if tests_next { iv.next } else { iv.phi };
+        let (lhs, rhs) = (*inst.operands[1].as_value(), *inst.operands[2].as_value());
+        let (bound, counter_is_lhs) = if lhs.get_inst_id() == Some(counter) {
+            (rhs, true)
+        } else {
+            (lhs, false)
+        };
+        if !self.is_invariant(loop_, &bound) {
+            return false;
+        }
+
+        // A pointer keeps the order of the indices, so the test keeps its kind. A scaled
+        // bound could wrap where none of the scaled values computed in the loop do.
+        let (new, base) = match derived.iter().find_map(|(new, d)| match d {
+            Derivation::Pointer(base) => Some((new, base)),
+            Derivation::Scale(_) => None,
+        }) {
+            Some(derived) => derived,
+            None => return false,
+        };
+        let (kind, parent) = (inst.operands[0], inst.parent);
+
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point_before_terminator(preheader);
+        let mut indices = base[1..].to_vec();
+        indices.push(bound);
+        let bound = builder.build_gep(base[0], indices);
+        let counter = if tests_next { new.next } else { new.phi };
+        let counter = self.func.get_inst_value(counter);
+        let (lhs, rhs) = if counter_is_lhs {
+            (counter, bound)
+        } else {
+            (bound, counter)
+        };
+        self.func.change_inst(
+            cmp,
+            Instruction::new(
+                Opcode::ICmp,
+                vec![kind, Operand::Value(lhs), Operand::Value(rhs)],
+                Type::i1,
+                parent,
+            ),
+        );
+
+        self.func.remove_inst(iv.next);
+        self.func.remove_inst(iv.phi);
+        true
+    }
+
+    fn users_of(&self, id: InstructionId) -> Vec<InstructionId> {
+        let mut users = self.func.inst_table[id].users.borrow().clone();
+        users.sort_by_key(|id| id.index());
+        users.dedup();
+        users
+    }
+
+    fn is_invariant(&self, loop_: &Loop<BasicBlock>, val: &Value) -> bool {
+        match val {
+            Value::Instruction(iv) => !loop_.contains(&self.func.inst_table[iv.id].parent),
+            _ => true,
+        }
+    }
+}
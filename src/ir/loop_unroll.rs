@@ -0,0 +1,766 @@
+use crate::{
+    analysis::{
+        induction::{InductionAnalysis, LoopInduction},
+        loops::{Loop, Loops},
+        manager::{AnalysisManager, PreservedAnalyses},
+    },
+    ir::{
+        basic_block::{BasicBlock, BasicBlockId},
+        builder::{IRBuilder, IRBuilderWithFunction},
+        function::Function,
+        module::Module,
+        opcode::{ICmpKind, Instruction, InstructionId, Opcode, Operand},
+        simplify_cfg::SimplifyCFG,
+        simplify_loop::SimplifyLoopOnFunction,
+        types::Type,
+        value::Value,
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Unrolls innermost loops whose trip count `InductionAnalysis` knows.
+///
+/// A loop with a constant trip count is fully unrolled if the copies of its body have
+/// at most `threshold` instructions in total. Otherwise, a loop testing an induction
+/// variable with `<`, `<=`, `>` or `>=` in its header is unrolled `factor` times: the
+/// unrolled loop runs while `factor` more iterations are left, and a copy of the
+/// original loop runs the rest.
+///
+/// `SimplifyCFG` then merges the copies into straight-line code.
+pub struct LoopUnroll {
+    pub threshold: usize,
+    pub factor: usize,
+}
+
+struct LoopUnrollOnFunction<'a> {
+    func: &'a mut Function,
+    threshold: usize,
+    factor: usize,
+}
+
+/// A loop with a preheader, a single latch and a single exiting block, which is the
+/// header or the latch
+struct LoopShape {
+    /// In layout order
+    blocks: Vec<BasicBlockId>,
+    set: FxHashSet<BasicBlockId>,
+    header: BasicBlockId,
+    latch: BasicBlockId,
+    preheader: BasicBlockId,
+    exiting: BasicBlockId,
+    exit: BasicBlockId,
+}
+
+/// The blocks and values of a copy of a loop, by those of the loop. Empty for the loop
+/// itself.
+#[derive(Default)]
+struct LoopCopy {
+    blocks: FxHashMap<BasicBlockId, BasicBlockId>,
+    values: FxHashMap<InstructionId, Value>,
+}
+
+/// The exit test of a loop unrolled `factor` times
+struct ExitTest {
+    cond: InstructionId,
+
+    /// The index of the operand of `cond` that is an induction variable
+    operand: usize,
+
+    /// How far the induction variable moves in all copies but the first
+    distance: i64,
+}
+
+impl LoopUnroll {
+    pub fn new() -> Self {
+        Self {
+            threshold: 150,
+            factor: 4,
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_factor(mut self, factor: usize) -> Self {
+        self.factor = factor;
+        self
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for LoopUnroll {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "LoopUnroll"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for LoopUnroll {
+    fn name(&self) -> &'static str {
+        "LoopUnroll"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let dom_tree = am.get_dom_tree(func);
+        let simplified = SimplifyLoopOnFunction::new(func).run_with_dom_tree(&dom_tree);
+        if simplified {
+            am.invalidate(func, &PreservedAnalyses::none());
+        }
+
+        let loops = am.get_loops(func);
+        let induction = am.get_induction(func);
+        let unrolled = LoopUnrollOnFunction {
+            func,
+            threshold: self.threshold,
+            factor: self.factor,
+        }
+        .run(&loops, &induction);
+        if !unrolled {
+            return if simplified {
+                PreservedAnalyses::none()
+            } else {
+                PreservedAnalyses::all()
+            };
+        }
+
+        // Cached analyses describe the loops before unrolling.
+        am.invalidate(func, &PreservedAnalyses::none());
+        SimplifyCFG::new().run_on_function(func, am);
+        PreservedAnalyses::none()
+    }
+}
+
+impl<'a> LoopUnrollOnFunction<'a> {
+    /// Returns true if any loop is unrolled.
+    fn run(&mut self, loops: &Loops<BasicBlock>, induction: &InductionAnalysis) -> bool {
+        let mut innermost: Vec<_> = loops
+            .arena
+            .iter()
+            .filter(|(_, loop_)| loop_.sub_loops.is_empty())
+            .map(|(id, _)| id)
+            .collect();
+        innermost.sort_by_key(|&id| loops.arena[id].header.index());
+
+        let mut changed = false;
+        for id in innermost {
+            let induction = match induction.get(id) {
+                Some(induction) if induction.trip_count.is_some() => induction,
+                _ => continue,
+            };
+            let shape = match self.shape_of(&loops.arena[id], induction) {
+                Some(shape) => shape,
+                None => continue,
+            };
+            let size = self.size_of(&shape);
+
+            // The exit test passes `trip` times and fails once.
+            let trip = induction.constant_trip_count();
+            let count = trip.map(|trip| trip as usize + 1);
+            if let Some(count) = count.filter(|&c| c.saturating_mul(size) <= self.threshold) {
+                self.fully_unroll(&shape, count);
+                changed = true;
+                continue;
+            }
+
+            if self.factor < 2
+                || (self.factor + 1).saturating_mul(size) > self.threshold
+                || trip.map_or(false, |trip| trip < self.factor as i64)
+            {
+                continue;
+            }
+            if let Some(test) = self.exit_test_of(&shape, induction) {
+                self.partially_unroll(&shape, &test);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    fn shape_of(&self, loop_: &Loop<BasicBlock>, induction: &LoopInduction) -> Option<LoopShape> {
+        let (exiting, exit) = match (&*induction.exiting, &*induction.exits) {
+            (&[exiting], &[exit]) => (exiting, exit),
+            _ => return None,
+        };
+        let latch = induction.latch?;
+        if exiting != induction.header && exiting != latch {
+            return None;
+        }
+
+        let blocks: Vec<BasicBlockId> = self
+            .func
+            .basic_blocks
+            .order
+            .iter()
+            .copied()
+            .filter(|block| loop_.contains(block))
+            .collect();
+        // A copy of an alloca would be another object.
+        let has_alloca = blocks.iter().any(|&block| {
+            self.func.basic_blocks.arena[block]
+                .iseq_ref()
+                .iter()
+                .any(|v| self.func.inst_table[v.as_instruction().id].opcode == Opcode::Alloca)
+        });
+        let br = self.func.get_terminator(exiting)?;
+        if has_alloca || self.func.inst_table[br].opcode != Opcode::CondBr {
+            return None;
+        }
+
+        Some(LoopShape {
+            set: blocks.iter().copied().collect(),
+            blocks,
+            header: induction.header,
+            latch,
+            preheader: induction.preheader?,
+            exiting,
+            exit,
+        })
+    }
+
+    fn size_of(&self, shape: &LoopShape) -> usize {
+        shape
+            .blocks
+            .iter()
+            .map(|&block| self.func.basic_blocks.arena[block].iseq_ref().len())
+            .sum()
+    }
+
+    // Returns the exit test of a loop testing an induction variable in its header, whose
+    // unrolled copies run only if the test would pass for all of them.
+    fn exit_test_of(&self, shape: &LoopShape, induction: &LoopInduction) -> Option<ExitTest> {
+        if shape.exiting != shape.header || shape.header == shape.latch {
+            return None;
+        }
+        let br = self.func.get_terminator(shape.header)?;
+        let cond = self.func.inst_table[br].operands[0]
+            .as_value()
+            .get_inst_id()?;
+        let inst = &self.func.inst_table[cond];
+        if inst.opcode != Opcode::ICmp
+            || !matches!(
+                inst.operands[0].as_icmp_kind(),
+                ICmpKind::Lt | ICmpKind::Le | ICmpKind::Gt | ICmpKind::Ge
+            )
+        {
+            return None;
+        }
+
+        let (operand, step) = (1..3).find_map(|operand| {
+            let id = inst.operands[operand].as_value().get_inst_id()?;
+            induction
+                .induction_vars
+                .iter()
+                .find(|iv| iv.phi == id || iv.next == id)
+                .map(|iv| (operand, iv.step))
+        })?;
+
+        // The test must move towards failing as the induction variable moves.
+        let towards_bound = match (inst.operands[0].as_icmp_kind(), operand) {
+            (ICmpKind::Lt, 1) | (ICmpKind::Le, 1) | (ICmpKind::Gt, 2) | (ICmpKind::Ge, 2) => {
+                step > 0
+            }
+            _ => step < 0,
+        };
+        let bound = *inst.operands[3 - operand].as_value();
+        let invariant = bound.get_inst_id().map_or(true, |id| {
+            !shape.set.contains(&self.func.inst_table[id].parent)
+        });
+        if !towards_bound || !invariant {
+            return None;
+        }
+
+        // A constant bound is known to be far enough from the end of its type.
+        let distance = step.checked_mul(self.factor as i64 - 1)?;
+        let (min, max) = range_of(bound.get_type())?;
+        if bound.get_imm().is_some() {
+            let limit = bound.as_i64()?.checked_sub(distance)?;
+            if limit < min || limit > max {
+                return None;
+            }
+        }
+
+        Some(ExitTest {
+            cond,
+            operand,
+            distance,
+        })
+    }
+
+    // Makes `count` copies of the loop body run one after another, the last one leaving
+    // the loop.
+    fn fully_unroll(&mut self, shape: &LoopShape, count: usize) {
+        let outside_uses = self.outside_uses_of(shape);
+        let exit_incomings: Vec<(InstructionId, Value)> = self
+            .func
+            .get_phis(shape.exit)
+            .into_iter()
+            .filter_map(|phi| Some((phi, self.func.get_incoming(phi, shape.exiting)?)))
+            .collect();
+
+        let next = self.block_after(shape);
+        let mut copies = vec![LoopCopy::default()];
+        for _ in 1..count {
+            copies.push(self.clone_loop(shape, next));
+        }
+        self.chain(shape, &mut copies);
+
+        for (i, copy) in copies.iter().enumerate() {
+            let exiting = map_block(copy, shape.exiting);
+            if i + 1 < count {
+                let target = self.successor_other_than(exiting, shape.exit);
+                self.set_br(exiting, target);
+                continue;
+            }
+
+            self.set_br(exiting, shape.exit);
+            if i > 0 {
+                for &(phi, val) in &exit_incomings {
+                    let val = map_value(copy, val);
+                    Instruction::add_operand(&mut self.func.inst_table, phi, Operand::Value(val));
+                    Instruction::add_operand(
+                        &mut self.func.inst_table,
+                        phi,
+                        Operand::BasicBlock(exiting),
+                    );
+                }
+            }
+        }
+
+        let last = copies.last().unwrap();
+        for (user, id) in outside_uses {
+            let val = self.func.get_inst_value(id);
+            Instruction::replace_operand(
+                &mut self.func.inst_table,
+                user,
+                &Operand::Value(val),
+                Operand::Value(map_value(last, val)),
+            );
+        }
+    }
+
+    // Unrolls the loop `self.factor` times and makes a copy of the original loop, the
+    // remainder loop, run the iterations left when the unrolled loop exits.
+    fn partially_unroll(&mut self, shape: &LoopShape, test: &ExitTest) {
+        let outside_uses = self.outside_uses_of(shape);
+        let header = shape.header;
+
+        let next = self.block_after(shape);
+        let rem = self.clone_loop(shape, next);
+        let rem_header = map_block(&rem, header);
+
+        // The remainder loop starts where the unrolled loop stops.
+        for phi in self.func.get_phis(header) {
+            let val = self.func.get_inst_value(phi);
+            self.replace_incoming(
+                map_value(&rem, val).as_instruction().id,
+                shape.preheader,
+                val,
+                header,
+            );
+        }
+        self.func.basic_blocks.arena[rem_header].pred.insert(header);
+        self.redirect(header, shape.exit, rem_header);
+
+        // and leaves the loop instead of it.
+        self.func.basic_blocks.arena[shape.exit]
+            .pred
+            .insert(rem_header);
+        for phi in self.func.get_phis(shape.exit) {
+            if let Some(val) = self.func.get_incoming(phi, header) {
+                let val = map_value(&rem, val);
+                self.replace_incoming(phi, header, val, rem_header);
+            }
+        }
+        for (user, id) in outside_uses {
+            let val = self.func.get_inst_value(id);
+            Instruction::replace_operand(
+                &mut self.func.inst_table,
+                user,
+                &Operand::Value(val),
+                Operand::Value(map_value(&rem, val)),
+            );
+        }
+
+        let first_rem_block = map_block(&rem, shape.blocks[0]);
+        let mut copies = vec![LoopCopy::default()];
+        for _ in 1..self.factor {
+            copies.push(self.clone_loop(shape, Some(first_rem_block)));
+        }
+        self.chain(shape, &mut copies);
+        for copy in &copies[1..] {
+            let header = map_block(copy, header);
+            let target = self.successor_other_than(header, rem_header);
+            self.set_br(header, target);
+        }
+
+        // The unrolled loop runs while the test passes for the value the induction
+        // variable has in the last copy. The distance is taken from the bound outside
+        // the loop, where it can't wrap unless the unrolled loop never runs at all.
+        let cond = self.func.inst_table[test.cond].clone();
+        let tested = *cond.operands[test.operand].as_value();
+        let bound = *cond.operands[3 - test.operand].as_value();
+        let ty = bound.get_type();
+        let (min, max) = range_of(ty).unwrap();
+        let preheader_br = self.func.get_terminator(shape.preheader).unwrap();
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point_before_inst(preheader_br);
+        let limit = builder.build_sub(bound, Value::new_imm_int(ty, test.distance));
+        let in_range = match bound {
+            Value::Immediate(_) => None,
+            _ if test.distance > 0 => Some(builder.build_icmp(
+                ICmpKind::Ge,
+                bound,
+                Value::new_imm_int(ty, min + test.distance),
+            )),
+            _ => Some(builder.build_icmp(
+                ICmpKind::Le,
+                bound,
+                Value::new_imm_int(ty, max + test.distance),
+            )),
+        };
+
+        let br = self.func.get_terminator(header).unwrap();
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point_before_inst(br);
+        let (lhs, rhs) = match test.operand {
+            1 => (tested, limit),
+            _ => (limit, tested),
+        };
+        let new_cond = builder.build_icmp(*cond.operands[0].as_icmp_kind(), lhs, rhs);
+        let old_cond = self.func.get_inst_value(test.cond);
+        Instruction::replace_operand(
+            &mut self.func.inst_table,
+            br,
+            &Operand::Value(old_cond),
+            Operand::Value(new_cond),
+        );
+        if self.func.inst_table[test.cond].users.borrow().is_empty() {
+            self.func.remove_inst(test.cond);
+        }
+
+        // If the bound is too close to the end of its type, only the remainder loop runs.
+        if let Some(in_range) = in_range {
+            let preheader = shape.preheader;
+            self.func.change_inst(
+                preheader_br,
+                Instruction::new(
+                    Opcode::CondBr,
+                    vec![
+                        Operand::Value(in_range),
+                        Operand::BasicBlock(header),
+                        Operand::BasicBlock(rem_header),
+                    ],
+                    Type::Void,
+                    preheader,
+                ),
+            );
+            self.func.basic_blocks.arena[preheader]
+                .succ
+                .insert(rem_header);
+            self.func.basic_blocks.arena[rem_header]
+                .pred
+                .insert(preheader);
+            for phi in self.func.get_phis(header) {
+                let start = self.func.get_incoming(phi, preheader).unwrap();
+                let remphi = map_value(&rem, self.func.get_inst_value(phi))
+                    .as_instruction()
+                    .id;
+                Instruction::add_operand(&mut self.func.inst_table, remphi, Operand::Value(start));
+                Instruction::add_operand(
+                    &mut self.func.inst_table,
+                    remphi,
+                    Operand::BasicBlock(preheader),
+                );
+            }
+        }
+    }
+
+    // Turns the copies of the loop into a chain, where the latch of each copy branches to
+    // the header of the next copy, and the last one back to the header of the loop. The
+    // phis in the headers of the copies are replaced by the values from the copy before.
+    fn chain(&mut self, shape: &LoopShape, copies: &mut [LoopCopy]) {
+        let count = copies.len();
+        if count == 1 {
+            return;
+        }
+
+        let phis = self.func.get_phis(shape.header);
+        let from_latch: Vec<Value> = phis
+            .iter()
+            .map(|&phi| self.func.get_incoming(phi, shape.latch).unwrap())
+            .collect();
+        for i in 1..count {
+            for (&phi, &val) in phis.iter().zip(from_latch.iter()) {
+                let val = map_value(&copies[i - 1], val);
+                let copied = copies[i].values[&phi].as_instruction().id;
+                Instruction::replace_all_uses(
+                    &mut self.func.inst_table,
+                    copied,
+                    Operand::Value(val),
+                );
+                self.func.remove_inst(copied);
+                copies[i].values.insert(phi, val);
+            }
+        }
+
+        for (i, copy) in copies.iter().enumerate() {
+            let latch = map_block(copy, shape.latch);
+            let next_header = map_block(&copies[(i + 1) % count], shape.header);
+            self.redirect(latch, map_block(copy, shape.header), next_header);
+            self.func.basic_blocks.arena[next_header].pred.insert(latch);
+        }
+
+        let last = &copies[count - 1];
+        let last_latch = map_block(last, shape.latch);
+        for (&phi, &val) in phis.iter().zip(from_latch.iter()) {
+            self.replace_incoming(phi, shape.latch, map_value(last, val), last_latch);
+        }
+    }
+
+    // Copies the blocks of the loop before `before`. Edges leaving the loop are left to
+    // the caller, so the blocks outside the loop don't know the copies as predecessors.
+    fn clone_loop(&mut self, shape: &LoopShape, before: Option<BasicBlockId>) -> LoopCopy {
+        let mut copy = LoopCopy::default();
+
+        for &block in &shape.blocks {
+            let new = match before {
+                Some(before) => self.func.append_basic_block_before(before),
+                None => self.func.append_basic_block(),
+            };
+            if let Some(name) = self.func.names.get_block(block).cloned() {
+                self.func.names.set_block(new, &name);
+            }
+            copy.blocks.insert(block, new);
+        }
+
+        // Instructions are allocated first, since operands may refer to ones that come
+        // later, such as in phis.
+        let mut insts = vec![];
+        for &block in &shape.blocks {
+            let new_block = copy.blocks[&block];
+            let iseq = self.func.basic_blocks.arena[block].iseq_ref().clone();
+            for val in iseq {
+                let old = val.as_instruction().id;
+                let inst = &self.func.inst_table[old];
+                let (opcode, ty) = (inst.opcode, inst.ty);
+                let new = self
+                    .func
+                    .alloc_inst(Instruction::new(opcode, vec![], ty, new_block));
+                if let Some(name) = self.func.names.get_inst(old).cloned() {
+                    self.func.names.set_inst(new, &name);
+                }
+                copy.values.insert(old, self.func.get_inst_value(new));
+                insts.push((old, new, new_block));
+            }
+        }
+
+        for (old, new, new_block) in insts {
+            let operands = self.func.inst_table[old]
+                .operands
+                .iter()
+                .map(|op| match op {
+                    Operand::Value(v) => Operand::Value(map_value(&copy, *v)),
+                    Operand::BasicBlock(b) => Operand::BasicBlock(map_block(&copy, *b)),
+                    op => *op,
+                })
+                .collect();
+            self.func.inst_table[new].operands = operands;
+            self.func.inst_table[new].set_users(&self.func.inst_table);
+            let new = self.func.get_inst_value(new);
+            self.func.basic_blocks.arena[new_block]
+                .iseq_ref_mut()
+                .push(new);
+        }
+
+        for &block in &shape.blocks {
+            let (pred, succ) = {
+                let block = &self.func.basic_blocks.arena[block];
+                let pred: FxHashSet<BasicBlockId> = block
+                    .pred
+                    .iter()
+                    .filter(|b| shape.set.contains(b))
+                    .map(|&b| map_block(&copy, b))
+                    .collect();
+                let succ: FxHashSet<BasicBlockId> =
+                    block.succ.iter().map(|&b| map_block(&copy, b)).collect();
+                (pred, succ)
+            };
+            let new = &mut self.func.basic_blocks.arena[copy.blocks[&block]];
+            new.pred = pred;
+            new.succ = succ;
+        }
+
+        copy
+    }
+
+    // Returns the instructions outside the loop using values defined in it, and the
+    // values they use.
+    fn outside_uses_of(&self, shape: &LoopShape) -> Vec<(InstructionId, InstructionId)> {
+        let mut uses = vec![];
+        for &block in &shape.blocks {
+            for val in &*self.func.basic_blocks.arena[block].iseq_ref() {
+                let id = val.as_instruction().id;
+                for &user in &*self.func.inst_table[id].users.borrow() {
+                    if !shape.set.contains(&self.func.inst_table[user].parent)
+                        && !uses.contains(&(user, id))
+                    {
+                        uses.push((user, id))
+                    }
+                }
+            }
+        }
+        uses
+    }
+
+    // Makes `block` branch only to `target`. The condition of a removed `CondBr` goes
+    // away with it if nothing else uses it.
+    fn set_br(&mut self, block: BasicBlockId, target: BasicBlockId) {
+        let br = self.func.get_terminator(block).unwrap();
+        let cond = match self.func.inst_table[br].opcode {
+            Opcode::CondBr => self.func.inst_table[br].operands[0]
+                .as_value()
+                .get_inst_id(),
+            _ => None,
+        };
+
+        let mut succs: Vec<BasicBlockId> = self.func.basic_blocks.arena[block]
+            .succ
+            .iter()
+            .copied()
+            .filter(|&succ| succ != target)
+            .collect();
+        succs.sort_by_key(|id| id.index());
+        for succ in succs {
+            self.remove_edge(block, succ);
+        }
+
+        self.func.change_inst(
+            br,
+            Instruction::new(
+                Opcode::Br,
+                vec![Operand::BasicBlock(target)],
+                Type::Void,
+                block,
+            ),
+        );
+        if self.func.basic_blocks.arena[block].succ.insert(target) {
+            self.func.basic_blocks.arena[target].pred.insert(block);
+        }
+
+        if let Some(cond) = cond {
+            if self.func.inst_table[cond].users.borrow().is_empty() {
+                self.func.remove_inst(cond);
+            }
+        }
+    }
+
+    // Makes the terminator of `block` branch to `to` instead of `from`. The predecessors
+    // of `to` are left to the caller.
+    fn redirect(&mut self, block: BasicBlockId, from: BasicBlockId, to: BasicBlockId) {
+        let br = self.func.get_terminator(block).unwrap();
+        Instruction::replace_operand(
+            &mut self.func.inst_table,
+            br,
+            &Operand::BasicBlock(from),
+            Operand::BasicBlock(to),
+        );
+        let block_ = &mut self.func.basic_blocks.arena[block];
+        block_.succ.remove(&from);
+        block_.succ.insert(to);
+        self.func.basic_blocks.arena[from].pred.remove(&block);
+    }
+
+    // Removes the edge from `from` to `to` and the incoming values of the phis in `to`
+    // from `from`. The terminator of `from` is left as it is.
+    fn remove_edge(&mut self, from: BasicBlockId, to: BasicBlockId) {
+        self.func.basic_blocks.arena[from].succ.remove(&to);
+        self.func.basic_blocks.arena[to].pred.remove(&from);
+
+        for phi in self.func.get_phis(to) {
+            let inst = &self.func.inst_table[phi];
+            let mut operands: Vec<Operand> = vec![];
+            for pair in inst.operands.chunks(2) {
+                if *pair[1].as_basic_block() != from {
+                    operands.extend(pair.iter().copied())
+                }
+            }
+            if operands.len() == inst.operands.len() {
+                continue;
+            }
+            let inst = Instruction::new(Opcode::Phi, operands, inst.ty, to);
+            self.func.change_inst(phi, inst);
+        }
+    }
+
+    // Replaces the incoming value of `phi` from `from` with `val` from `to`.
+    fn replace_incoming(
+        &mut self,
+        phi: InstructionId,
+        from: BasicBlockId,
+        val: Value,
+        to: BasicBlockId,
+    ) {
+        let inst = &self.func.inst_table[phi];
+        let operands = inst
+            .operands
+            .chunks(2)
+            .flat_map(|pair| match pair[1].as_basic_block() {
+                &block if block == from => vec![Operand::Value(val), Operand::BasicBlock(to)],
+                _ => pair.to_vec(),
+            })
+            .collect();
+        let inst = Instruction::new(Opcode::Phi, operands, inst.ty, inst.parent);
+        self.func.change_inst(phi, inst);
+    }
+
+    fn successor_other_than(&self, block: BasicBlockId, other: BasicBlockId) -> BasicBlockId {
+        let br = self.func.get_terminator(block).unwrap();
+        self.func.inst_table[br].operands[1..]
+            .iter()
+            .map(|op| *op.as_basic_block())
+            .find(|&succ| succ != other)
+            .unwrap()
+    }
+
+    fn block_after(&self, shape: &LoopShape) -> Option<BasicBlockId> {
+        let order = &self.func.basic_blocks.order;
+        let last = order.iter().rposition(|b| shape.set.contains(b)).unwrap();
+        order.get(last + 1).copied()
+    }
+}
+
+fn map_block(copy: &LoopCopy, block: BasicBlockId) -> BasicBlockId {
+    copy.blocks.get(&block).copied().unwrap_or(block)
+}
+
+fn map_value(copy: &LoopCopy, val: Value) -> Value {
+    match val.get_inst_id().and_then(|id| copy.values.get(&id)) {
+        Some(&new) => new,
+        None => val,
+    }
+}
+
+fn range_of(ty: Type) -> Option<(i64, i64)> {
+    match ty {
+        Type::i8 => Some((i8::MIN as i64, i8::MAX as i64)),
+        Type::i32 => Some((i32::MIN as i64, i32::MAX as i64)),
+        Type::i64 => Some((i64::MIN, i64::MAX)),
+        _ => None,
+    }
+}
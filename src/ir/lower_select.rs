@@ -0,0 +1,136 @@
+use crate::ir::{
+    basic_block::BasicBlockId,
+    builder::{IRBuilder, IRBuilderWithFunction},
+    function::Function,
+    module::Module,
+    opcode::{Instruction, InstructionId, Opcode, Operand},
+    value::{InstructionValue, Value},
+};
+
+/// Turns every `Select` back into a branch and a phi, since no backend selects a
+/// conditional move for it yet. Runs right before the conversion into DAG.
+pub struct LowerSelect {}
+
+struct LowerSelectOnFunction<'a> {
+    func: &'a mut Function,
+}
+
+impl LowerSelect {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        for (_, func) in &mut module.functions {
+            if func.is_internal {
+                continue;
+            }
+
+            LowerSelectOnFunction { func }.run()
+        }
+    }
+}
+
+impl<'a> LowerSelectOnFunction<'a> {
+    fn run(&mut self) {
+        while let Some(select) = self.find_select() {
+            self.lower(select)
+        }
+    }
+
+    fn find_select(&self) -> Option<InstructionId> {
+        self.func.basic_blocks.order.iter().find_map(|&block| {
+            self.func.basic_blocks.arena[block]
+                .iseq_ref()
+                .iter()
+                .map(|v| v.as_instruction().id)
+                .find(|&id| self.func.inst_table[id].opcode == Opcode::Select)
+        })
+    }
+
+    // Splits the block of `select` into `block -> then -> after` and `block -> after`,
+    // and turns `select` into a phi at the start of `after`.
+    fn lower(&mut self, select: InstructionId) {
+        let (block, pos) = self.func.find_inst_pos(select).unwrap();
+        let after = self.split_block_after(block, pos);
+        let then = self.func.append_basic_block_before(after);
+        self.func.remove_inst_from_block(select);
+
+        let inst = &self.func.inst_table[select];
+        let (cond, then_val, else_val) = (
+            *inst.operands[0].as_value(),
+            *inst.operands[1].as_value(),
+            *inst.operands[2].as_value(),
+        );
+        let phi = Instruction::new(
+            Opcode::Phi,
+            vec![
+                Operand::Value(then_val),
+                Operand::BasicBlock(then),
+                Operand::Value(else_val),
+                Operand::BasicBlock(block),
+            ],
+            inst.ty,
+            after,
+        );
+        self.func.change_inst(select, phi);
+        let phi = Value::Instruction(InstructionValue {
+            func_id: self.func.id.unwrap(),
+            id: select,
+            ty: self.func.inst_table[select].ty,
+        });
+        self.func.basic_blocks.arena[after]
+            .iseq_ref_mut()
+            .insert(0, phi);
+
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point(block);
+        builder.build_cond_br(cond, then, after);
+        builder.set_insert_point(then);
+        builder.build_br(after);
+    }
+
+    // Moves the instructions after `pos` into a new block, which takes over the
+    // successors of `block`.
+    fn split_block_after(&mut self, block: BasicBlockId, pos: usize) -> BasicBlockId {
+        let order = &self.func.basic_blocks.order;
+        let next = order.get(order.iter().position(|&b| b == block).unwrap() + 1);
+        let after = match next.copied() {
+            Some(next) => self.func.append_basic_block_before(next),
+            None => self.func.append_basic_block(),
+        };
+
+        let moved: Vec<Value> = self.func.basic_blocks.arena[block]
+            .iseq_ref_mut()
+            .drain(pos + 1..)
+            .collect();
+        for val in &moved {
+            self.func.inst_table[val.as_instruction().id].parent = after;
+        }
+        *self.func.basic_blocks.arena[after].iseq_ref_mut() = moved;
+
+        let succs = std::mem::take(&mut self.func.basic_blocks.arena[block].succ);
+        for &succ in &succs {
+            let succ_ = &mut self.func.basic_blocks.arena[succ];
+            succ_.pred.remove(&block);
+            succ_.pred.insert(after);
+
+            let iseq = self.func.basic_blocks.arena[succ].iseq_ref().clone();
+            for val in iseq {
+                let id = val.as_instruction().id;
+                if self.func.inst_table[id].opcode != Opcode::Phi {
+                    break;
+                }
+                Instruction::replace_operand(
+                    &mut self.func.inst_table,
+                    id,
+                    &Operand::BasicBlock(block),
+                    Operand::BasicBlock(after),
+                );
+            }
+        }
+        self.func.basic_blocks.arena[after].succ = succs;
+
+        after
+    }
+}
@@ -1,25 +1,29 @@
 use crate::{
-    analysis::dom_tree::{DominatorTree, DominatorTreeConstructor},
+    analysis::{
+        dom_tree::DominatorTree,
+        manager::{AnalysisManager, PreservedAnalyses},
+    },
     ir::{
         basic_block::{BasicBlock, BasicBlockId},
-        const_folding::ConstantFolding,
+        const_folding::ConstantFoldingOnFunction,
         function::Function,
         module::Module,
         opcode::{Instruction, InstructionId, Opcode, Operand},
         value::{InstructionValue, Value},
     },
-    traits::function::FunctionTrait,
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
 };
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
+use std::sync::Arc;
 
 pub struct Mem2Reg {}
 
 struct Mem2RegOnFunction<'a> {
     cur_func: &'a mut Function,
     inst_indexes: InstructionIndexes,
-    dom_tree: DominatorTree<BasicBlock>,
+    dom_tree: Arc<DominatorTree<BasicBlock>>,
     phi_block_to_allocas: FxHashMap<BasicBlockId, Vec<InstructionId>>,
 }
 
@@ -37,21 +41,44 @@ impl Mem2Reg {
     }
 
     pub fn run_on_module(&mut self, module: &mut Module) {
-        for (_, func) in &mut module.functions {
-            if func.is_internal || func.is_empty() {
-                continue;
-            }
+        run_function_pass_on_module(self, module)
+    }
+}
 
-            Mem2RegOnFunction {
-                dom_tree: DominatorTreeConstructor::new(func.get_basic_blocks()).construct(),
-                cur_func: func,
-                inst_indexes: InstructionIndexes::new(),
-                phi_block_to_allocas: FxHashMap::default(),
-            }
-            .run();
+impl ModulePassTrait for Mem2Reg {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "Mem2Reg"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for Mem2Reg {
+    fn name(&self) -> &'static str {
+        "Mem2Reg"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        Mem2RegOnFunction {
+            dom_tree: am.get_dom_tree(func),
+            cur_func: func,
+            inst_indexes: InstructionIndexes::new(),
+            phi_block_to_allocas: FxHashMap::default(),
         }
+        .run();
+
+        ConstantFoldingOnFunction::new(func).run();
 
-        ConstantFolding::new().run_on_module(module)
+        // Only instructions are inserted and removed.
+        PreservedAnalyses::cfg()
     }
 }
 
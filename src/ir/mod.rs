@@ -5,19 +5,38 @@ pub mod const_folding;
 pub mod constant_pool;
 pub mod cse;
 pub mod dce;
+pub mod dse;
 pub mod function;
+pub mod global_dce;
 pub mod global_val;
+pub mod gvn;
+pub mod inline;
 pub mod inst_combine;
+pub mod ipcp;
+pub mod lcssa;
 pub mod licm;
 pub mod liveness;
+pub mod loop_rotate;
+pub mod loop_strength_reduce;
+pub mod loop_unroll;
+pub mod lower_select;
 pub mod mem2reg;
 pub mod merge_ret;
 pub mod module;
+pub mod names;
 pub mod opcode;
+pub mod pipeline;
+pub mod pre;
 pub mod prelude;
+pub mod reassociate;
+pub mod sccp;
+pub mod simplify_cfg;
 pub mod simplify_loop;
+pub mod sroa;
+pub mod tail_recursion;
 pub mod types;
 pub mod value;
+pub mod verifier;
 
 pub trait DumpToString {
     fn dump(&self, module: &module::Module) -> String;
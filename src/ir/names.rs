@@ -0,0 +1,114 @@
+use super::{basic_block::BasicBlockId, opcode::InstructionId};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Optional source-level names of the values and basic blocks in a function.
+/// Names are kept unique within a function: asking for a name that is already taken
+/// gives a suffixed one (`x`, `x.1`, `x.2`, ...).
+#[derive(Debug, Clone)]
+pub struct Names {
+    insts: FxHashMap<InstructionId, String>,
+    params: FxHashMap<usize, String>,
+    blocks: FxHashMap<BasicBlockId, String>,
+    used: FxHashSet<String>,
+}
+
+impl Names {
+    /// Creates an empty name table.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cilk::ir::names::Names;
+    /// let names = Names::new();
+    /// assert!(names.get_param(0).is_none());
+    /// ```
+    pub fn new() -> Self {
+        Self {
+            insts: FxHashMap::default(),
+            params: FxHashMap::default(),
+            blocks: FxHashMap::default(),
+            used: FxHashSet::default(),
+        }
+    }
+
+    pub fn get_inst(&self, id: InstructionId) -> Option<&String> {
+        self.insts.get(&id)
+    }
+
+    pub fn get_param(&self, idx: usize) -> Option<&String> {
+        self.params.get(&idx)
+    }
+
+    pub fn get_block(&self, id: BasicBlockId) -> Option<&String> {
+        self.blocks.get(&id)
+    }
+
+    /// Names an instruction and returns the name actually given.
+    pub fn set_inst(&mut self, id: InstructionId, name: &str) -> Option<String> {
+        let old = self.insts.remove(&id);
+        let name = self.unique(old, name)?;
+        self.insts.insert(id, name.clone());
+        Some(name)
+    }
+
+    /// Names the `idx`-th parameter and returns the name actually given.
+    pub fn set_param(&mut self, idx: usize, name: &str) -> Option<String> {
+        let old = self.params.remove(&idx);
+        let name = self.unique(old, name)?;
+        self.params.insert(idx, name.clone());
+        Some(name)
+    }
+
+    /// Forgets the name of the `idx`-th parameter, which may then be given to another
+    /// value, and returns it.
+    pub fn remove_param(&mut self, idx: usize) -> Option<String> {
+        let name = self.params.remove(&idx)?;
+        self.used.remove(&name);
+        Some(name)
+    }
+
+    /// Names a basic block and returns the name actually given.
+    pub fn set_block(&mut self, id: BasicBlockId, name: &str) -> Option<String> {
+        let old = self.blocks.remove(&id);
+        let name = self.unique(old, name)?;
+        self.blocks.insert(id, name.clone());
+        Some(name)
+    }
+
+    fn unique(&mut self, old: Option<String>, name: &str) -> Option<String> {
+        if let Some(old) = old {
+            self.used.remove(&old);
+        }
+
+        let base = Self::sanitize(name)?;
+        let mut name = base.clone();
+        let mut n = 0;
+        while !self.used.insert(name.clone()) {
+            n += 1;
+            name = format!("{}.{}", base, n);
+        }
+        Some(name)
+    }
+
+    // Unnamed values are printed as '%<index>', '%arg.<index>' and 'label.<index>',
+    // so names that could be confused with them get prefixed with '_'.
+    fn sanitize(name: &str) -> Option<String> {
+        if name.is_empty() {
+            return None;
+        }
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let reserved = name.starts_with(|c: char| c.is_ascii_digit())
+            || name.starts_with("arg.")
+            || name.starts_with("label.");
+        Some(if reserved { format!("_{}", name) } else { name })
+    }
+}
@@ -1,4 +1,10 @@
-use super::{basic_block::BasicBlockId, function::FunctionId, module::Module, types::*, value::*};
+use super::{
+    basic_block::BasicBlockId,
+    function::{Function, FunctionId},
+    module::Module,
+    types::*,
+    value::*,
+};
 use id_arena::{Arena, Id};
 use std::cell::RefCell;
 
@@ -38,6 +44,7 @@ pub enum Opcode {
     Br,
     CondBr,
     Phi,
+    Select, // cond, then val, else val
     Call,
     Ret,
 }
@@ -106,7 +113,10 @@ impl Instruction {
     }
 
     pub fn set_user(&self, inst_arena: &Arena<Instruction>, new: InstructionId) {
-        inst_arena[self.id.unwrap()].users.borrow_mut().push(new);
+        let mut users = inst_arena[self.id.unwrap()].users.borrow_mut();
+        if !users.contains(&new) {
+            users.push(new);
+        }
     }
 
     pub fn add_operand(arena: &mut Arena<Instruction>, self_id: InstructionId, operand: Operand) {
@@ -192,13 +202,8 @@ impl Instruction {
     }
 
     pub fn fold_const(&self) -> Option<Value> {
-        let operands = &self.operands;
-        match self.opcode {
-            Opcode::Add => operands[0].as_value().const_add(&operands[1].as_value()),
-            Opcode::Sub => operands[0].as_value().const_sub(&operands[1].as_value()),
-            Opcode::Mul => operands[0].as_value().const_mul(&operands[1].as_value()),
-            Opcode::Div => operands[0].as_value().const_div(&operands[1].as_value()),
-            Opcode::Rem => operands[0].as_value().const_rem(&operands[1].as_value()),
+        match &self.operands[..] {
+            [Operand::Value(x), Operand::Value(y)] => x.const_binary(self.opcode, y),
             _ => None,
         }
     }
@@ -207,14 +212,14 @@ impl Instruction {
         self.users.borrow().len() == 1
     }
 
-    pub fn to_string(&self, parent: &Module) -> String {
+    pub fn to_string(&self, parent: &Module, func: &Function) -> String {
         let mut output = self.opcode.to_string().to_owned();
         for (i, operand) in self.operands.iter().enumerate() {
             output = format!(
                 "{}{}{}",
                 output,
                 if i == 0 { " " } else { ", " },
-                operand.to_string(parent)
+                operand.to_string(parent, func)
             );
         }
 
@@ -264,6 +269,7 @@ impl Opcode {
             Opcode::Br => "br",
             Opcode::CondBr => "br",
             Opcode::Phi => "phi",
+            Opcode::Select => "select",
             Opcode::Call => "call",
             Opcode::Ret => "ret",
         }
@@ -276,9 +282,9 @@ impl Operand {
     }
 
     // TODO: should return cow?
-    pub fn to_string(&self, parent: &Module) -> String {
+    pub fn to_string(&self, parent: &Module, func: &Function) -> String {
         match self {
-            Self::BasicBlock(id) => format!("%label.{}", id.index()),
+            Self::BasicBlock(id) => format!("%{}", func.block_label(*id)),
             Self::ICmpKind(kind) => kind.as_str().to_owned(),
             Self::FCmpKind(kind) => kind.as_str().to_owned(),
             Self::Type(ty) => parent.types.to_string(*ty),
@@ -351,6 +357,29 @@ impl ICmpKind {
             ICmpKind::Ge => "ge",
         }
     }
+
+    /// `a kind b` is `b kind.swapped() a`.
+    pub fn swapped(self) -> Self {
+        match self {
+            ICmpKind::Lt => ICmpKind::Gt,
+            ICmpKind::Le => ICmpKind::Ge,
+            ICmpKind::Gt => ICmpKind::Lt,
+            ICmpKind::Ge => ICmpKind::Le,
+            kind => kind,
+        }
+    }
+
+    /// `!(a kind b)` is `a kind.inverted() b`.
+    pub fn inverted(self) -> Self {
+        match self {
+            ICmpKind::Eq => ICmpKind::Ne,
+            ICmpKind::Ne => ICmpKind::Eq,
+            ICmpKind::Lt => ICmpKind::Ge,
+            ICmpKind::Le => ICmpKind::Gt,
+            ICmpKind::Gt => ICmpKind::Le,
+            ICmpKind::Ge => ICmpKind::Lt,
+        }
+    }
 }
 
 impl FCmpKind {
@@ -0,0 +1,106 @@
+use crate::{
+    ir::{
+        const_folding::ConstantFolding, cse::CommonSubexprElimination, dce::DeadCodeElimination,
+        dse::DeadStoreElimination, gvn::GlobalValueNumbering, inst_combine::InstructionCombine,
+        lcssa::LoopClosedSSA, licm::LoopInvariantCodeMotion, loop_rotate::LoopRotate,
+        loop_strength_reduce::LoopStrengthReduction, loop_unroll::LoopUnroll, mem2reg::Mem2Reg,
+        pre::PartialRedundancyElimination, reassociate::Reassociate,
+        sccp::SparseConditionalConstantPropagation, simplify_cfg::SimplifyCFG,
+        sroa::ScalarReplacementOfAggregates, tail_recursion::TailRecursionElimination,
+    },
+    traits::pass::{FunctionPassManager, FunctionPassTrait},
+};
+
+/// Names accepted in a textual pipeline description.
+pub const PASS_NAMES: &[&str] = &[
+    "mem2reg",
+    "cse",
+    "licm",
+    "dce",
+    "constfold",
+    "instcombine",
+    "sccp",
+    "gvn",
+    "tailrec",
+    "sroa",
+    "dse",
+    "simplifycfg",
+    "unroll",
+    "lcssa",
+    "rotate",
+    "lsr",
+    "reassociate",
+    "pre",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineError {
+    UnknownPass(String),
+    EmptyPassName,
+    UnsupportedPass(String),
+}
+
+/// Parses a comma-separated list of pass names such as `"mem2reg,cse,licm,dce"`.
+///
+/// # Examples
+///
+/// ```
+/// use cilk::ir::pipeline::{parse_pipeline, PipelineError};
+/// assert_eq!(parse_pipeline("mem2reg, dce").unwrap(), vec!["mem2reg", "dce"]);
+/// assert!(parse_pipeline("").unwrap().is_empty());
+/// assert_eq!(
+///     parse_pipeline("mem2reg,vectorize"),
+///     Err(PipelineError::UnknownPass("vectorize".to_string()))
+/// );
+/// ```
+pub fn parse_pipeline(desc: &str) -> Result<Vec<String>, PipelineError> {
+    if desc.trim().is_empty() {
+        return Ok(vec![]);
+    }
+
+    desc.split(',')
+        .map(|name| {
+            let name = name.trim();
+            if name.is_empty() {
+                Err(PipelineError::EmptyPassName)
+            } else if !PASS_NAMES.contains(&name) {
+                Err(PipelineError::UnknownPass(name.to_string()))
+            } else {
+                Ok(name.to_string())
+            }
+        })
+        .collect()
+}
+
+/// Builds a pass manager running `names` in order. Every name must be in `PASS_NAMES`.
+pub fn build_pipeline<S: AsRef<str>>(names: &[S]) -> FunctionPassManager {
+    let mut pass_mgr = FunctionPassManager::new();
+    for name in names {
+        pass_mgr.list.push(create_pass(name.as_ref()));
+    }
+    pass_mgr
+}
+
+fn create_pass(name: &str) -> Box<dyn FunctionPassTrait> {
+    match name {
+        "mem2reg" => Box::new(Mem2Reg::new()),
+        "cse" => Box::new(CommonSubexprElimination::new()),
+        "licm" => Box::new(LoopInvariantCodeMotion::new()),
+        "dce" => Box::new(DeadCodeElimination::new()),
+        "constfold" => Box::new(ConstantFolding::new()),
+        "instcombine" => Box::new(InstructionCombine::new()),
+        "sccp" => Box::new(SparseConditionalConstantPropagation::new()),
+        "gvn" => Box::new(GlobalValueNumbering::new()),
+        "tailrec" => Box::new(TailRecursionElimination::new()),
+        "sroa" => Box::new(ScalarReplacementOfAggregates::new()),
+        "dse" => Box::new(DeadStoreElimination::new()),
+        "simplifycfg" => Box::new(SimplifyCFG::new()),
+        "unroll" => Box::new(LoopUnroll::new()),
+        "lcssa" => Box::new(LoopClosedSSA::new()),
+        "rotate" => Box::new(LoopRotate::new()),
+        "lsr" => Box::new(LoopStrengthReduction::new()),
+        "reassociate" => Box::new(Reassociate::new()),
+        "pre" => Box::new(PartialRedundancyElimination::new()),
+        _ => panic!("unknown pass '{}'", name),
+    }
+}
@@ -0,0 +1,512 @@
+use crate::{
+    analysis::manager::{AnalysisManager, PreservedAnalyses},
+    ir::{
+        basic_block::BasicBlockId,
+        function::Function,
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        types::Type,
+        value::Value,
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Partial redundancy elimination by lazy code motion. An expression computed on some
+/// paths to a computation of it is also computed on the edges where it is missing, so
+/// the later computation is fully redundant and replaced by a phi:
+///
+/// ```text
+///        entry                         entry
+///       /     \                       /     \
+///  %a = x+y    |          =>     %a = x+y   %b = x+y
+///       \     /                       \     /
+///      %c = x+y                   %c = phi %a, %b
+/// ```
+///
+/// Computations are inserted as late as possible where the expression is anticipated,
+/// so no path computes it more often than before, nor where it didn't compute it at
+/// all, and a `Div` can't trap on a new path. Critical edges are split to insert on
+/// them. Expressions are the operations `CommonSubexprElimination` merges, with the
+/// same operands.
+///
+/// Nothing is moved in a function whose entry block has predecessors, since a
+/// computation can't be inserted on the edge into the function then.
+pub struct PartialRedundancyElimination {}
+
+struct PartialRedundancyEliminationOnFunction<'a> {
+    func: &'a mut Function,
+    split_edges: bool,
+
+    /// The blocks reachable from the entry block
+    reachable: FxHashSet<BasicBlockId>,
+}
+
+/// An operation and the computations of it
+struct Expression {
+    opcode: Opcode,
+    ty: Type,
+    operands: Vec<Operand>,
+
+    /// Computations by block, in order
+    occurrences: FxHashMap<BasicBlockId, Vec<InstructionId>>,
+}
+
+/// Where lazy code motion computes an expression
+struct Placement {
+    /// Edges to insert a computation on
+    inserts: Vec<(BasicBlockId, BasicBlockId)>,
+
+    /// Blocks whose first computation becomes redundant
+    deletes: FxHashSet<BasicBlockId>,
+}
+
+impl PartialRedundancyElimination {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for PartialRedundancyElimination {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "PartialRedundancyElimination"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for PartialRedundancyElimination {
+    fn name(&self) -> &'static str {
+        "PartialRedundancyElimination"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        _am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let mut pre = PartialRedundancyEliminationOnFunction {
+            func,
+            split_edges: false,
+            reachable: FxHashSet::default(),
+        };
+        let changed = pre.run();
+        if pre.split_edges {
+            PreservedAnalyses::none()
+        } else if changed {
+            PreservedAnalyses::cfg()
+        } else {
+            PreservedAnalyses::all()
+        }
+    }
+}
+
+impl<'a> PartialRedundancyEliminationOnFunction<'a> {
+    /// Returns true if any computation is moved or removed.
+    fn run(&mut self) -> bool {
+        let entry = match self.func.basic_blocks.order.first() {
+            Some(&entry) if self.func.basic_blocks.arena[entry].pred.is_empty() => entry,
+            _ => return false,
+        };
+        self.reachable = self.reachable_from(entry);
+
+        let mut changed = false;
+
+        loop {
+            // Removing a computation changes the operands of the expressions using it,
+            // which are collected again in the next round.
+            let mut removed = FxHashSet::default();
+            let mut stale = false;
+            for expr in self.collect_expressions() {
+                let is_stale = expr.operands.iter().any(|op| match op {
+                    Operand::Value(Value::Instruction(iv)) => removed.contains(&iv.id),
+                    _ => false,
+                });
+                if is_stale {
+                    stale = true;
+                    continue;
+                }
+                changed |= self.eliminate(&expr, &mut removed);
+            }
+            if !stale {
+                break;
+            }
+        }
+
+        changed
+    }
+
+    // Returns the expressions computed more than once.
+    fn collect_expressions(&self) -> Vec<Expression> {
+        let mut exprs: Vec<Expression> = vec![];
+        let mut indices: FxHashMap<(Opcode, Type, Vec<Operand>), usize> = FxHashMap::default();
+
+        for &block in &self.func.basic_blocks.order {
+            for val in &*self.func.basic_blocks.arena[block].iseq_ref() {
+                let id = val.as_instruction().id;
+                let inst = &self.func.inst_table[id];
+                if !is_movable(inst.opcode) {
+                    continue;
+                }
+
+                let key = (inst.opcode, inst.ty, inst.operands.clone());
+                let i = *indices.entry(key).or_insert_with(|| {
+                    exprs.push(Expression {
+                        opcode: inst.opcode,
+                        ty: inst.ty,
+                        operands: inst.operands.clone(),
+                        occurrences: FxHashMap::default(),
+                    });
+                    exprs.len() - 1
+                });
+                exprs[i].occurrences.entry(block).or_default().push(id);
+            }
+        }
+
+        exprs.retain(|expr| {
+            expr.occurrences.len() > 1 || expr.occurrences.values().any(|ids| ids.len() > 1)
+        });
+        exprs
+    }
+
+    // Moves the computations of `expr` where lazy code motion places them. Returns true
+    // if anything changes.
+    fn eliminate(&mut self, expr: &Expression, removed: &mut FxHashSet<InstructionId>) -> bool {
+        let placement = self.place(expr);
+        let has_local_redundancy = expr.occurrences.values().any(|ids| ids.len() > 1);
+        if placement.inserts.is_empty() && placement.deletes.is_empty() && !has_local_redundancy {
+            return false;
+        }
+
+        // The value of the expression at the end of the blocks computing it
+        let mut values_out = FxHashMap::default();
+        for &(from, to) in &placement.inserts {
+            let block = if self.func.basic_blocks.arena[from].succ.len() == 1 {
+                from
+            } else {
+                self.split_edge(from, to)
+            };
+            let id = self.func.alloc_inst(Instruction::new(
+                expr.opcode,
+                expr.operands.clone(),
+                expr.ty,
+                block,
+            ));
+            let val = self.func.get_inst_value(id);
+            let pos = self.terminator_pos(block);
+            self.func.basic_blocks.arena[block]
+                .iseq_ref_mut()
+                .insert(pos, val);
+            values_out.insert(block, val);
+        }
+        for (&block, ids) in &expr.occurrences {
+            if !placement.deletes.contains(&block) {
+                values_out.insert(block, self.func.get_inst_value(ids[0]));
+            }
+        }
+
+        let mut values_in = FxHashMap::default();
+        for block in sorted(expr.occurrences.keys().copied()) {
+            let ids = &expr.occurrences[&block];
+            let (val, redundant) = if placement.deletes.contains(&block) {
+                let val = self.value_in(block, expr.ty, &values_out, &mut values_in);
+                (val, &ids[..])
+            } else {
+                (self.func.get_inst_value(ids[0]), &ids[1..])
+            };
+            for &id in redundant {
+                Instruction::replace_all_uses(&mut self.func.inst_table, id, Operand::Value(val));
+                self.func.remove_inst(id);
+                removed.insert(id);
+            }
+        }
+
+        true
+    }
+
+    // Solves the dataflow equations of lazy code motion for `expr`.
+    fn place(&self, expr: &Expression) -> Placement {
+        let blocks = &self.func.basic_blocks.order;
+        let arena = &self.func.basic_blocks.arena;
+        let entry = blocks[0];
+
+        // An expression is transparent in a block not defining its operands, and
+        // locally anticipated if it is computed there as well.
+        let transp: FxHashSet<BasicBlockId> = blocks
+            .iter()
+            .copied()
+            .filter(|&block| {
+                !expr.operands.iter().any(|op| match op {
+                    Operand::Value(Value::Instruction(iv)) => {
+                        self.func.inst_table[iv.id].parent == block
+                    }
+                    _ => false,
+                })
+            })
+            .collect();
+        let comp = |block: BasicBlockId| expr.occurrences.contains_key(&block);
+        let antloc = |block: BasicBlockId| comp(block) && transp.contains(&block);
+
+        // Available at the end of a block on every path to it
+        let mut avout: FxHashSet<BasicBlockId> = blocks.iter().copied().collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in blocks {
+                let preds = &arena[block].pred;
+                let avin = block != entry
+                    && !preds.is_empty()
+                    && preds.iter().all(|pred| avout.contains(pred));
+                if !(comp(block) || (avin && transp.contains(&block))) {
+                    changed |= avout.remove(&block);
+                }
+            }
+        }
+
+        // Computed on every path from the start of a block before its operands change
+        let mut antin: FxHashSet<BasicBlockId> = blocks.iter().copied().collect();
+        let antout = |antin: &FxHashSet<BasicBlockId>, block: BasicBlockId| {
+            let succs = &arena[block].succ;
+            !succs.is_empty() && succs.iter().all(|succ| antin.contains(succ))
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in blocks.iter().rev() {
+                if !(antloc(block) || (antout(&antin, block) && transp.contains(&block))) {
+                    changed |= antin.remove(&block);
+                }
+            }
+        }
+
+        // The earliest edges to compute the expression on, from which the computation
+        // is delayed while that shortens no path.
+        let earliest = |from: BasicBlockId, to: BasicBlockId| {
+            antin.contains(&to)
+                && !avout.contains(&from)
+                && (!transp.contains(&from) || !antout(&antin, from))
+        };
+        let later = |laterin: &FxHashSet<BasicBlockId>, from: BasicBlockId, to: BasicBlockId| {
+            earliest(from, to) || (laterin.contains(&from) && !antloc(from))
+        };
+        let mut laterin: FxHashSet<BasicBlockId> = blocks.iter().copied().collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in blocks {
+                let preds = &arena[block].pred;
+                let mut is_later = preds.iter().all(|&pred| later(&laterin, pred, block));
+                if block == entry {
+                    // The edge into the function is the earliest if it is anticipated.
+                    is_later &= antin.contains(&entry)
+                } else if preds.is_empty() {
+                    is_later = false
+                }
+                if !is_later {
+                    changed |= laterin.remove(&block);
+                }
+            }
+        }
+
+        let mut inserts = vec![];
+        for &block in blocks {
+            if laterin.contains(&block) {
+                continue;
+            }
+            for pred in sorted(arena[block].pred.iter().copied()) {
+                if later(&laterin, pred, block) {
+                    inserts.push((pred, block))
+                }
+            }
+        }
+        // A block that never runs has no value of the expression to start with.
+        let deletes = blocks
+            .iter()
+            .copied()
+            .filter(|&block| {
+                antloc(block) && !laterin.contains(&block) && self.reachable.contains(&block)
+            })
+            .collect();
+
+        Placement { inserts, deletes }
+    }
+
+    // Returns the value of the expression at the start of `block`, inserting phis where
+    // values from different predecessors meet.
+    fn value_in(
+        &mut self,
+        block: BasicBlockId,
+        ty: Type,
+        values_out: &FxHashMap<BasicBlockId, Value>,
+        values_in: &mut FxHashMap<BasicBlockId, Value>,
+    ) -> Value {
+        if let Some(&val) = values_in.get(&block) {
+            return val;
+        }
+
+        let preds = sorted(self.func.basic_blocks.arena[block].pred.iter().copied());
+        if preds.len() == 1 {
+            let val = self.value_out(preds[0], ty, values_out, values_in);
+            values_in.insert(block, val);
+            return val;
+        }
+
+        // The phi is recorded first, since a loop leads back to it.
+        let phi = self
+            .func
+            .alloc_inst(Instruction::new(Opcode::Phi, vec![], ty, block));
+        let phi_val = self.func.get_inst_value(phi);
+        self.func.basic_blocks.arena[block]
+            .iseq_ref_mut()
+            .insert(0, phi_val);
+        values_in.insert(block, phi_val);
+
+        // The value from a predecessor that never runs doesn't matter.
+        let mut incomings = vec![];
+        for pred in preds {
+            let val = if self.reachable.contains(&pred) {
+                self.value_out(pred, ty, values_out, values_in)
+            } else {
+                Value::null(ty)
+            };
+            Instruction::add_operand(&mut self.func.inst_table, phi, Operand::Value(val));
+            Instruction::add_operand(&mut self.func.inst_table, phi, Operand::BasicBlock(pred));
+            if val != phi_val && self.reachable.contains(&pred) {
+                incomings.push(val)
+            }
+        }
+
+        // A phi merging one value is that value.
+        match incomings.first() {
+            Some(&val) if incomings.iter().all(|&v| v == val) => {
+                Instruction::replace_all_uses(&mut self.func.inst_table, phi, Operand::Value(val));
+                self.func.remove_inst(phi);
+                for v in values_in.values_mut().filter(|v| **v == phi_val) {
+                    *v = val
+                }
+                val
+            }
+            _ => phi_val,
+        }
+    }
+
+    fn value_out(
+        &mut self,
+        block: BasicBlockId,
+        ty: Type,
+        values_out: &FxHashMap<BasicBlockId, Value>,
+        values_in: &mut FxHashMap<BasicBlockId, Value>,
+    ) -> Value {
+        match values_out.get(&block) {
+            Some(&val) => val,
+            None => self.value_in(block, ty, values_out, values_in),
+        }
+    }
+
+    // Inserts an empty block on the edge from `from` to `to`.
+    fn split_edge(&mut self, from: BasicBlockId, to: BasicBlockId) -> BasicBlockId {
+        self.split_edges = true;
+        let block = self.func.append_basic_block_before(to);
+
+        let pos = self.terminator_pos(from);
+        let terminators: Vec<InstructionId> = self.func.basic_blocks.arena[from].iseq_ref()[pos..]
+            .iter()
+            .map(|v| v.as_instruction().id)
+            .collect();
+        for id in terminators {
+            Instruction::replace_operand(
+                &mut self.func.inst_table,
+                id,
+                &Operand::BasicBlock(to),
+                Operand::BasicBlock(block),
+            );
+        }
+        for val in self.func.basic_blocks.arena[to].iseq_ref().clone() {
+            let id = val.as_instruction().id;
+            if self.func.inst_table[id].opcode != Opcode::Phi {
+                break;
+            }
+            Instruction::replace_operand(
+                &mut self.func.inst_table,
+                id,
+                &Operand::BasicBlock(from),
+                Operand::BasicBlock(block),
+            );
+        }
+
+        let br = self.func.alloc_inst(Instruction::new(
+            Opcode::Br,
+            vec![Operand::BasicBlock(to)],
+            Type::Void,
+            block,
+        ));
+        let br = self.func.get_inst_value(br);
+        self.func.basic_blocks.arena[block].iseq_ref_mut().push(br);
+
+        let arena = &mut self.func.basic_blocks.arena;
+        arena[from].succ.remove(&to);
+        arena[from].succ.insert(block);
+        arena[to].pred.remove(&from);
+        arena[to].pred.insert(block);
+        arena[block].pred.insert(from);
+        arena[block].succ.insert(to);
+        if self.reachable.contains(&from) {
+            self.reachable.insert(block);
+        }
+        block
+    }
+
+    fn reachable_from(&self, entry: BasicBlockId) -> FxHashSet<BasicBlockId> {
+        let mut reachable = FxHashSet::default();
+        let mut worklist = vec![entry];
+        while let Some(block) = worklist.pop() {
+            if reachable.insert(block) {
+                worklist.extend(self.func.basic_blocks.arena[block].succ.iter().copied());
+            }
+        }
+        reachable
+    }
+
+    fn terminator_pos(&self, block: BasicBlockId) -> usize {
+        let iseq = self.func.basic_blocks.arena[block].iseq_ref();
+        let terminators = iseq
+            .iter()
+            .rev()
+            .take_while(|v| {
+                self.func.inst_table[v.as_instruction().id]
+                    .opcode
+                    .is_terminator()
+            })
+            .count();
+        iseq.len() - terminators
+    }
+}
+
+fn is_movable(opcode: Opcode) -> bool {
+    matches!(
+        opcode,
+        Opcode::Add
+            | Opcode::Sub
+            | Opcode::Mul
+            | Opcode::Div
+            | Opcode::Rem
+            | Opcode::Shl
+            | Opcode::SIToFP
+            | Opcode::FPToSI
+            | Opcode::Sext
+    )
+}
+
+fn sorted(blocks: impl Iterator<Item = BasicBlockId>) -> Vec<BasicBlockId> {
+    let mut blocks: Vec<BasicBlockId> = blocks.collect();
+    blocks.sort_by_key(|id| id.index());
+    blocks
+}
@@ -0,0 +1,323 @@
+use crate::{
+    analysis::manager::{AnalysisManager, PreservedAnalyses},
+    ir::{
+        builder::{IRBuilder, IRBuilderWithFunction},
+        function::Function,
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        types::Type,
+        value::{ImmediateValue, Value},
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+use rustc_hash::FxHashMap;
+
+/// Reorders trees of `Add`s or `Mul`s by the rank of their operands. Constants get the
+/// lowest rank and are folded into one, applied last; arguments come next, then values
+/// by the block defining them. Rewritten trees combine the lowest ranked operands
+/// first, so the same values get combined the same way everywhere and
+/// `CommonSubexprElimination` and `GlobalValueNumbering` find more to share:
+///
+/// ```text
+/// %t = add 1, %x          =>    %0 = add %x, %y
+/// %u = add %y, 2                %r = add %0, 3
+/// %r = add %t, %u
+/// ```
+///
+/// A `Sub` of a constant in an `Add` tree is an `Add` of its negation. Floating-point
+/// trees are rewritten only if `fast_math` is set, since that changes their rounding.
+/// The IR has no bitwise operations yet, which would be reassociated the same way.
+pub struct Reassociate {
+    pub fast_math: bool,
+}
+
+struct ReassociateOnFunction<'a> {
+    func: &'a mut Function,
+    fast_math: bool,
+    ranks: FxHashMap<InstructionId, usize>,
+}
+
+impl Reassociate {
+    pub fn new() -> Self {
+        Self { fast_math: false }
+    }
+
+    pub fn with_fast_math(mut self, fast_math: bool) -> Self {
+        self.fast_math = fast_math;
+        self
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for Reassociate {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "Reassociate"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for Reassociate {
+    fn name(&self) -> &'static str {
+        "Reassociate"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        _am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let changed = ReassociateOnFunction {
+            func,
+            fast_math: self.fast_math,
+            ranks: FxHashMap::default(),
+        }
+        .run();
+        if changed {
+            PreservedAnalyses::cfg()
+        } else {
+            PreservedAnalyses::all()
+        }
+    }
+}
+
+impl<'a> ReassociateOnFunction<'a> {
+    /// Returns true if any tree is rewritten.
+    fn run(&mut self) -> bool {
+        // The roots of trees, which aren't used only by a larger tree. Inner trees come
+        // first and may then become leaves of the ones around them.
+        let mut roots = vec![];
+        for &block in &self.func.basic_blocks.order {
+            for val in &*self.func.basic_blocks.arena[block].iseq_ref() {
+                let id = val.as_instruction().id;
+                let inst = &self.func.inst_table[id];
+                if !self.is_reassociable(inst) {
+                    continue;
+                }
+                let users = inst.users.borrow();
+                let in_tree = users.len() == 1 && {
+                    let user = &self.func.inst_table[users[0]];
+                    user.opcode == inst.opcode && user.parent == inst.parent
+                };
+                if !in_tree {
+                    roots.push(id)
+                }
+            }
+        }
+
+        let mut changed = false;
+        for root in roots {
+            changed |= self.reassociate(root);
+        }
+        changed
+    }
+
+    fn reassociate(&mut self, root: InstructionId) -> bool {
+        let opcode = self.func.inst_table[root].opcode;
+        let ty = self.func.inst_table[root].ty;
+        let mut nodes = vec![];
+        let mut leaves = vec![];
+        self.linearize(root, root, &mut nodes, &mut leaves);
+
+        // Variables are combined from the lowest rank, and constants folded at the end.
+        let (mut consts, mut vars): (Vec<Value>, Vec<Value>) = leaves
+            .into_iter()
+            .partition(|val| matches!(val, Value::Immediate(_)));
+        // Values of the same rank are ordered by where they are created, to combine them
+        // the same way in every tree.
+        vars.sort_by_cached_key(|val| {
+            let order = val.get_inst_id().map(|id| id.index());
+            (self.rank_of(val), order)
+        });
+        let konst = consts.pop().map(|first| {
+            consts
+                .into_iter()
+                .fold(first, |acc, val| fold(opcode, ty, &acc, &val))
+        });
+        match konst {
+            Some(konst) if opcode == Opcode::Mul && konst.as_i64() == Some(0) => vars.clear(),
+            Some(konst) if !is_identity(opcode, &konst) || vars.is_empty() => vars.push(konst),
+            _ => {}
+        }
+
+        if self.is_chain(root, &vars) {
+            return false;
+        }
+
+        let name = self
+            .func
+            .names
+            .get_inst(root)
+            .cloned()
+            .filter(|_| vars.len() > 1);
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point_before_inst(root).unwrap();
+        let mut vars = vars.into_iter();
+        let first = vars.next().unwrap_or_else(|| Value::null(ty));
+        let new = vars.fold(first, |acc, val| match opcode {
+            Opcode::Add => builder.build_add(acc, val),
+            _ => builder.build_mul(acc, val),
+        });
+        if let Some(name) = name {
+            builder.set_value_name(new, &name);
+        }
+
+        Instruction::replace_all_uses(&mut self.func.inst_table, root, Operand::Value(new));
+        self.func.remove_inst(root);
+        for node in nodes {
+            self.func.remove_inst(node)
+        }
+        true
+    }
+
+    // Collects the leaves of the tree at `id`, and the nodes in it except `root`.
+    fn linearize(
+        &self,
+        root: InstructionId,
+        id: InstructionId,
+        nodes: &mut Vec<InstructionId>,
+        leaves: &mut Vec<Value>,
+    ) {
+        let inst = &self.func.inst_table[id];
+        if id != root {
+            nodes.push(id)
+        }
+
+        let negate = inst.opcode == Opcode::Sub;
+        for (i, op) in inst.operands.iter().enumerate() {
+            let val = *op.as_value();
+            if negate && i == 1 {
+                let negated = val.as_i64().unwrap().wrapping_neg();
+                leaves.push(Value::new_imm_int(inst.ty, negated));
+                continue;
+            }
+            match val {
+                Value::Instruction(iv) if self.is_node_of(root, iv.id) => {
+                    self.linearize(root, iv.id, nodes, leaves)
+                }
+                val => leaves.push(val),
+            }
+        }
+    }
+
+    // Returns true if `id` is used only by the tree at `root` and may be merged into it.
+    fn is_node_of(&self, root: InstructionId, id: InstructionId) -> bool {
+        let root = &self.func.inst_table[root];
+        let inst = &self.func.inst_table[id];
+        if !inst.has_one_use() || inst.parent != root.parent || inst.ty != root.ty {
+            return false;
+        }
+        match inst.opcode {
+            opcode if opcode == root.opcode => self.is_reassociable(inst),
+            Opcode::Sub => {
+                root.opcode == Opcode::Add
+                    && is_integer(inst.ty)
+                    && inst.operands[1].as_value().as_i64().is_some()
+            }
+            _ => false,
+        }
+    }
+
+    // Returns true if the tree at `root` already combines `vars` in order.
+    fn is_chain(&self, root: InstructionId, vars: &[Value]) -> bool {
+        let opcode = self.func.inst_table[root].opcode;
+        let mut id = root;
+        for i in (1..vars.len()).rev() {
+            let inst = &self.func.inst_table[id];
+            if inst.opcode != opcode || *inst.operands[1].as_value() != vars[i] {
+                return false;
+            }
+            let lhs = *inst.operands[0].as_value();
+            if i == 1 {
+                return lhs == vars[0];
+            }
+            match lhs {
+                Value::Instruction(iv) if self.is_node_of(root, iv.id) => id = iv.id,
+                _ => return false,
+            }
+        }
+        false
+    }
+
+    fn rank_of(&mut self, val: &Value) -> usize {
+        let id = match val {
+            Value::Immediate(_) => return 0,
+            Value::Argument(arg) => return arg.index + 1,
+            Value::Instruction(iv) => iv.id,
+            _ => return 1,
+        };
+        if let Some(&rank) = self.ranks.get(&id) {
+            return rank;
+        }
+
+        // Values computed from others rank right above them, and the rest by the block
+        // defining them.
+        let inst = &self.func.inst_table[id];
+        let rank = match inst.opcode {
+            Opcode::Phi | Opcode::Load | Opcode::Call | Opcode::Alloca => {
+                let block = inst.parent;
+                let pos = self
+                    .func
+                    .basic_blocks
+                    .order
+                    .iter()
+                    .position(|&b| b == block)
+                    .unwrap();
+                (pos + 1) << 16
+            }
+            _ => {
+                let operands: Vec<Value> = inst
+                    .operands
+                    .iter()
+                    .filter_map(|op| match op {
+                        Operand::Value(val) => Some(*val),
+                        _ => None,
+                    })
+                    .collect();
+                operands
+                    .iter()
+                    .map(|val| self.rank_of(val))
+                    .max()
+                    .unwrap_or(0)
+                    + 1
+            }
+        };
+        self.ranks.insert(id, rank);
+        rank
+    }
+
+    fn is_reassociable(&self, inst: &Instruction) -> bool {
+        matches!(inst.opcode, Opcode::Add | Opcode::Mul)
+            && (is_integer(inst.ty) || (self.fast_math && inst.ty == Type::f64))
+    }
+}
+
+fn is_integer(ty: Type) -> bool {
+    matches!(ty, Type::i8 | Type::i32 | Type::i64)
+}
+
+fn is_identity(opcode: Opcode, konst: &Value) -> bool {
+    match (opcode, konst) {
+        (Opcode::Add, Value::Immediate(ImmediateValue::F64(f))) => *f == 0.0,
+        (Opcode::Mul, Value::Immediate(ImmediateValue::F64(f))) => *f == 1.0,
+        (Opcode::Add, konst) => konst.as_i64() == Some(0),
+        (_, konst) => konst.as_i64() == Some(1),
+    }
+}
+
+// Integers wrap around as they do at run time.
+fn fold(opcode: Opcode, ty: Type, x: &Value, y: &Value) -> Value {
+    match (x.as_i64(), y.as_i64(), opcode) {
+        (Some(x), Some(y), Opcode::Add) => Value::new_imm_int(ty, x.wrapping_add(y)),
+        (Some(x), Some(y), _) => Value::new_imm_int(ty, x.wrapping_mul(y)),
+        (_, _, Opcode::Add) => x.const_add(y).unwrap(),
+        _ => x.const_mul(y).unwrap(),
+    }
+}
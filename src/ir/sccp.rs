@@ -0,0 +1,449 @@
+use crate::{
+    analysis::manager::{AnalysisManager, PreservedAnalyses},
+    ir::{
+        basic_block::BasicBlockId,
+        dce::DeadCodeElimination,
+        function::Function,
+        module::Module,
+        opcode::{ICmpKind, Instruction, InstructionId, Opcode, Operand},
+        types::Type,
+        value::Value,
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Sparse conditional constant propagation. Values are assumed constant until proven
+/// otherwise and only blocks reachable through executable edges are evaluated, so
+/// constants flow through phis and branches on constants prune whole regions.
+pub struct SparseConditionalConstantPropagation {}
+
+struct SparseConditionalConstantPropagationOnFunction<'a> {
+    func: &'a mut Function,
+    values: FxHashMap<InstructionId, LatticeValue>,
+    executable_blocks: FxHashSet<BasicBlockId>,
+    executable_edges: FxHashSet<(BasicBlockId, BasicBlockId)>,
+    block_worklist: Vec<BasicBlockId>,
+    inst_worklist: Vec<InstructionId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LatticeValue {
+    /// Not known yet
+    Undefined,
+
+    /// An immediate. An i1, e.g. the result of `ICmp`, is kept as an `Int8` of 0 or 1
+    /// and never materialized; only the branches on it are folded.
+    Constant(Value),
+
+    /// May be more than one value
+    Overdefined,
+}
+
+impl SparseConditionalConstantPropagation {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for SparseConditionalConstantPropagation {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "SparseConditionalConstantPropagation"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for SparseConditionalConstantPropagation {
+    fn name(&self) -> &'static str {
+        "SparseConditionalConstantPropagation"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let cfg_changed = SparseConditionalConstantPropagationOnFunction::new(func).run();
+        let preserved = if cfg_changed {
+            PreservedAnalyses::none()
+        } else {
+            PreservedAnalyses::cfg()
+        };
+        am.invalidate(func, &preserved);
+
+        // Folded values and branches leave dead instructions behind.
+        DeadCodeElimination::new().run_on_function(func, am);
+        preserved
+    }
+}
+
+impl LatticeValue {
+    fn meet(self, other: Self) -> Self {
+        match (self, other) {
+            (LatticeValue::Undefined, x) | (x, LatticeValue::Undefined) => x,
+            (LatticeValue::Constant(x), LatticeValue::Constant(y)) if x == y => self,
+            _ => LatticeValue::Overdefined,
+        }
+    }
+}
+
+impl<'a> SparseConditionalConstantPropagationOnFunction<'a> {
+    fn new(func: &'a mut Function) -> Self {
+        Self {
+            func,
+            values: FxHashMap::default(),
+            executable_blocks: FxHashSet::default(),
+            executable_edges: FxHashSet::default(),
+            block_worklist: vec![],
+            inst_worklist: vec![],
+        }
+    }
+
+    /// Returns true if the control flow graph has changed.
+    fn run(mut self) -> bool {
+        let entry = match self.func.get_entry_block() {
+            Some(entry) => entry,
+            None => return false,
+        };
+        self.executable_blocks.insert(entry);
+        self.block_worklist.push(entry);
+
+        loop {
+            self.solve();
+            if !self.resolve_undefined_branches() {
+                break;
+            }
+        }
+
+        let mut cfg_changed = self.remove_dead_blocks();
+        cfg_changed |= self.fold_branches();
+        self.replace_constants();
+        cfg_changed
+    }
+
+    fn solve(&mut self) {
+        loop {
+            if let Some(block) = self.block_worklist.pop() {
+                let iseq = self.func.basic_blocks.arena[block].iseq_ref().clone();
+                for val in iseq {
+                    self.visit(val.as_instruction().id)
+                }
+            } else if let Some(inst) = self.inst_worklist.pop() {
+                self.visit(inst)
+            } else {
+                break;
+            }
+        }
+    }
+
+    // A branch on a value still undefined after solving would leave its successors
+    // unreachable, so its edges are taken to be executable.
+    fn resolve_undefined_branches(&mut self) -> bool {
+        let mut resolved = false;
+        let mut blocks: Vec<BasicBlockId> = self.executable_blocks.iter().copied().collect();
+        blocks.sort_by_key(|id| id.index());
+
+        for block in blocks {
+            let br = match self.func.get_terminator(block) {
+                Some(br) if self.func.inst_table[br].opcode == Opcode::CondBr => br,
+                _ => continue,
+            };
+            let cond = *self.func.inst_table[br].operands[0].as_value();
+            if self.value_of(&cond) == LatticeValue::Undefined {
+                for succ in self.successors_of(br) {
+                    resolved |= self.mark_edge_executable(block, succ);
+                }
+            }
+        }
+
+        resolved
+    }
+
+    fn visit(&mut self, id: InstructionId) {
+        let inst = &self.func.inst_table[id];
+        let block = inst.parent;
+        if !self.executable_blocks.contains(&block) {
+            return;
+        }
+
+        let new = match inst.opcode {
+            Opcode::Phi => {
+                let mut val = LatticeValue::Undefined;
+                for pair in inst.operands.chunks(2) {
+                    if self
+                        .executable_edges
+                        .contains(&(*pair[1].as_basic_block(), block))
+                    {
+                        val = val.meet(self.value_of(pair[0].as_value()));
+                    }
+                }
+                val
+            }
+            Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div | Opcode::Rem => {
+                match self.constant_operands(inst) {
+                    Ok((x, y)) => x
+                        .const_binary(inst.opcode, &y)
+                        .map_or(LatticeValue::Overdefined, LatticeValue::Constant),
+                    Err(val) => val,
+                }
+            }
+            Opcode::ICmp => match self.constant_operands(inst) {
+                Ok((x, y)) => fold_icmp(*inst.operands[0].as_icmp_kind(), &x, &y)
+                    .map_or(LatticeValue::Overdefined, |b| {
+                        LatticeValue::Constant(Value::new_imm_int8(b as i8))
+                    }),
+                Err(val) => val,
+            },
+            Opcode::Br => {
+                let dst = *inst.operands[0].as_basic_block();
+                self.mark_edge_executable(block, dst);
+                return;
+            }
+            Opcode::CondBr => {
+                let (then_, else_) = (
+                    *inst.operands[1].as_basic_block(),
+                    *inst.operands[2].as_basic_block(),
+                );
+                match self.value_of(inst.operands[0].as_value()) {
+                    LatticeValue::Undefined => {}
+                    LatticeValue::Constant(c) => {
+                        let taken = if is_true(&c) { then_ } else { else_ };
+                        self.mark_edge_executable(block, taken);
+                    }
+                    LatticeValue::Overdefined => {
+                        self.mark_edge_executable(block, then_);
+                        self.mark_edge_executable(block, else_);
+                    }
+                }
+                return;
+            }
+            _ if inst.ty == Type::Void => return,
+            _ => LatticeValue::Overdefined,
+        };
+
+        let old = self
+            .values
+            .get(&id)
+            .copied()
+            .unwrap_or(LatticeValue::Undefined);
+        if old == new || old == LatticeValue::Overdefined {
+            return;
+        }
+        // Values only go down the lattice.
+        let new = if old == LatticeValue::Undefined {
+            new
+        } else {
+            LatticeValue::Overdefined
+        };
+        self.values.insert(id, new);
+        let users = self.func.inst_table[id].users.borrow().clone();
+        self.inst_worklist.extend(users);
+    }
+
+    // Both operands if they are constants, otherwise the lattice value of the result.
+    fn constant_operands(&self, inst: &Instruction) -> Result<(Value, Value), LatticeValue> {
+        let ops = &inst.operands[inst.operands.len() - 2..];
+        match (
+            self.value_of(ops[0].as_value()),
+            self.value_of(ops[1].as_value()),
+        ) {
+            (LatticeValue::Constant(x), LatticeValue::Constant(y)) => Ok((x, y)),
+            (LatticeValue::Overdefined, _) | (_, LatticeValue::Overdefined) => {
+                Err(LatticeValue::Overdefined)
+            }
+            _ => Err(LatticeValue::Undefined),
+        }
+    }
+
+    fn value_of(&self, val: &Value) -> LatticeValue {
+        match val {
+            Value::Immediate(_) => LatticeValue::Constant(*val),
+            Value::Instruction(iv) => self
+                .values
+                .get(&iv.id)
+                .copied()
+                .unwrap_or(LatticeValue::Undefined),
+            _ => LatticeValue::Overdefined,
+        }
+    }
+
+    /// Returns true if the edge has just become executable.
+    fn mark_edge_executable(&mut self, from: BasicBlockId, to: BasicBlockId) -> bool {
+        if !self.executable_edges.insert((from, to)) {
+            return false;
+        }
+
+        if self.executable_blocks.insert(to) {
+            self.block_worklist.push(to);
+        } else {
+            // Only the phis see the new edge.
+            for val in &*self.func.basic_blocks.arena[to].iseq_ref() {
+                let id = val.as_instruction().id;
+                if self.func.inst_table[id].opcode != Opcode::Phi {
+                    break;
+                }
+                self.inst_worklist.push(id);
+            }
+        }
+
+        true
+    }
+
+    fn remove_dead_blocks(&mut self) -> bool {
+        let dead: Vec<BasicBlockId> = self
+            .func
+            .basic_blocks
+            .order
+            .iter()
+            .copied()
+            .filter(|block| !self.executable_blocks.contains(block))
+            .collect();
+
+        for &block in &dead {
+            let succs = self.func.basic_blocks.arena[block].succ.clone();
+            for succ in succs {
+                self.func.basic_blocks.arena[succ].pred.remove(&block);
+                self.remove_phi_incomings(succ, block);
+            }
+            let preds = self.func.basic_blocks.arena[block].pred.clone();
+            for pred in preds {
+                self.func.basic_blocks.arena[pred].succ.remove(&block);
+            }
+
+            let iseq = std::mem::take(&mut *self.func.basic_blocks.arena[block].iseq_ref_mut());
+            for val in iseq {
+                let id = val.as_instruction().id;
+                self.func.inst_table[id].remove(&self.func.inst_table);
+            }
+        }
+
+        self.func
+            .basic_blocks
+            .order
+            .retain(|block| !dead.contains(block));
+        !dead.is_empty()
+    }
+
+    // Turns a `CondBr` that only takes one of its edges into a `Br`.
+    fn fold_branches(&mut self) -> bool {
+        let mut changed = false;
+
+        for block in self.func.basic_blocks.order.clone() {
+            let br = match self.func.get_terminator(block) {
+                Some(br) if self.func.inst_table[br].opcode == Opcode::CondBr => br,
+                _ => continue,
+            };
+            let succs = self.successors_of(br);
+            let taken: Vec<BasicBlockId> = succs
+                .iter()
+                .copied()
+                .filter(|&succ| self.executable_edges.contains(&(block, succ)))
+                .collect();
+            if taken.len() != 1 || succs[0] == succs[1] {
+                continue;
+            }
+
+            let taken = taken[0];
+            for &succ in &succs {
+                if succ != taken {
+                    self.func.basic_blocks.arena[block].succ.remove(&succ);
+                    self.func.basic_blocks.arena[succ].pred.remove(&block);
+                    self.remove_phi_incomings(succ, block);
+                }
+            }
+            self.func.change_inst(
+                br,
+                Instruction::new(
+                    Opcode::Br,
+                    vec![Operand::BasicBlock(taken)],
+                    Type::Void,
+                    block,
+                ),
+            );
+            changed = true;
+        }
+
+        changed
+    }
+
+    fn replace_constants(&mut self) {
+        let mut constants: Vec<(InstructionId, Value)> = self
+            .values
+            .iter()
+            .filter_map(|(&id, val)| match val {
+                LatticeValue::Constant(c) => Some((id, *c)),
+                _ => None,
+            })
+            .collect();
+        constants.sort_by_key(|(id, _)| id.index());
+
+        for (id, c) in constants {
+            // An i1 (`ICmp` or a phi or select of them) has no immediate of its type.
+            let inst = &self.func.inst_table[id];
+            if inst.ty == Type::i1 || !self.executable_blocks.contains(&inst.parent) {
+                continue;
+            }
+            Instruction::replace_all_uses(&mut self.func.inst_table, id, Operand::Value(c));
+            self.func.remove_inst(id);
+        }
+    }
+
+    fn remove_phi_incomings(&mut self, block: BasicBlockId, pred: BasicBlockId) {
+        let iseq = self.func.basic_blocks.arena[block].iseq_ref().clone();
+        for val in iseq {
+            let id = val.as_instruction().id;
+            if self.func.inst_table[id].opcode != Opcode::Phi {
+                break;
+            }
+            let phi = &mut self.func.inst_table[id];
+            let pos = match phi
+                .operands
+                .chunks(2)
+                .position(|pair| *pair[1].as_basic_block() == pred)
+            {
+                Some(pos) => pos * 2,
+                None => continue,
+            };
+            let removed: Vec<Operand> = phi.operands.drain(pos..pos + 2).collect();
+            if !self.func.inst_table[id].operands.contains(&removed[0]) {
+                removed[0].remove_from_users(&self.func.inst_table, id);
+            }
+        }
+    }
+
+    fn successors_of(&self, br: InstructionId) -> Vec<BasicBlockId> {
+        self.func.inst_table[br]
+            .operands
+            .iter()
+            .filter_map(|op| match op {
+                Operand::BasicBlock(bb) => Some(*bb),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn is_true(cond: &Value) -> bool {
+    cond.as_i64().map_or(false, |i| i != 0)
+}
+
+fn fold_icmp(kind: ICmpKind, x: &Value, y: &Value) -> Option<bool> {
+    let (x, y) = (x.as_i64()?, y.as_i64()?);
+    Some(match kind {
+        ICmpKind::Eq => x == y,
+        ICmpKind::Ne => x != y,
+        ICmpKind::Lt => x < y,
+        ICmpKind::Le => x <= y,
+        ICmpKind::Gt => x > y,
+        ICmpKind::Ge => x >= y,
+    })
+}
@@ -0,0 +1,499 @@
+use crate::{
+    analysis::manager::{AnalysisManager, PreservedAnalyses},
+    ir::{
+        basic_block::BasicBlockId,
+        builder::{IRBuilder, IRBuilderWithFunction},
+        function::Function,
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        types::Type,
+        value::Value,
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+use rustc_hash::FxHashSet;
+
+/// How many instructions a side of a diamond may have to be turned into `Select`s.
+const MAX_SPECULATED: usize = 1;
+
+/// Simplifies the control flow graph until nothing changes:
+///
+/// - folds a `CondBr` on a constant, or with the same target twice, into a `Br`
+/// - removes blocks unreachable from the entry block
+/// - merges a block into its predecessor if it's the only successor of it
+/// - redirects the predecessors of a block only branching to another to the other one
+/// - turns a diamond (or a triangle) whose sides compute at most `MAX_SPECULATED`
+///   instructions into `Select`s of the incoming values of the phis after it, if
+///   `form_selects` is set
+///
+/// Selects aren't formed by default, since no backend selects instructions for them:
+/// `LowerSelect` turns them back into branches, and both sides of the diamond would
+/// run.
+pub struct SimplifyCFG {
+    pub form_selects: bool,
+}
+
+struct SimplifyCFGOnFunction<'a> {
+    func: &'a mut Function,
+    form_selects: bool,
+}
+
+impl SimplifyCFG {
+    pub fn new() -> Self {
+        Self {
+            form_selects: false,
+        }
+    }
+
+    pub fn with_selects(mut self, form_selects: bool) -> Self {
+        self.form_selects = form_selects;
+        self
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for SimplifyCFG {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "SimplifyCFG"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for SimplifyCFG {
+    fn name(&self) -> &'static str {
+        "SimplifyCFG"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        _am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let changed = SimplifyCFGOnFunction {
+            func,
+            form_selects: self.form_selects,
+        }
+        .run();
+        if changed {
+            PreservedAnalyses::none()
+        } else {
+            PreservedAnalyses::all()
+        }
+    }
+}
+
+impl<'a> SimplifyCFGOnFunction<'a> {
+    /// Returns true if the function is changed.
+    fn run(&mut self) -> bool {
+        let mut changed = false;
+        loop {
+            let changed_ = self.fold_branches()
+                | self.remove_unreachable_blocks()
+                | self.merge_blocks()
+                | self.thread_jumps()
+                | (self.form_selects && self.form_selects());
+            if !changed_ {
+                return changed;
+            }
+            changed = true;
+        }
+    }
+
+    fn fold_branches(&mut self) -> bool {
+        let mut changed = false;
+
+        for block in self.func.basic_blocks.order.clone() {
+            let br = match self.func.get_terminator(block) {
+                Some(br) if self.func.inst_table[br].opcode == Opcode::CondBr => br,
+                _ => continue,
+            };
+            let ops = &self.func.inst_table[br].operands;
+            let (then, else_) = (*ops[1].as_basic_block(), *ops[2].as_basic_block());
+            let taken = match ops[0].as_value().as_i64() {
+                _ if then == else_ => then,
+                Some(0) => else_,
+                Some(_) => then,
+                None => continue,
+            };
+
+            let not_taken = if taken == then { else_ } else { then };
+            if not_taken != taken {
+                self.remove_edge(block, not_taken);
+            }
+            self.func.change_inst(
+                br,
+                Instruction::new(
+                    Opcode::Br,
+                    vec![Operand::BasicBlock(taken)],
+                    Type::Void,
+                    block,
+                ),
+            );
+            changed = true;
+        }
+
+        changed
+    }
+
+    fn remove_unreachable_blocks(&mut self) -> bool {
+        let blocks = &self.func.basic_blocks;
+        let mut reachable = FxHashSet::default();
+        let mut worklist = vec![blocks.order[0]];
+        while let Some(block) = worklist.pop() {
+            if reachable.insert(block) {
+                worklist.extend(blocks.arena[block].succ.iter().copied());
+            }
+        }
+        let unreachable: Vec<BasicBlockId> = blocks
+            .order
+            .iter()
+            .copied()
+            .filter(|block| !reachable.contains(block))
+            .collect();
+
+        for &block in &unreachable {
+            for succ in self.func.basic_blocks.arena[block].succ.clone() {
+                self.remove_edge(block, succ);
+            }
+
+            let iseq = std::mem::take(&mut *self.func.basic_blocks.arena[block].iseq_ref_mut());
+            for val in iseq {
+                let id = val.as_instruction().id;
+                self.func.inst_table[id].remove(&self.func.inst_table);
+            }
+        }
+
+        self.func
+            .basic_blocks
+            .order
+            .retain(|block| reachable.contains(block));
+        !unreachable.is_empty()
+    }
+
+    // Merges a block into its only predecessor if the predecessor only branches to it.
+    fn merge_blocks(&mut self) -> bool {
+        let mut changed = false;
+
+        for block in self.func.basic_blocks.order.clone() {
+            let pred = match self.single_pred_of(block) {
+                Some(pred) if pred != block && block != self.func.basic_blocks.order[0] => pred,
+                _ => continue,
+            };
+            let br = match self.func.get_terminator(pred) {
+                Some(br) if self.func.inst_table[br].opcode == Opcode::Br => br,
+                _ => continue,
+            };
+
+            // Phis have only one incoming value now.
+            for phi in self.func.get_phis(block) {
+                let val = *self.func.inst_table[phi].operands[0].as_value();
+                Instruction::replace_all_uses(&mut self.func.inst_table, phi, Operand::Value(val));
+                self.func.remove_inst(phi);
+            }
+            self.func.remove_inst(br);
+
+            let moved = std::mem::take(&mut *self.func.basic_blocks.arena[block].iseq_ref_mut());
+            for val in &moved {
+                self.func.inst_table[val.as_instruction().id].parent = pred;
+            }
+            self.func.basic_blocks.arena[pred]
+                .iseq_ref_mut()
+                .extend(moved);
+
+            let succs = std::mem::take(&mut self.func.basic_blocks.arena[block].succ);
+            for &succ in &succs {
+                let succ_ = &mut self.func.basic_blocks.arena[succ];
+                succ_.pred.remove(&block);
+                succ_.pred.insert(pred);
+                self.replace_block_in_phis(succ, block, pred);
+            }
+            self.func.basic_blocks.arena[pred].succ = succs;
+            self.func.basic_blocks.arena[block].pred.clear();
+            self.func.basic_blocks.order.retain(|&b| b != block);
+            changed = true;
+        }
+
+        changed
+    }
+
+    // Makes the predecessors of a block consisting only of a `Br` branch to its target,
+    // and removes the block.
+    fn thread_jumps(&mut self) -> bool {
+        let mut changed = false;
+
+        for block in self.func.basic_blocks.order.clone() {
+            if block == self.func.basic_blocks.order[0] {
+                continue;
+            }
+            let br = match &self.func.basic_blocks.arena[block].iseq_ref()[..] {
+                [br] => br.as_instruction().id,
+                _ => continue,
+            };
+            let target = match &self.func.inst_table[br] {
+                inst if inst.opcode == Opcode::Br => *inst.operands[0].as_basic_block(),
+                _ => continue,
+            };
+            if target == block || !self.can_thread(block, target) {
+                continue;
+            }
+
+            let phis = self.func.get_phis(target);
+            for pred in self.sorted_preds_of(block) {
+                let pred_br = self.func.get_terminator(pred).unwrap();
+                Instruction::replace_operand(
+                    &mut self.func.inst_table,
+                    pred_br,
+                    &Operand::BasicBlock(block),
+                    Operand::BasicBlock(target),
+                );
+                self.func.basic_blocks.arena[pred].succ.remove(&block);
+                self.func.basic_blocks.arena[block].pred.remove(&pred);
+                // A predecessor already branching to `target` has the same incoming
+                // values as `block`.
+                if self.func.basic_blocks.arena[pred].succ.insert(target) {
+                    self.func.basic_blocks.arena[target].pred.insert(pred);
+                    for &phi in &phis {
+                        let val = self.func.get_incoming(phi, block).unwrap();
+                        Instruction::add_operand(
+                            &mut self.func.inst_table,
+                            phi,
+                            Operand::Value(val),
+                        );
+                        Instruction::add_operand(
+                            &mut self.func.inst_table,
+                            phi,
+                            Operand::BasicBlock(pred),
+                        );
+                    }
+                }
+            }
+
+            self.remove_edge(block, target);
+            self.func.remove_inst(br);
+            self.func.basic_blocks.order.retain(|&b| b != block);
+            changed = true;
+        }
+
+        changed
+    }
+
+    fn can_thread(&self, block: BasicBlockId, target: BasicBlockId) -> bool {
+        let preds = &self.func.basic_blocks.arena[block].pred;
+        !preds.is_empty()
+            && self.func.get_phis(target).into_iter().all(|phi| {
+                let val = self.func.get_incoming(phi, block);
+                preds.iter().all(|&pred| {
+                    !self.func.basic_blocks.arena[target].pred.contains(&pred)
+                        || self.func.get_incoming(phi, pred) == val
+                })
+            })
+    }
+
+    fn form_selects(&mut self) -> bool {
+        let mut changed = false;
+
+        for block in self.func.basic_blocks.order.clone() {
+            if !self.func.basic_blocks.order.contains(&block) {
+                continue;
+            }
+            let br = match self.func.get_terminator(block) {
+                Some(br) if self.func.inst_table[br].opcode == Opcode::CondBr => br,
+                _ => continue,
+            };
+            let ops = &self.func.inst_table[br].operands;
+            let cond = *ops[0].as_value();
+            let (then, else_) = (*ops[1].as_basic_block(), *ops[2].as_basic_block());
+            if then == else_ || then == block || else_ == block {
+                continue;
+            }
+
+            // The blocks whose values the phis in `merge` take on either side
+            let (merge, then_src, else_src) = match (
+                self.speculatable_side(block, then),
+                self.speculatable_side(block, else_),
+            ) {
+                (Some(m1), Some(m2)) if m1 == m2 => {
+                    if self.func.basic_blocks.arena[m1].pred.contains(&block) {
+                        continue;
+                    }
+                    (m1, then, else_)
+                }
+                (Some(merge), _) if merge == else_ => (merge, then, block),
+                (_, Some(merge)) if merge == then => (merge, block, else_),
+                _ => continue,
+            };
+            if merge == block {
+                continue;
+            }
+
+            self.speculate(block, then_src, else_src, merge, cond, br);
+            changed = true;
+        }
+
+        changed
+    }
+
+    // Returns the block `side` branches to if it's only reached from `block` and its
+    // instructions are cheap and safe to execute unconditionally.
+    fn speculatable_side(&self, block: BasicBlockId, side: BasicBlockId) -> Option<BasicBlockId> {
+        if self.single_pred_of(side) != Some(block) {
+            return None;
+        }
+        let iseq = self.func.basic_blocks.arena[side].iseq_ref();
+        let (br, insts) = iseq.split_last()?;
+        let br = &self.func.inst_table[br.as_instruction().id];
+        let speculatable = insts.len() <= MAX_SPECULATED
+            && insts.iter().all(|v| {
+                matches!(
+                    self.func.inst_table[v.as_instruction().id].opcode,
+                    Opcode::Add
+                        | Opcode::Sub
+                        | Opcode::Mul
+                        | Opcode::Shl
+                        | Opcode::Sext
+                        | Opcode::SIToFP
+                        | Opcode::FPToSI
+                        | Opcode::ICmp
+                        | Opcode::FCmp
+                        | Opcode::GetElementPtr
+                )
+            });
+        match br.opcode {
+            Opcode::Br if speculatable => Some(*br.operands[0].as_basic_block()),
+            _ => None,
+        }
+    }
+
+    // Hoists the sides of the `CondBr` `br` ending `block` into `block` and selects
+    // the incoming values from `then_src` and `else_src` of the phis in `merge`.
+    fn speculate(
+        &mut self,
+        block: BasicBlockId,
+        then_src: BasicBlockId,
+        else_src: BasicBlockId,
+        merge: BasicBlockId,
+        cond: Value,
+        br: InstructionId,
+    ) {
+        let sides: Vec<BasicBlockId> = vec![then_src, else_src]
+            .into_iter()
+            .filter(|&side| side != block)
+            .collect();
+
+        for &side in &sides {
+            let mut iseq = std::mem::take(&mut *self.func.basic_blocks.arena[side].iseq_ref_mut());
+            let side_br = iseq.pop().unwrap().as_instruction().id;
+            self.func.inst_table[side_br].remove(&self.func.inst_table);
+            let (_, pos) = self.func.find_inst_pos(br).unwrap();
+            for val in iseq.into_iter().rev() {
+                self.func.inst_table[val.as_instruction().id].parent = block;
+                self.func.basic_blocks.arena[block]
+                    .iseq_ref_mut()
+                    .insert(pos, val);
+            }
+        }
+
+        for phi in self.func.get_phis(merge) {
+            let then_val = self.func.get_incoming(phi, then_src).unwrap();
+            let else_val = self.func.get_incoming(phi, else_src).unwrap();
+            let val = if then_val == else_val {
+                then_val
+            } else {
+                let mut builder = IRBuilderWithFunction::new(self.func);
+                builder.set_insert_point_before_inst(br);
+                builder.build_select(cond, then_val, else_val)
+            };
+
+            let inst = &self.func.inst_table[phi];
+            let mut operands: Vec<Operand> = inst
+                .operands
+                .chunks(2)
+                .filter(|pair| ![then_src, else_src].contains(pair[1].as_basic_block()))
+                .flatten()
+                .copied()
+                .collect();
+            operands.push(Operand::Value(val));
+            operands.push(Operand::BasicBlock(block));
+            let inst = Instruction::new(Opcode::Phi, operands, inst.ty, merge);
+            self.func.change_inst(phi, inst);
+        }
+
+        self.func.change_inst(
+            br,
+            Instruction::new(
+                Opcode::Br,
+                vec![Operand::BasicBlock(merge)],
+                Type::Void,
+                block,
+            ),
+        );
+        for side in sides {
+            self.func.basic_blocks.arena[side].pred.clear();
+            self.func.basic_blocks.arena[side].succ.clear();
+            self.func.basic_blocks.arena[merge].pred.remove(&side);
+            self.func.basic_blocks.order.retain(|&b| b != side);
+        }
+        self.func.basic_blocks.arena[block].succ = vec![merge].into_iter().collect();
+        self.func.basic_blocks.arena[merge].pred.insert(block);
+    }
+
+    // Removes the edge from `from` to `to` and the incoming values of the phis in `to`
+    // from `from`. The terminator of `from` is left as it is.
+    fn remove_edge(&mut self, from: BasicBlockId, to: BasicBlockId) {
+        self.func.basic_blocks.arena[from].succ.remove(&to);
+        self.func.basic_blocks.arena[to].pred.remove(&from);
+
+        for phi in self.func.get_phis(to) {
+            let inst = &mut self.func.inst_table[phi];
+            let pos = match inst
+                .operands
+                .chunks(2)
+                .position(|pair| *pair[1].as_basic_block() == from)
+            {
+                Some(pos) => pos * 2,
+                None => continue,
+            };
+            let removed: Vec<Operand> = inst.operands.drain(pos..pos + 2).collect();
+            if !self.func.inst_table[phi].operands.contains(&removed[0]) {
+                removed[0].remove_from_users(&self.func.inst_table, phi);
+            }
+        }
+    }
+
+    fn replace_block_in_phis(&mut self, block: BasicBlockId, from: BasicBlockId, to: BasicBlockId) {
+        for phi in self.func.get_phis(block) {
+            Instruction::replace_operand(
+                &mut self.func.inst_table,
+                phi,
+                &Operand::BasicBlock(from),
+                Operand::BasicBlock(to),
+            );
+        }
+    }
+
+    fn single_pred_of(&self, block: BasicBlockId) -> Option<BasicBlockId> {
+        let pred = &self.func.basic_blocks.arena[block].pred;
+        match pred.len() {
+            1 => pred.iter().next().copied(),
+            _ => None,
+        }
+    }
+
+    fn sorted_preds_of(&self, block: BasicBlockId) -> Vec<BasicBlockId> {
+        let mut preds: Vec<BasicBlockId> = self.func.basic_blocks.arena[block]
+            .pred
+            .iter()
+            .copied()
+            .collect();
+        preds.sort_by_key(|id| id.index());
+        preds
+    }
+}
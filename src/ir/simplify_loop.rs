@@ -44,10 +44,17 @@ impl<'a> SimplifyLoopOnFunction<'a> {
 
     pub fn run(&mut self) {
         let dom_tree = DominatorTreeConstructor::new(&self.func.basic_blocks).construct();
-        let backedges_to_merge = self.collect_backedges_to_merge(&dom_tree);
+        self.run_with_dom_tree(&dom_tree);
+    }
+
+    /// Returns true if the control flow graph has changed.
+    pub fn run_with_dom_tree(&mut self, dom_tree: &DominatorTree<BasicBlock>) -> bool {
+        let backedges_to_merge = self.collect_backedges_to_merge(dom_tree);
+        let changed = backedges_to_merge.len() > 0;
         for back_edges in backedges_to_merge {
             self.merge_backedges(back_edges);
         }
+        changed
     }
 
     fn collect_backedges_to_merge(
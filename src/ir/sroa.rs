@@ -0,0 +1,273 @@
+use crate::{
+    analysis::manager::{AnalysisManager, PreservedAnalyses},
+    ir::{
+        builder::{IRBuilder, IRBuilderWithFunction},
+        function::Function,
+        mem2reg::Mem2Reg,
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        types::{CompoundType, Type},
+        value::Value,
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+use rustc_hash::FxHashMap;
+
+/// Arrays with more elements than this are left in memory.
+const MAX_ELEMENTS: usize = 32;
+
+/// Scalar replacement of aggregates. A struct or array alloca accessed only through
+/// `GetElementPtr`s with constant indices is split into an alloca per field, nested
+/// aggregates included, which `Mem2Reg` then promotes to registers.
+///
+/// An alloca may also be passed as a `byval` argument. The callee only gets a copy, so
+/// the fields are copied into a new alloca right before the call.
+pub struct ScalarReplacementOfAggregates {}
+
+struct ScalarReplacementOfAggregatesOnFunction<'a> {
+    func: &'a mut Function,
+}
+
+enum Use {
+    /// A `GetElementPtr` into the field at the index
+    Field(InstructionId, usize),
+
+    /// A call taking the alloca as a `byval` argument
+    ByvalArg(InstructionId),
+}
+
+impl ScalarReplacementOfAggregates {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for ScalarReplacementOfAggregates {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "ScalarReplacementOfAggregates"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for ScalarReplacementOfAggregates {
+    fn name(&self) -> &'static str {
+        "ScalarReplacementOfAggregates"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let changed = ScalarReplacementOfAggregatesOnFunction { func }.run();
+        if !changed {
+            return PreservedAnalyses::all();
+        }
+        Mem2Reg::new().run_on_function(func, am)
+    }
+}
+
+impl<'a> ScalarReplacementOfAggregatesOnFunction<'a> {
+    /// Returns true if any alloca is split.
+    fn run(&mut self) -> bool {
+        let mut worklist: Vec<InstructionId> = vec![];
+        for &block in &self.func.basic_blocks.order {
+            for val in &*self.func.basic_blocks.arena[block].iseq_ref() {
+                let id = val.as_instruction().id;
+                if self.func.inst_table[id].opcode == Opcode::Alloca {
+                    worklist.push(id)
+                }
+            }
+        }
+
+        let mut changed = false;
+        while let Some(alloca) = worklist.pop() {
+            if let Some(uses) = self.collect_uses(alloca) {
+                self.split(alloca, uses, &mut worklist);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    // Returns the uses of `alloca` if it can be split.
+    fn collect_uses(&self, alloca: InstructionId) -> Option<Vec<Use>> {
+        let fields = self.fields_of(*self.func.inst_table[alloca].operands[0].as_type())?;
+        let alloca_val = self.func.get_inst_value(alloca);
+
+        let mut users = self.func.inst_table[alloca].users.borrow().clone();
+        users.sort_by_key(|id| id.index());
+        users.dedup();
+
+        let mut uses = vec![];
+        let mut is_read = false;
+        for user in users {
+            let inst = &self.func.inst_table[user];
+            match inst.opcode {
+                Opcode::GetElementPtr => {
+                    let ops = &inst.operands;
+                    if ops.len() < 3
+                        || ops[1].as_value().as_i64() != Some(0)
+                        || ops[1..].iter().any(|op| *op.as_value() == alloca_val)
+                    {
+                        return None;
+                    }
+                    let index = ops[2]
+                        .as_value()
+                        .as_i64()
+                        .filter(|&i| 0 <= i && (i as usize) < fields.len())?;
+                    if !self.is_field_ptr_safe(user) {
+                        return None;
+                    }
+                    is_read |= inst.users.borrow().iter().any(|&id| {
+                        matches!(
+                            self.func.inst_table[id].opcode,
+                            Opcode::Load | Opcode::GetElementPtr
+                        )
+                    });
+                    uses.push(Use::Field(user, index as usize));
+                }
+                Opcode::Call
+                    if self.is_byval_arg_only(user, &alloca_val)
+                        && fields.iter().all(|ty| ty.is_atomic()) =>
+                {
+                    uses.push(Use::ByvalArg(user))
+                }
+                _ => return None,
+            }
+        }
+
+        // Splitting pays off only if a field is read back other than by a `byval` copy.
+        // This also keeps the copies, which are only written, from being split again.
+        Some(uses).filter(|_| is_read)
+    }
+
+    // Returns true if the pointer computed by `gep` can't reach other fields.
+    fn is_field_ptr_safe(&self, gep: InstructionId) -> bool {
+        let ptr = self.func.get_inst_value(gep);
+        self.func.inst_table[gep].users.borrow().iter().all(|&id| {
+            let inst = &self.func.inst_table[id];
+            match inst.opcode {
+                Opcode::Load => true,
+                Opcode::Store => *inst.operands[0].as_value() != ptr,
+                Opcode::GetElementPtr => {
+                    inst.operands.get(1).and_then(|op| op.as_value().as_i64()) == Some(0)
+                        && inst.operands[1..].iter().all(|op| *op.as_value() != ptr)
+                }
+                Opcode::Call => self.is_byval_arg_only(id, &ptr),
+                _ => false,
+            }
+        })
+    }
+
+    fn is_byval_arg_only(&self, call: InstructionId, ptr: &Value) -> bool {
+        let ops = &self.func.inst_table[call].operands;
+        let fn_ty = match ops[0].as_value() {
+            Value::Function(f) => f.ty,
+            _ => return false,
+        };
+        let fn_ty = self.func.types.compound_ty(fn_ty);
+        let params_attr = &fn_ty.as_function().params_attr;
+        *ops[0].as_value() != *ptr
+            && ops[1..].iter().enumerate().all(|(i, op)| {
+                op.as_value() != ptr || params_attr.get(&i).map_or(false, |a| a.byval)
+            })
+    }
+
+    fn split(&mut self, alloca: InstructionId, uses: Vec<Use>, worklist: &mut Vec<InstructionId>) {
+        let ty = *self.func.inst_table[alloca].operands[0].as_type();
+        let fields = self.fields_of(ty).unwrap();
+        let name = self.func.names.get_inst(alloca).cloned();
+        let mut field_allocas: FxHashMap<usize, Value> = FxHashMap::default();
+
+        // Field allocas are inserted where `alloca` is, when first used.
+        let mut field_alloca = |func: &mut Function, index: usize| -> Value {
+            *field_allocas.entry(index).or_insert_with(|| {
+                let mut builder = IRBuilderWithFunction::new(func);
+                builder.set_insert_point_before_inst(alloca);
+                let new = builder.build_alloca(fields[index]);
+                let id = new.as_instruction().id;
+                if let Some(name) = &name {
+                    func.names.set_inst(id, &format!("{}.{}", name, index));
+                }
+                if !fields[index].is_atomic() {
+                    worklist.push(id)
+                }
+                new
+            })
+        };
+
+        for use_ in uses {
+            match use_ {
+                Use::Field(gep, index) => {
+                    let new = field_alloca(self.func, index);
+                    let inst = &self.func.inst_table[gep];
+                    if inst.operands.len() == 3 {
+                        Instruction::replace_all_uses(
+                            &mut self.func.inst_table,
+                            gep,
+                            Operand::Value(new),
+                        );
+                        self.func.remove_inst(gep);
+                    } else {
+                        let mut operands =
+                            vec![Operand::Value(new), Operand::Value(Value::new_imm_int32(0))];
+                        operands.extend(inst.operands[3..].iter().copied());
+                        let inst = Instruction::new(inst.opcode, operands, inst.ty, inst.parent);
+                        self.func.change_inst(gep, inst);
+                    }
+                }
+                Use::ByvalArg(call) => {
+                    let fields: Vec<Value> = (0..fields.len())
+                        .map(|i| field_alloca(self.func, i))
+                        .collect();
+
+                    let mut builder = IRBuilderWithFunction::new(self.func);
+                    builder.set_insert_point_before_inst(alloca);
+                    let copy = builder.build_alloca(ty);
+                    builder.set_insert_point_before_inst(call);
+                    for (i, field) in fields.into_iter().enumerate() {
+                        let val = builder.build_load(field);
+                        let ptr = builder.build_gep(
+                            copy,
+                            vec![Value::new_imm_int32(0), Value::new_imm_int32(i as i32)],
+                        );
+                        builder.build_store(val, ptr);
+                    }
+
+                    let old = self.func.get_inst_value(alloca);
+                    Instruction::replace_operand(
+                        &mut self.func.inst_table,
+                        call,
+                        &Operand::Value(old),
+                        Operand::Value(copy),
+                    );
+                }
+            }
+        }
+
+        self.func.remove_inst(alloca);
+    }
+
+    // Returns the types of the fields of a struct or a small array.
+    fn fields_of(&self, ty: Type) -> Option<Vec<Type>> {
+        match ty {
+            Type::Struct(_) | Type::Array(_) => {}
+            _ => return None,
+        }
+        match &*self.func.types.compound_ty(ty) {
+            CompoundType::Struct(s) => Some(s.fields_ty().to_vec()),
+            CompoundType::Array(a) if a.len <= MAX_ELEMENTS => Some(vec![a.elem_ty; a.len]),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use crate::{
+    analysis::{
+        alias::AliasAnalysis,
+        manager::{AnalysisManager, PreservedAnalyses},
+    },
+    ir::{
+        basic_block::BasicBlockId,
+        builder::{IRBuilder, IRBuilderWithFunction},
+        function::Function,
+        module::Module,
+        opcode::{Instruction, InstructionId, Opcode, Operand},
+        types::Type,
+        value::{ArgumentValue, Value},
+    },
+    traits::pass::{run_function_pass_on_module, FunctionPassTrait, ModulePassTrait},
+};
+
+/// Turns self-recursive calls in tail position into branches back to the entry block,
+/// whose phis take the arguments of the call.
+///
+/// A call whose result is combined by an `Add` or `Mul` with a value computed before it
+/// (e.g. `ret n * f(n - 1)`) is eliminated too. The combined values are kept in an
+/// accumulator that every other return is combined with.
+pub struct TailRecursionElimination {}
+
+struct TailRecursionEliminationOnFunction<'a> {
+    func: &'a mut Function,
+}
+
+/// A recursive call followed by a return, possibly through an accumulating operation
+struct TailCall {
+    block: BasicBlockId,
+    call: InstructionId,
+    accumulate: Option<(Opcode, Value)>,
+}
+
+impl TailRecursionElimination {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn run_on_module(&mut self, module: &mut Module) {
+        run_function_pass_on_module(self, module)
+    }
+}
+
+impl ModulePassTrait for TailRecursionElimination {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "TailRecursionElimination"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        self.run_on_module(module);
+    }
+}
+
+impl FunctionPassTrait for TailRecursionElimination {
+    fn name(&self) -> &'static str {
+        "TailRecursionElimination"
+    }
+
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        _am: &mut AnalysisManager,
+    ) -> PreservedAnalyses {
+        let changed = TailRecursionEliminationOnFunction { func }.run();
+        if changed {
+            PreservedAnalyses::none()
+        } else {
+            PreservedAnalyses::all()
+        }
+    }
+}
+
+impl<'a> TailRecursionEliminationOnFunction<'a> {
+    /// Returns true if any call is eliminated.
+    fn run(mut self) -> bool {
+        let mut tail_calls = self.collect_tail_calls();
+
+        // All the accumulated calls must use the same operation.
+        let op = tail_calls
+            .iter()
+            .find_map(|c| c.accumulate.map(|(op, _)| op));
+        tail_calls.retain(|c| c.accumulate.map_or(true, |(op_, _)| Some(op_) == op));
+
+        if tail_calls.is_empty() {
+            return false;
+        }
+
+        let header = self.func.basic_blocks.order[0];
+        let entry = self.insert_new_entry(header);
+        let params = self.insert_param_phis(header, entry);
+        let accumulator = op.map(|op| (op, self.insert_accumulator_phi(header, entry, op)));
+
+        // Returns other than the eliminated ones give the result of the innermost call.
+        if let Some((op, acc)) = accumulator {
+            self.accumulate_into_returns(&tail_calls, op, acc);
+        }
+
+        for tail_call in tail_calls {
+            self.eliminate(tail_call, header, &params, accumulator);
+        }
+
+        true
+    }
+
+    fn collect_tail_calls(&self) -> Vec<TailCall> {
+        // The callee would reuse the frame, so it must not be able to reach the allocas
+        // in it, whether through its arguments or through memory.
+        let aa = AliasAnalysis::new(self.func);
+        let alloca_escapes = self.func.inst_table.iter().any(|(id, i)| {
+            i.opcode == Opcode::Alloca && !aa.is_local(&self.func.get_inst_value(id))
+        });
+        if alloca_escapes {
+            return vec![];
+        }
+
+        let mut tail_calls = vec![];
+
+        for &block in &self.func.basic_blocks.order {
+            let iseq: Vec<InstructionId> = self.func.basic_blocks.arena[block]
+                .iseq_ref()
+                .iter()
+                .map(|v| v.as_instruction().id)
+                .collect();
+            let ret = match iseq.last() {
+                Some(&ret) if self.func.inst_table[ret].opcode == Opcode::Ret => ret,
+                _ => continue,
+            };
+            let ret_val = *self.func.inst_table[ret].operands[0].as_value();
+
+            let tail_call = match iseq.len() {
+                len if len >= 2 && self.is_recursive_call(iseq[len - 2]) => {
+                    let call = iseq[len - 2];
+                    let call_val = self.func.get_inst_value(call);
+                    if ret_val != call_val
+                        && !(ret_val == Value::None && call_val.get_type() == Type::Void)
+                    {
+                        continue;
+                    }
+                    TailCall {
+                        block,
+                        call,
+                        accumulate: None,
+                    }
+                }
+                len if len >= 3 && self.is_recursive_call(iseq[len - 3]) => {
+                    let (call, op) = (iseq[len - 3], iseq[len - 2]);
+                    match self.accumulation(call, op) {
+                        Some(accumulate) if ret_val == self.func.get_inst_value(op) => TailCall {
+                            block,
+                            call,
+                            accumulate: Some(accumulate),
+                        },
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+            tail_calls.push(tail_call)
+        }
+
+        tail_calls
+    }
+
+    // Returns the operation and the other operand if `op` is `call + x` or `call * x`
+    // on integers.
+    fn accumulation(&self, call: InstructionId, op: InstructionId) -> Option<(Opcode, Value)> {
+        let inst = &self.func.inst_table[op];
+        if !matches!(inst.opcode, Opcode::Add | Opcode::Mul)
+            || identity_of(inst.opcode, inst.ty).is_none()
+            || self.func.inst_table[call].users.borrow().len() != 1
+        {
+            return None;
+        }
+        let call_val = self.func.get_inst_value(call);
+        match (*inst.operands[0].as_value(), *inst.operands[1].as_value()) {
+            (x, y) if x == call_val && y != call_val => Some((inst.opcode, y)),
+            (x, y) if y == call_val && x != call_val => Some((inst.opcode, x)),
+            _ => None,
+        }
+    }
+
+    fn is_recursive_call(&self, id: InstructionId) -> bool {
+        let inst = &self.func.inst_table[id];
+        inst.opcode == Opcode::Call
+            && match inst.operands[0].as_value() {
+                Value::Function(f) => Some(f.func_id) == self.func.id,
+                _ => false,
+            }
+    }
+
+    // Adds a block before `header` (the old entry block) to jump to it from, and moves
+    // the allocas there so that they are not executed in every iteration.
+    fn insert_new_entry(&mut self, header: BasicBlockId) -> BasicBlockId {
+        let entry = self.func.append_basic_block_before(header);
+
+        let allocas: Vec<Value> = self.func.basic_blocks.arena[header]
+            .iseq_ref()
+            .iter()
+            .copied()
+            .filter(|v| self.func.inst_table[v.as_instruction().id].opcode == Opcode::Alloca)
+            .collect();
+        for alloca in &allocas {
+            let id = alloca.as_instruction().id;
+            self.func.remove_inst_from_block(id);
+            self.func.inst_table[id].parent = entry;
+        }
+        *self.func.basic_blocks.arena[entry].iseq_ref_mut() = allocas;
+
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point(entry);
+        builder.build_br(header);
+
+        entry
+    }
+
+    fn insert_param_phis(&mut self, header: BasicBlockId, entry: BasicBlockId) -> Vec<Value> {
+        let func_id = self.func.id.unwrap();
+        let params: Vec<Value> = (0..self.func.get_params_len())
+            .map(|index| {
+                Value::Argument(ArgumentValue {
+                    func_id,
+                    index,
+                    ty: self.func.get_param_type(index).unwrap(),
+                })
+            })
+            .collect();
+
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point_at(0, header);
+        let phis: Vec<Value> = params
+            .iter()
+            .map(|&param| builder.build_phi(vec![(param, entry)]))
+            .collect();
+
+        // Every use of a parameter now takes the value of the current iteration.
+        for block in self.func.basic_blocks.order.clone() {
+            let iseq = self.func.basic_blocks.arena[block].iseq_ref().clone();
+            for val in iseq {
+                let id = val.as_instruction().id;
+                if phis.contains(&val) {
+                    continue;
+                }
+                for (param, phi) in params.iter().zip(phis.iter()) {
+                    Instruction::replace_operand(
+                        &mut self.func.inst_table,
+                        id,
+                        &Operand::Value(*param),
+                        Operand::Value(*phi),
+                    );
+                }
+            }
+        }
+
+        phis
+    }
+
+    fn insert_accumulator_phi(
+        &mut self,
+        header: BasicBlockId,
+        entry: BasicBlockId,
+        op: Opcode,
+    ) -> Value {
+        let ty = self.func.get_return_type();
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point_at(0, header);
+        builder.build_phi(vec![(identity_of(op, ty).unwrap(), entry)])
+    }
+
+    fn accumulate_into_returns(&mut self, tail_calls: &[TailCall], op: Opcode, acc: Value) {
+        for block in self.func.basic_blocks.order.clone() {
+            if tail_calls.iter().any(|c| c.block == block) {
+                continue;
+            }
+            let ret = match self.func.basic_blocks.arena[block].iseq_ref().last() {
+                Some(v) => v.as_instruction().id,
+                None => continue,
+            };
+            if self.func.inst_table[ret].opcode != Opcode::Ret {
+                continue;
+            }
+
+            let val = *self.func.inst_table[ret].operands[0].as_value();
+            let mut builder = IRBuilderWithFunction::new(self.func);
+            builder.set_insert_point_before_inst(ret);
+            let new_val = match op {
+                Opcode::Add => builder.build_add(acc, val),
+                _ => builder.build_mul(acc, val),
+            };
+            Instruction::replace_operand(
+                &mut self.func.inst_table,
+                ret,
+                &Operand::Value(val),
+                Operand::Value(new_val),
+            );
+        }
+    }
+
+    // Replaces the call and the return with a branch to `header`.
+    fn eliminate(
+        &mut self,
+        tail_call: TailCall,
+        header: BasicBlockId,
+        params: &[Value],
+        accumulator: Option<(Opcode, Value)>,
+    ) {
+        let TailCall {
+            block,
+            call,
+            accumulate,
+        } = tail_call;
+        let args: Vec<Value> = self.func.inst_table[call].operands[1..]
+            .iter()
+            .map(|op| *op.as_value())
+            .collect();
+
+        let iseq: Vec<InstructionId> = self.func.basic_blocks.arena[block]
+            .iseq_ref()
+            .iter()
+            .map(|v| v.as_instruction().id)
+            .collect();
+        let pos = iseq.iter().position(|&id| id == call).unwrap();
+        for &id in iseq[pos..].iter().rev() {
+            self.func.remove_inst(id);
+        }
+
+        // The accumulated operand was collected before parameters were replaced.
+        let accumulate = accumulate.map(|(op, x)| match x {
+            Value::Argument(a) => (op, params[a.index]),
+            x => (op, x),
+        });
+
+        let mut builder = IRBuilderWithFunction::new(self.func);
+        builder.set_insert_point(block);
+        let acc_next = match (accumulator, accumulate) {
+            (Some((_, acc)), Some((Opcode::Add, x))) => Some((acc, builder.build_add(acc, x))),
+            (Some((_, acc)), Some((_, x))) => Some((acc, builder.build_mul(acc, x))),
+            (Some((_, acc)), None) => Some((acc, acc)),
+            (None, _) => None,
+        };
+        builder.build_br(header);
+
+        for (phi, arg) in params.iter().zip(args.into_iter()) {
+            self.add_incoming(*phi, arg, block);
+        }
+        if let Some((phi, next)) = acc_next {
+            self.add_incoming(phi, next, block);
+        }
+    }
+
+    fn add_incoming(&mut self, phi: Value, val: Value, block: BasicBlockId) {
+        let phi = phi.as_instruction().id;
+        Instruction::add_operand(&mut self.func.inst_table, phi, Operand::Value(val));
+        Instruction::add_operand(&mut self.func.inst_table, phi, Operand::BasicBlock(block));
+    }
+}
+
+fn identity_of(op: Opcode, ty: Type) -> Option<Value> {
+    let i = match op {
+        Opcode::Add => 0,
+        Opcode::Mul => 1,
+        _ => return None,
+    };
+    match ty {
+        Type::i8 => Some(Value::new_imm_int8(i)),
+        Type::i32 => Some(Value::new_imm_int32(i as i32)),
+        _ => None,
+    }
+}
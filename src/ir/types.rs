@@ -3,16 +3,30 @@ use id_arena::*;
 use rustc_hash::FxHashMap;
 use std::convert::From;
 use std::fmt;
-use std::{cell::Ref, cell::RefCell, rc::Rc};
-
+use std::{
+    ops::Deref,
+    sync::{Arc, RwLock, RwLockReadGuard},
+};
+
+/// The type table of a module. Clones share the same table, which can be read from any
+/// thread. Once frozen, no new type can be added, so the ids seen by every thread stay
+/// the same.
 #[derive(Clone)]
 pub struct Types {
-    pub base: Rc<RefCell<TypesBase>>,
+    pub base: Arc<RwLock<TypesBase>>,
 }
 
 #[derive(Clone)]
 pub struct TypesBase {
     pub compound_types: Arena<CompoundType>,
+    frozen: bool,
+}
+
+/// A compound type borrowed from `Types`. The table stays read-locked while this is alive,
+/// so no type can be added until it is dropped.
+pub struct CompoundTypeRef<'a> {
+    base: RwLockReadGuard<'a, TypesBase>,
+    id: CompoundTypeId,
 }
 
 pub type CompoundTypeId = Id<CompoundType>;
@@ -56,6 +70,9 @@ pub struct FunctionType {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct ParamAttribute {
     pub byval: bool,
+
+    /// The argument doesn't alias any pointer that is not derived from it
+    pub noalias: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -75,19 +92,39 @@ pub struct StructType {
 impl Types {
     pub fn new() -> Self {
         Self {
-            base: Rc::new(RefCell::new(TypesBase::new())),
+            base: Arc::new(RwLock::new(TypesBase::new())),
             // compound_types: Arena::new(),
         }
     }
 
     fn new_compound_ty(&self, t: CompoundType) -> CompoundTypeId {
-        let compound_types = &mut self.base.borrow_mut().compound_types;
-        for (id, t_) in &*compound_types {
-            if &t == t_ {
-                return id;
-            }
+        // Looking up an existing type only needs the read lock, which is all a frozen
+        // table ever takes.
+        if let Some((id, _)) = self
+            .base
+            .read()
+            .unwrap()
+            .compound_types
+            .iter()
+            .find(|(_, t_)| *t_ == &t)
+        {
+            return id;
         }
-        compound_types.alloc(t)
+        self.base.write().unwrap().new_compound_ty(t)
+    }
+
+    /// Forbids adding new types. Existing types can still be looked up with `new_*_ty`.
+    pub fn freeze(&self) {
+        self.base.write().unwrap().frozen = true
+    }
+
+    /// Allows adding new types again after `freeze`.
+    pub fn unfreeze(&self) {
+        self.base.write().unwrap().frozen = false
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.base.read().unwrap().frozen
     }
 
     pub fn new_pointer_ty(&self, elem_ty: Type) -> Type {
@@ -107,7 +144,13 @@ impl Types {
                 Type::Struct(_) => {
                     let ptr = self.new_pointer_ty(*ty);
                     *ty = ptr;
-                    params_attr.insert(i, ParamAttribute { byval: true });
+                    params_attr.insert(
+                        i,
+                        ParamAttribute {
+                            byval: true,
+                            ..Default::default()
+                        },
+                    );
                 }
                 _ => {}
             }
@@ -125,16 +168,27 @@ impl Types {
         Type::Struct(id)
     }
 
-    pub fn compound_ty<T: Into<CompoundTypeId>>(&self, id: T) -> Ref<CompoundType> {
-        Ref::map(self.base.borrow(), |x| &x.compound_types[id.into()])
+    /// Returns the function type `fn_ty` with the attribute of its `idx`th parameter
+    /// replaced by `attr`.
+    pub fn with_param_attr(&self, fn_ty: Type, idx: usize, attr: ParamAttribute) -> Type {
+        let mut f = self.compound_ty(fn_ty).as_function().clone();
+        f.params_attr.insert(idx, attr);
+        Type::Function(self.new_compound_ty(CompoundType::Function(f)))
+    }
+
+    pub fn compound_ty<T: Into<CompoundTypeId>>(&self, id: T) -> CompoundTypeRef {
+        CompoundTypeRef {
+            base: self.base.read().unwrap(),
+            id: id.into(),
+        }
     }
 
     pub fn get_element_ty(&self, ty: Type, index: Option<&Value>) -> Option<Type> {
         match ty {
-            Type::Pointer(id) => Some(*self.base.borrow().compound_types[id].as_pointer()),
-            Type::Array(id) => Some(self.base.borrow().compound_types[id].as_array().elem_ty),
+            Type::Pointer(id) => Some(*self.compound_ty(id).as_pointer()),
+            Type::Array(id) => Some(self.compound_ty(id).as_array().elem_ty),
             Type::Struct(id) => Some(
-                self.base.borrow().compound_types[id].as_struct().fields_ty
+                self.compound_ty(id).as_struct().fields_ty
                     [index.unwrap().as_imm().as_int32() as usize],
             ),
             Type::Void
@@ -161,35 +215,35 @@ impl Types {
             | Type::f64
             | Type::Function(_) => None,
             Type::Pointer(id) => match indices.len() {
-                1 => Some(*self.base.borrow().compound_types[id].as_pointer()),
+                1 => Some(*self.compound_ty(id).as_pointer()),
                 _ => {
-                    let elem_ty = *self.base.borrow().compound_types[id].as_pointer();
+                    let elem_ty = *self.compound_ty(id).as_pointer();
                     self.get_element_ty_with_indices(elem_ty, &indices[1..])
                 }
             },
             Type::Array(id) => match indices.len() {
-                1 => Some(self.base.borrow().compound_types[id].as_array().elem_ty),
+                1 => Some(self.compound_ty(id).as_array().elem_ty),
                 _ => {
-                    let elem_ty = self.base.borrow().compound_types[id].as_array().elem_ty;
+                    let elem_ty = self.compound_ty(id).as_array().elem_ty;
                     self.get_element_ty_with_indices(elem_ty, &indices[1..])
                 }
             },
             Type::Struct(id) => match indices.len() {
                 1 => Some(
-                    self.base.borrow().compound_types[id].as_struct().fields_ty
+                    self.compound_ty(id).as_struct().fields_ty
                         [indices[0].as_imm().as_int32() as usize],
                 ),
-                _ => self.get_element_ty_with_indices(
-                    self.base.borrow().compound_types[id].as_struct().fields_ty
-                        [indices[0].as_imm().as_int32() as usize],
-                    &indices[1..],
-                ),
+                _ => {
+                    let field_ty = self.compound_ty(id).as_struct().fields_ty
+                        [indices[0].as_imm().as_int32() as usize];
+                    self.get_element_ty_with_indices(field_ty, &indices[1..])
+                }
             },
         }
     }
 
     pub fn to_string(&self, ty: Type) -> String {
-        self.base.borrow().to_string(ty)
+        self.base.read().unwrap().to_string(ty)
     }
 
     // pub fn get_pointer_ty(&self) -> Type {
@@ -197,10 +251,19 @@ impl Types {
     // }
 }
 
+impl<'a> Deref for CompoundTypeRef<'a> {
+    type Target = CompoundType;
+
+    fn deref(&self) -> &CompoundType {
+        &self.base.compound_types[self.id]
+    }
+}
+
 impl TypesBase {
     pub fn new() -> Self {
         Self {
             compound_types: Arena::new(),
+            frozen: false,
         }
     }
 
@@ -210,6 +273,7 @@ impl TypesBase {
                 return id;
             }
         }
+        assert!(!self.frozen, "can't add a new type to a frozen type table");
         self.compound_types.alloc(t)
     }
 
@@ -230,7 +294,13 @@ impl TypesBase {
                 Type::Struct(_) => {
                     let ptr = self.new_pointer_ty(*ty);
                     *ty = ptr;
-                    params_attr.insert(i, ParamAttribute { byval: true });
+                    params_attr.insert(
+                        i,
+                        ParamAttribute {
+                            byval: true,
+                            ..Default::default()
+                        },
+                    );
                 }
                 _ => {}
             }
@@ -465,6 +535,10 @@ impl StructType {
         self.align
     }
 
+    pub fn fields_ty(&self) -> &[Type] {
+        &self.fields_ty
+    }
+
     pub fn get_elem_offset(&self, i: usize) -> Option<&usize> {
         self.fields_offset.get(i)
     }
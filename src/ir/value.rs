@@ -5,25 +5,27 @@ use super::{
 use std::hash;
 
 macro_rules! const_op {
-    ($name:ident, $op:tt) => {
+    ($name:ident, |$x:ident, $y:ident| $int:expr, $op:tt) => {
     pub fn $name(&self, v: &Value) -> Option<Value> {
         use ImmediateValue::*;
         match (self, v) {
-            (Value::Immediate(Int8(x)), Value::Immediate(Int8(y))) => Some(Value::Immediate(Int8(x $op y))),
-            (Value::Immediate(Int32(x)), Value::Immediate(Int32(y))) => Some(Value::Immediate(Int32(x $op y))),
             (Value::Immediate(F64(x)), Value::Immediate(F64(y))) => Some(Value::Immediate(F64(x $op y))),
-            _ => None,
+            _ => const_op!(@int self, v, |$x, $y| $int),
         }
     } };
-    (int_only $name:ident, $op:tt) => {
+    (int_only $name:ident, |$x:ident, $y:ident| $int:expr) => {
     pub fn $name(&self, v: &Value) -> Option<Value> {
+        const_op!(@int self, v, |$x, $y| $int)
+    } };
+    (@int $v1:expr, $v2:expr, |$x:ident, $y:ident| $int:expr) => {{
         use ImmediateValue::*;
-        match (self, v) {
-            (Value::Immediate(Int8(x)), Value::Immediate(Int8(y))) => Some(Value::Immediate(Int8(x $op y))),
-            (Value::Immediate(Int32(x)), Value::Immediate(Int32(y))) => Some(Value::Immediate(Int32(x $op y))),
+        match ($v1, $v2) {
+            (Value::Immediate(Int8($x)), Value::Immediate(Int8($y))) => { let ($x, $y) = (*$x, *$y); $int.map(|i| Value::Immediate(Int8(i))) }
+            (Value::Immediate(Int32($x)), Value::Immediate(Int32($y))) => { let ($x, $y) = (*$x, *$y); $int.map(|i| Value::Immediate(Int32(i))) }
+            (Value::Immediate(Int64($x)), Value::Immediate(Int64($y))) => { let ($x, $y) = (*$x, *$y); $int.map(|i| Value::Immediate(Int64(i))) }
             _ => None,
         }
-    } }
+    }};
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
@@ -103,6 +105,16 @@ impl Value {
         Self::Immediate(ImmediateValue::F64(f))
     }
 
+    /// Makes an immediate of the integer type `ty`, truncating `i` to fit in it.
+    pub fn new_imm_int(ty: Type, i: i64) -> Self {
+        match ty {
+            Type::i1 | Type::i8 => Self::new_imm_int8(i as i8),
+            Type::i32 => Self::new_imm_int32(i as i32),
+            Type::i64 => Self::Immediate(ImmediateValue::Int64(i)),
+            _ => panic!("not an integer type: {:?}", ty),
+        }
+    }
+
     pub fn new_func(f: FunctionValue) -> Self {
         Self::Function(f)
     }
@@ -139,12 +151,26 @@ impl Value {
     }
 
     // Constant folding
+    // Integers wrap around as they do at run time. Division by zero and overflowing
+    // division are left to run time.
 
-    const_op!(const_add, +);
-    const_op!(const_sub, -);
-    const_op!(const_mul, *);
-    const_op!(const_div, /);
-    const_op!(int_only const_rem, %);
+    const_op!(const_add, |x, y| Some(x.wrapping_add(y)), +);
+    const_op!(const_sub, |x, y| Some(x.wrapping_sub(y)), -);
+    const_op!(const_mul, |x, y| Some(x.wrapping_mul(y)), *);
+    const_op!(const_div, |x, y| x.checked_div(y), /);
+    const_op!(int_only const_rem, |x, y| x.checked_rem(y));
+
+    /// Folds `self opcode v` if `opcode` is a binary arithmetic operation.
+    pub fn const_binary(&self, opcode: Opcode, v: &Value) -> Option<Value> {
+        match opcode {
+            Opcode::Add => self.const_add(v),
+            Opcode::Sub => self.const_sub(v),
+            Opcode::Mul => self.const_mul(v),
+            Opcode::Div => self.const_div(v),
+            Opcode::Rem => self.const_rem(v),
+            _ => None,
+        }
+    }
 
     // Utils
 
@@ -153,7 +179,7 @@ impl Value {
             Value::Argument(ArgumentValue { index, func_id, .. }) => {
                 let f = parent.function_ref(*func_id);
                 let ty = f.get_param_type(*index).unwrap();
-                format!("{} %arg.{}", ty.to_string(), index)
+                format!("{} %{}", parent.types.to_string(ty), f.param_name(*index))
             }
             Value::Immediate(iv) => match iv {
                 ImmediateValue::Int8(i) => format!("i8 {}", i),
@@ -165,9 +191,9 @@ impl Value {
                 let f = parent.function_ref(*func_id);
                 let inst = &f.inst_table[*id];
                 if inst.ty == Type::Void {
-                    format!("    {}", inst.to_string(parent))
+                    format!("    {}", inst.to_string(parent, f))
                 } else {
-                    format!("    %{} = {}", id.index(), inst.to_string(parent))
+                    format!("    %{} = {}", f.inst_name(*id), inst.to_string(parent, f))
                 }
             }
             Value::Instruction(InstructionValue { func_id, id, .. }) => {
@@ -175,7 +201,7 @@ impl Value {
                 format!(
                     "{} %{}",
                     parent.types.to_string(f.inst_table[*id].ty),
-                    id.index()
+                    f.inst_name(*id)
                 )
             }
             Value::Function(FunctionValue { func_id, .. }) if inst => {
@@ -187,7 +213,8 @@ impl Value {
                 let ret_ty = parent
                     .types
                     .base
-                    .borrow()
+                    .read()
+                    .unwrap()
                     .as_function_ty(f.ty)
                     .unwrap()
                     .ret_ty;
@@ -211,6 +238,16 @@ impl Value {
         }
     }
 
+    /// Returns the value of an integer immediate.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Immediate(ImmediateValue::Int8(i)) => Some(*i as i64),
+            Self::Immediate(ImmediateValue::Int32(i)) => Some(*i as i64),
+            Self::Immediate(ImmediateValue::Int64(i)) => Some(*i),
+            _ => None,
+        }
+    }
+
     pub fn as_imm(&self) -> &ImmediateValue {
         match self {
             Value::Immediate(imm) => imm,
@@ -0,0 +1,180 @@
+use crate::ir::{
+    basic_block::BasicBlockId,
+    function::Function,
+    module::Module,
+    opcode::{InstructionId, Opcode, Operand},
+    value::Value,
+};
+use rustc_hash::FxHashSet;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    pub func: String,
+    pub msg: String,
+}
+
+/// Checks the structural invariants the passes rely on: every block ends with its only
+/// terminator, instructions name the block they are in as their parent, pred/succ agree
+/// with branch targets, phis come first and only name predecessors, and operands refer
+/// to instructions still in the function.
+pub fn verify_module(module: &Module) -> Result<(), VerifyError> {
+    for (_, func) in &module.functions {
+        if func.is_internal || func.is_empty() {
+            continue;
+        }
+        verify_function(func)?;
+    }
+    Ok(())
+}
+
+pub fn verify_function(func: &Function) -> Result<(), VerifyError> {
+    FunctionVerifier {
+        func,
+        blocks: func.basic_blocks.order.iter().copied().collect(),
+        insts: FxHashSet::default(),
+    }
+    .run()
+}
+
+struct FunctionVerifier<'a> {
+    func: &'a Function,
+    blocks: FxHashSet<BasicBlockId>,
+    insts: FxHashSet<InstructionId>,
+}
+
+impl<'a> FunctionVerifier<'a> {
+    fn run(mut self) -> Result<(), VerifyError> {
+        for &block_id in &self.func.basic_blocks.order {
+            for val in &*self.func.basic_blocks.arena[block_id].iseq_ref() {
+                self.insts.insert(val.as_instruction().id);
+            }
+        }
+
+        for &block_id in &self.func.basic_blocks.order {
+            self.verify_block(block_id)?;
+        }
+
+        Ok(())
+    }
+
+    fn verify_block(&self, block_id: BasicBlockId) -> Result<(), VerifyError> {
+        let label = self.func.block_label(block_id);
+        let block = &self.func.basic_blocks.arena[block_id];
+        let iseq = block.iseq_ref();
+
+        if iseq.len() == 0 {
+            return self.error(format!("block '{}' is empty", label));
+        }
+
+        let mut targets = FxHashSet::default();
+        let mut in_phis = true;
+
+        for (i, val) in iseq.iter().enumerate() {
+            let id = val.as_instruction().id;
+            let inst = &self.func.inst_table[id];
+            let name = self.func.inst_name(id);
+
+            if inst.parent != block_id {
+                return self.error(format!(
+                    "'%{}' is in block '{}' but its parent is '{}'",
+                    name,
+                    label,
+                    self.func.block_label(inst.parent)
+                ));
+            }
+
+            let is_last = i == iseq.len() - 1;
+            if inst.opcode.is_terminator() != is_last {
+                return self.error(if is_last {
+                    format!("block '{}' doesn't end with a terminator", label)
+                } else {
+                    format!("terminator '%{}' is in the middle of '{}'", name, label)
+                });
+            }
+
+            if inst.opcode == Opcode::Phi {
+                if !in_phis {
+                    return self.error(format!("phi '%{}' follows a non-phi in '{}'", name, label));
+                }
+                for operand in &inst.operands {
+                    if let Operand::BasicBlock(incoming) = operand {
+                        if !block.pred.contains(incoming) {
+                            return self.error(format!(
+                                "phi '%{}' has an incoming block '{}' that is not a predecessor",
+                                name,
+                                self.func.block_label(*incoming)
+                            ));
+                        }
+                    }
+                }
+            } else {
+                in_phis = false;
+            }
+
+            for operand in &inst.operands {
+                match operand {
+                    Operand::Value(Value::Instruction(v)) if !self.insts.contains(&v.id) => {
+                        return self.error(format!(
+                            "'%{}' uses '%{}' which is not in the function",
+                            name,
+                            self.func.inst_name(v.id)
+                        ))
+                    }
+                    Operand::BasicBlock(target) if inst.opcode.is_terminator() => {
+                        targets.insert(*target);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let succ: FxHashSet<BasicBlockId> = block
+            .succ
+            .iter()
+            .copied()
+            .filter(|s| self.blocks.contains(s))
+            .collect();
+        if succ != targets {
+            return self.error(format!(
+                "successors of '{}' don't match its terminator",
+                label
+            ));
+        }
+
+        for &s in &succ {
+            if !self.func.basic_blocks.arena[s].pred.contains(&block_id) {
+                return self.error(format!(
+                    "'{}' is a successor of '{}' but not the other way around",
+                    self.func.block_label(s),
+                    label
+                ));
+            }
+        }
+
+        for &p in block.pred.iter().filter(|p| self.blocks.contains(p)) {
+            if !self.func.basic_blocks.arena[p].succ.contains(&block_id) {
+                return self.error(format!(
+                    "'{}' is a predecessor of '{}' but not the other way around",
+                    self.func.block_label(p),
+                    label
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn error(&self, msg: String) -> Result<(), VerifyError> {
+        Err(VerifyError {
+            func: self.func.name.clone(),
+            msg,
+        })
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "function '{}': {}", self.func, self.msg)
+    }
+}
@@ -121,11 +121,13 @@ macro_rules! fcmp_kind {
 macro_rules! cilk_expr {
 ($builder:expr; $bb_map:expr; $label:ident : $($remain:tt)*) => {
     let bb = *$bb_map.entry(stringify!($label)).or_insert_with(|| $builder.append_basic_block());
+    $builder.set_block_name(bb, stringify!($label));
     $builder.set_insert_point(bb);
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = alloca $ty:ident; $($remain:tt)*) => {
     let $x = $builder.build_alloca(cilk_parse_ty!($builder.func_ref_mut().types, $ty));
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = alloca_ ($($ty:tt)*); $($remain:tt)*) => {
@@ -134,11 +136,13 @@ macro_rules! cilk_expr {
         let ty = cilk_parse_ty!(types, $( $ty )*);
         $builder.build_alloca(ty)
     };
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = load ($($val:tt)*); $($remain:tt)*) => {
     let val = cilk_value!($builder; $( $val )*);
     let $x = $builder.build_load(val);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; store ($($val1:tt)*), ($($val2:tt)*); $($remain:tt)*) => {
@@ -151,42 +155,49 @@ macro_rules! cilk_expr {
     let val1 = cilk_value!($builder; $( $val1 )*);
     let val2 = cilk_value!($builder; $( $val2 )*);
     let $x = $builder.build_add(val1, val2);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = sub ($($val1:tt)*), ($($val2:tt)*); $($remain:tt)*) => {
     let val1 = cilk_value!($builder; $( $val1 )*);
     let val2 = cilk_value!($builder; $( $val2 )*);
     let $x = $builder.build_sub(val1, val2);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = mul ($($val1:tt)*), ($($val2:tt)*); $($remain:tt)*) => {
     let val1 = cilk_value!($builder; $( $val1 )*);
     let val2 = cilk_value!($builder; $( $val2 )*);
     let $x = $builder.build_mul(val1, val2);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = div ($($val1:tt)*), ($($val2:tt)*); $($remain:tt)*) => {
     let val1 = cilk_value!($builder; $( $val1 )*);
     let val2 = cilk_value!($builder; $( $val2 )*);
     let $x = $builder.build_div(val1, val2);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = rem ($($val1:tt)*), ($($val2:tt)*); $($remain:tt)*) => {
     let val1 = cilk_value!($builder; $( $val1 )*);
     let val2 = cilk_value!($builder; $( $val2 )*);
     let $x = $builder.build_rem(val1, val2);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = sext [$($ty:tt)*] ($($val:tt)*); $($remain:tt)*) => {
     let val = cilk_value!($builder; $( $val )*);
     let ty = cilk_parse_ty!($builder.func.module.types, $($ty)*);
     let $x = $builder.build_sext(val, ty);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = gep ($($val:tt)*), [$( ( $($idx:tt)* ) ),*] ; $($remain:tt)*) => {
     let val = cilk_value!($builder; $( $val )*);
     let indices = vec![$( cilk_value!($builder; $( $idx )*) ),*];
     let $x = $builder.build_gep(val, indices);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = phi [$( [ ($($arg:tt)*), $bb:ident ] ),*] ; $($remain:tt)*) => {
@@ -195,6 +206,7 @@ macro_rules! cilk_expr {
                         *$bb_map.entry(stringify!($bb)).or_insert_with(|| $builder.append_basic_block()))
                    ),*];
     let $x = $builder.build_phi(args);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = call $name:ident [$( ( $($arg:tt)* ) ),*] ; $($remain:tt)*) => {
@@ -205,6 +217,7 @@ macro_rules! cilk_expr {
                 func_id: id,
                 ty: $builder.module().unwrap().function_ref(id).ty,
             }}), args);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = call (->$id:expr) [$( ( $($arg:tt)* ) ),*] ; $($remain:tt)*) => {
@@ -213,18 +226,21 @@ macro_rules! cilk_expr {
             let ty = $builder.module().unwrap().function_ref($id).ty;
             value::FunctionValue { func_id: $id, ty}
         }), args);
+        $builder.set_value_name($x, stringify!($x));
         cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = icmp $kind:ident ($($val1:tt)*), ($($val2:tt)*); $($remain:tt)*) => {
     let val1 = cilk_value!($builder; $( $val1 )*);
     let val2 = cilk_value!($builder; $( $val2 )*);
     let $x = $builder.build_icmp(icmp_kind!($kind), val1, val2);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; $x:ident = fcmp $kind:ident ($($val1:tt)*), ($($val2:tt)*); $($remain:tt)*) => {
     let val1 = cilk_value!($builder; $( $val1 )*);
     let val2 = cilk_value!($builder; $( $val2 )*);
     let $x = $builder.build_fcmp(fcmp_kind!($kind), val1, val2);
+    $builder.set_value_name($x, stringify!($x));
     cilk_expr!($builder; $bb_map; $( $remain )*);
 };
 ($builder:expr; $bb_map:expr; br ($($cond:tt)*) $l1:ident, $l2:ident; $($remain:tt)*) => {
@@ -250,7 +266,7 @@ macro_rules! cilk_expr {
 
 #[macro_export]
 macro_rules! cilk_ir {
-    ($m:expr; define [$($ret_ty:tt)*] $name:ident [$(($($arg:tt)*)),*] { $($exp:tt)* }) => {{
+    ($m:expr; define [$($ret_ty:tt)*] $name:ident [$(($($arg:tt)*) $(% $param:ident)?),*] { $($exp:tt)* }) => {{
         use builder::IRBuilder;
         let ret_ty = cilk_parse_ty!($m.types, $($ret_ty)*);
         let args_ty = vec![$( cilk_parse_ty!($m.types, $($arg)*) ),*];
@@ -258,6 +274,16 @@ macro_rules! cilk_ir {
                 stringify!($name), ret_ty, args_ty
             );
         let mut builder = builder::IRBuilderWithModuleAndFuncId::new(&mut $m, f_id);
+        // Named parameters, e.g. `(i32) %n`, can be referred to as `%n` as well as `%arg.0`.
+        let param_names: Vec<Option<&str>> = vec![$( None $( .or(Some(stringify!($param))) )? ),*];
+        $( $(
+            let $param = {
+                let idx = param_names.iter().position(|n| *n == Some(stringify!($param))).unwrap();
+                let param = builder.get_param(idx).unwrap();
+                builder.set_value_name(param, stringify!($param));
+                param
+            };
+        )? )*
         let mut bb_map: FxHashMap<&str, basic_block::BasicBlockId> = FxHashMap::default();
         cilk_expr!(builder; bb_map; $( $exp )*);
         f_id
@@ -1,4 +1,20 @@
-use std::fmt::Debug;
+use crate::{
+    analysis::{
+        call_graph::{CallGraph, CallGraphSCC},
+        manager::{AnalysisManager, PreservedAnalyses},
+    },
+    ir::{
+        function::{Function, FunctionId},
+        module::Module,
+        verifier::{verify_function, verify_module},
+    },
+};
+use rustc_hash::FxHashSet;
+use std::{
+    fmt::Debug,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 pub trait ModulePassTrait {
     type M: Debug;
@@ -6,28 +22,439 @@ pub trait ModulePassTrait {
     fn run_on_module(&mut self, module: &mut Self::M);
 }
 
+/// What a pass manager reports around each pass. Passes are selected by the name they
+/// return from `name()` (case-insensitive), or `all`.
+#[derive(Debug, Clone, Default)]
+pub struct PassInstrumentation {
+    /// Print a table of the time spent in each pass
+    pub time_passes: bool,
+
+    /// Dump the module before running these passes
+    pub print_before: Vec<String>,
+
+    /// Dump the module after running these passes
+    pub print_after: Vec<String>,
+
+    /// Verify the module after every pass and panic naming the pass that broke it. Only
+    /// IR has a verifier; module pass managers without one warn and ignore it.
+    pub verify_after: bool,
+
+    /// Write the Graphviz graph of the module after running these passes
+    pub dot_after: Vec<String>,
+
+    /// Where the graphs go as `<module or function>.<pass index>.<pass>.dot`.
+    /// They are printed to stderr if `None`.
+    pub dot_dir: Option<PathBuf>,
+}
+
+/// Time spent in each pass, summed over all the runs.
+#[derive(Debug, Clone, Default)]
+pub struct PassTimings {
+    entries: Vec<(&'static str, usize, Duration)>,
+}
+
 pub struct ModulePassManager<M: Debug> {
     pub list: Vec<Box<dyn ModulePassTrait<M = M>>>,
+    pub instrumentation: PassInstrumentation,
+    pub timings: PassTimings,
+
+    /// Used when `instrumentation.verify_after` is set
+    pub verifier: Option<fn(&M) -> Result<(), String>>,
+
+    /// Used for `instrumentation.dot_after`
+    pub dot_printer: Option<fn(&M) -> String>,
+}
+
+impl PassInstrumentation {
+    pub fn prints_before(&self, pass: &str) -> bool {
+        Self::selects(&self.print_before, pass)
+    }
+
+    pub fn prints_after(&self, pass: &str) -> bool {
+        Self::selects(&self.print_after, pass)
+    }
+
+    pub fn dots_after(&self, pass: &str) -> bool {
+        Self::selects(&self.dot_after, pass)
+    }
+
+    /// True if any pass would print something. Such output only makes sense when the
+    /// functions are compiled one after another.
+    pub fn has_output(&self) -> bool {
+        self.time_passes
+            || !self.print_before.is_empty()
+            || !self.print_after.is_empty()
+            || !self.dot_after.is_empty()
+    }
+
+    pub fn emit_dot(&self, file: &str, dot: &str) {
+        match &self.dot_dir {
+            Some(dir) => {
+                let path = dir.join(format!("{}.dot", file));
+                if let Err(e) = ::std::fs::write(&path, dot) {
+                    eprintln!("cannot write '{}': {}", path.display(), e)
+                }
+            }
+            None => eprint!("{}", dot),
+        }
+    }
+
+    fn selects(list: &[String], pass: &str) -> bool {
+        list.iter()
+            .any(|name| name == "all" || name.eq_ignore_ascii_case(pass))
+    }
+}
+
+impl PassTimings {
+    pub fn record(&mut self, pass: &'static str, time: Duration) {
+        match self.entries.iter_mut().find(|(name, _, _)| *name == pass) {
+            Some((_, runs, total)) => {
+                *runs += 1;
+                *total += time;
+            }
+            None => self.entries.push((pass, 1, time)),
+        }
+    }
+
+    /// Returns how many times `pass` ran and the time spent in it.
+    pub fn get(&self, pass: &str) -> Option<(usize, Duration)> {
+        self.entries
+            .iter()
+            .find(|(name, _, _)| *name == pass)
+            .map(|&(_, runs, total)| (runs, total))
+    }
+
+    pub fn total(&self) -> Duration {
+        self.entries.iter().map(|&(_, _, total)| total).sum()
+    }
+
+    /// Formats the timings as a table, slowest pass first.
+    pub fn report(&self, title: &str) -> String {
+        let total = self.total().as_secs_f64();
+        let mut entries = self.entries.clone();
+        entries.sort_by(|x, y| y.2.cmp(&x.2));
+
+        let mut s = format!("===== Pass execution timing report: {} =====\n", title);
+        s += &format!("{:>12} {:>7} {:>6}  {}\n", "Time (ms)", "%", "Runs", "Pass");
+        for (name, runs, time) in entries {
+            let time = time.as_secs_f64();
+            s += &format!(
+                "{:>12.3} {:>6.1}% {:>6}  {}\n",
+                time * 1000.0,
+                if total > 0.0 {
+                    time / total * 100.0
+                } else {
+                    0.0
+                },
+                runs,
+                name
+            );
+        }
+        s += &format!(
+            "{:>12.3} {:>6.1}% {:>6}  {}\n",
+            total * 1000.0,
+            100.0,
+            "",
+            "Total"
+        );
+        s
+    }
 }
 
 impl<M: Debug> ModulePassManager<M> {
     pub fn new() -> Self {
-        Self { list: vec![] }
+        Self {
+            list: vec![],
+            instrumentation: PassInstrumentation::default(),
+            timings: PassTimings::default(),
+            verifier: None,
+            dot_printer: None,
+        }
     }
 
     pub fn run_on_module(&mut self, module: &mut M) {
-        for pass in &mut self.list {
-            let now = ::std::time::Instant::now();
+        let kind = ::std::any::type_name::<M>().rsplit("::").next().unwrap();
+
+        if self.instrumentation.verify_after && self.verifier.is_none() {
+            eprintln!(
+                "warning: there is no verifier for {}, so it is not verified after each pass",
+                kind
+            );
+        }
+
+        for (i, pass) in self.list.iter_mut().enumerate() {
+            let name = pass.name();
+            if self.instrumentation.prints_before(name) {
+                eprintln!("*** {} dump before '{}' ***\n{:?}", kind, name, module);
+            }
+
+            let now = Instant::now();
             pass.run_on_module(module);
+            let elapsed = now.elapsed();
+            self.timings.record(name, elapsed);
+            debug!(println!("after pass '{}': {:?}", name, elapsed));
+
+            if self.instrumentation.prints_after(name) {
+                eprintln!("*** {} dump after '{}' ***\n{:?}", kind, name, module);
+            }
+
+            if let Some(dot) = self.dot_printer {
+                if self.instrumentation.dots_after(name) {
+                    self.instrumentation
+                        .emit_dot(&format!("{}.{:02}.{}", kind, i, name), &dot(module));
+                }
+            }
+
+            if self.instrumentation.verify_after {
+                if let Some(Err(e)) = self.verifier.map(|verify| verify(module)) {
+                    panic!("verification failed after pass '{}': {}", name, e)
+                }
+            }
+        }
+
+        if self.instrumentation.time_passes {
+            eprint!("{}", self.timings.report(kind));
+        }
+    }
+
+    pub fn add_pass<A: 'static + ModulePassTrait<M = M>>(&mut self, pass: A) {
+        self.list.push(Box::new(pass))
+    }
+}
+
+pub trait FunctionPassTrait {
+    fn name(&self) -> &'static str;
+    fn run_on_function(
+        &mut self,
+        func: &mut Function,
+        am: &mut AnalysisManager,
+    ) -> PreservedAnalyses;
+}
+
+/// Runs function passes over every function of a module, sharing the analyses they
+/// preserve. The cache is dropped once the module is done since other passes may change
+/// the functions in between.
+pub struct FunctionPassManager {
+    pub list: Vec<Box<dyn FunctionPassTrait>>,
+    pub analysis_manager: AnalysisManager,
+    pub instrumentation: PassInstrumentation,
+    pub timings: PassTimings,
+}
+
+impl FunctionPassManager {
+    pub fn new() -> Self {
+        Self {
+            list: vec![],
+            analysis_manager: AnalysisManager::new(),
+            instrumentation: PassInstrumentation::default(),
+            timings: PassTimings::default(),
+        }
+    }
+
+    pub fn run_on_function(&mut self, module: &mut Module, id: FunctionId) -> PreservedAnalyses {
+        let mut preserved = PreservedAnalyses::all();
+
+        for (i, pass) in self.list.iter_mut().enumerate() {
+            let name = pass.name();
+            if self.instrumentation.prints_before(name) {
+                let func = &module.functions[id];
+                eprintln!(
+                    "*** IR dump before '{}' on '{}' ***\n{}",
+                    name,
+                    func.name,
+                    module.dump(func)
+                );
+            }
+
+            let now = Instant::now();
+            let func = &mut module.functions[id];
+            let p = pass.run_on_function(func, &mut self.analysis_manager);
+            self.analysis_manager.invalidate(func, &p);
+            preserved.intersect(&p);
+            let elapsed = now.elapsed();
+            self.timings.record(name, elapsed);
             debug!(println!(
-                "after pass '{}': {:?}",
-                pass.name(),
-                ::std::time::Instant::now().duration_since(now)
+                "after pass '{}' on '{}': {:?}",
+                name, func.name, elapsed
             ));
+
+            let func = &module.functions[id];
+            if self.instrumentation.prints_after(name) {
+                eprintln!(
+                    "*** IR dump after '{}' on '{}' ***\n{}",
+                    name,
+                    func.name,
+                    module.dump(func)
+                );
+            }
+
+            if self.instrumentation.dots_after(name) {
+                self.instrumentation.emit_dot(
+                    &format!("{}.{:02}.{}", func.name, i, name),
+                    &func.to_dot(module),
+                );
+            }
+
+            if self.instrumentation.verify_after {
+                if let Err(e) = verify_function(func) {
+                    panic!("verification failed after pass '{}': {}", name, e)
+                }
+            }
         }
+
+        preserved
     }
 
-    pub fn add_pass<A: 'static + ModulePassTrait<M = M>>(&mut self, pass: A) {
+    pub fn add_pass<A: 'static + FunctionPassTrait>(&mut self, pass: A) {
         self.list.push(Box::new(pass))
     }
 }
+
+impl ModulePassTrait for FunctionPassManager {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "FunctionPassManager"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        let ids: Vec<FunctionId> = module
+            .functions
+            .iter()
+            .filter(|(_, func)| !func.is_internal && !func.is_empty())
+            .map(|(id, _)| id)
+            .collect();
+
+        for id in ids {
+            self.run_on_function(module, id);
+            self.analysis_manager
+                .invalidate(&mut module.functions[id], &PreservedAnalyses::none());
+        }
+
+        if self.instrumentation.time_passes {
+            eprint!("{}", self.timings.report("IR"));
+        }
+    }
+}
+
+pub trait SCCPassTrait {
+    fn name(&self) -> &'static str;
+
+    /// Returns true if the pass added calls or removed functions, so that the call graph
+    /// must be rebuilt before the next pass.
+    fn run_on_scc(
+        &mut self,
+        module: &mut Module,
+        scc: &CallGraphSCC,
+        call_graph: &CallGraph,
+    ) -> bool;
+}
+
+/// Runs SCC passes over the call graph bottom-up. When a pass visits a function, every
+/// function it calls outside its own SCC has already been visited by all the passes.
+pub struct SCCPassManager {
+    pub list: Vec<Box<dyn SCCPassTrait>>,
+    pub instrumentation: PassInstrumentation,
+    pub timings: PassTimings,
+}
+
+impl SCCPassManager {
+    pub fn new() -> Self {
+        Self {
+            list: vec![],
+            instrumentation: PassInstrumentation::default(),
+            timings: PassTimings::default(),
+        }
+    }
+
+    pub fn add_pass<A: 'static + SCCPassTrait>(&mut self, pass: A) {
+        self.list.push(Box::new(pass))
+    }
+}
+
+impl ModulePassTrait for SCCPassManager {
+    type M = Module;
+
+    fn name(&self) -> &'static str {
+        "SCCPassManager"
+    }
+
+    fn run_on_module(&mut self, module: &mut Self::M) {
+        let mut call_graph = CallGraph::new(module);
+        let mut visited = FxHashSet::default();
+        let mut worklist = call_graph.sccs();
+        worklist.reverse();
+
+        while let Some(scc) = worklist.pop() {
+            visited.extend(scc.functions.iter().copied());
+            let mut rebuilt = false;
+
+            for pass in &mut self.list {
+                let name = pass.name();
+                let now = Instant::now();
+                let changed = pass.run_on_scc(module, &scc, &call_graph);
+                self.timings.record(name, now.elapsed());
+
+                if changed {
+                    call_graph = CallGraph::new(module);
+                    rebuilt = true;
+                }
+
+                if self.instrumentation.verify_after {
+                    if let Err(e) = verify_module(module) {
+                        panic!("verification failed after pass '{}': {}", name, e)
+                    }
+                }
+            }
+
+            // The SCCs left may have been merged or split, and new functions may have
+            // been added, so the rest of the worklist comes from the new call graph.
+            if rebuilt {
+                worklist = call_graph
+                    .sccs()
+                    .into_iter()
+                    .filter(|scc| scc.functions.iter().any(|id| !visited.contains(id)))
+                    .rev()
+                    .collect();
+            }
+        }
+
+        if self.instrumentation.time_passes {
+            eprint!("{}", self.timings.report("SCC"));
+        }
+    }
+}
+
+// Function passes may add calls as well as delete them, e.g. when unrolling a loop
+// calling a function, so the call graph is rebuilt whenever a function changes.
+impl SCCPassTrait for FunctionPassManager {
+    fn name(&self) -> &'static str {
+        "FunctionPassManager"
+    }
+
+    fn run_on_scc(&mut self, module: &mut Module, scc: &CallGraphSCC, _: &CallGraph) -> bool {
+        let mut changed = false;
+        for &id in &scc.functions {
+            let func = &module.functions[id];
+            if func.is_internal || func.is_empty() {
+                continue;
+            }
+            changed |= !self.run_on_function(module, id).is_all();
+            self.analysis_manager
+                .invalidate(&mut module.functions[id], &PreservedAnalyses::none());
+        }
+        changed
+    }
+}
+
+/// Runs a single function pass over every function of a module.
+pub fn run_function_pass_on_module<P: FunctionPassTrait>(pass: &mut P, module: &mut Module) {
+    let mut am = AnalysisManager::new();
+    for (_, func) in &mut module.functions {
+        if func.is_internal || func.is_empty() {
+            continue;
+        }
+        pass.run_on_function(func, &mut am);
+        am.invalidate(func, &PreservedAnalyses::none());
+    }
+}
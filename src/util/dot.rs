@@ -0,0 +1,90 @@
+/// A small builder of Graphviz digraphs.
+///
+/// # Examples
+///
+/// ```
+/// use cilk::util::dot::Dot;
+/// let mut dot = Dot::new("cfg");
+/// dot.node("a", "entry:\nret", "");
+/// dot.node("b", "exit", "");
+/// dot.edge("a", "b", "style=dashed");
+/// let s = dot.finish();
+/// assert!(s.starts_with("digraph \"cfg\" {"));
+/// assert!(s.contains("a -> b [style=dashed];"));
+/// ```
+pub struct Dot {
+    name: String,
+    body: String,
+    indent: usize,
+}
+
+impl Dot {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            body: "".to_string(),
+            indent: 1,
+        }
+    }
+
+    /// Adds a box node whose label lines are left-justified.
+    pub fn node(&mut self, id: &str, label: &str, attrs: &str) {
+        let attrs = if attrs.is_empty() {
+            "".to_string()
+        } else {
+            format!(", {}", attrs)
+        };
+        self.line(&format!(
+            "{} [shape=box, label=\"{}\"{}];",
+            id,
+            escape(label),
+            attrs
+        ))
+    }
+
+    pub fn edge(&mut self, from: &str, to: &str, attrs: &str) {
+        if attrs.is_empty() {
+            self.line(&format!("{} -> {};", from, to))
+        } else {
+            self.line(&format!("{} -> {} [{}];", from, to, attrs))
+        }
+    }
+
+    /// Opens a cluster drawn as a box around the nodes added until `end_cluster()`.
+    pub fn begin_cluster(&mut self, id: &str, label: &str) {
+        self.line(&format!("subgraph cluster_{} {{", id));
+        self.indent += 1;
+        self.line(&format!("label=\"{}\";", escape(label)));
+    }
+
+    pub fn end_cluster(&mut self) {
+        self.indent -= 1;
+        self.line("}")
+    }
+
+    pub fn finish(self) -> String {
+        format!("digraph \"{}\" {{\n{}}}\n", escape(&self.name), self.body)
+    }
+
+    fn line(&mut self, s: &str) {
+        self.body += &format!("{}{}\n", "  ".repeat(self.indent), s)
+    }
+}
+
+/// Escapes `s` for a quoted label. Each line is left-justified.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\l"),
+            '\t' => escaped.push_str("  "),
+            c => escaped.push(c),
+        }
+    }
+    if s.contains('\n') && !s.ends_with('\n') {
+        escaped.push_str("\\l");
+    }
+    escaped
+}
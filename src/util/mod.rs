@@ -1,2 +1,3 @@
 pub mod allocator;
 pub mod count;
+pub mod dot;
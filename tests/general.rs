@@ -1148,4 +1148,2370 @@ main:
         let func = jit.find_function_by_name("main").unwrap();
         assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::F64(24.6));
     }
+
+    #[test]
+    fn value_names() {
+        let mut m = module::Module::new("cilk");
+
+        let func = cilk_ir!(m; define [i32] func [(i32) %n] {
+        entry:
+            i = alloca i32;
+            store (%n), (%i);
+            br loop_;
+        loop_:
+            x = load (%i);
+            x = add (%x), (i32 1);
+            ret (%x);
+        });
+
+        let dump = m.dump(func);
+        assert!(dump.contains("define i32 func(i32 %n)"));
+        assert!(dump.contains("%i = alloca i32"));
+        assert!(dump.contains("store i32 %n, i32* %i"));
+        assert!(dump.contains("%x = load i32* %i"));
+        assert!(dump.contains("%x.1 = add i32 %x, i32 1"));
+        assert!(dump.contains("br %loop_"));
+        assert!(dump.contains("loop_:"));
+    }
+
+    #[test]
+    fn checked_builder() {
+        use cilk::ir::builder::BuildError;
+
+        let mut m = module::Module::new("cilk");
+        let f = m.create_function("f", types::Type::i32, vec![types::Type::i32]);
+        let struct_ty = m
+            .types
+            .new_struct_ty(vec![types::Type::i32, types::Type::f64]);
+
+        let mut builder = builder::IRBuilderWithModuleAndFuncId::new(&mut m, f);
+        assert_eq!(
+            builder.try_build_alloca(types::Type::i32),
+            Err(BuildError::NoInsertPoint)
+        );
+
+        let entry = builder.append_basic_block();
+        builder.set_insert_point(entry);
+
+        let arg = builder.get_param(0).unwrap();
+        let one = value::Value::new_imm_f64(1.0);
+        assert_eq!(
+            builder.try_build_add(arg, one),
+            Err(BuildError::OperandTypeMismatch(
+                opcode::Opcode::Add,
+                types::Type::i32,
+                types::Type::f64
+            ))
+        );
+        assert_eq!(
+            builder.try_build_load(arg),
+            Err(BuildError::NotPointer(
+                opcode::Opcode::Load,
+                types::Type::i32
+            ))
+        );
+
+        let var = builder.try_build_alloca(struct_ty).unwrap();
+        let bad_idx = value::Value::new_imm_int32(2);
+        assert_eq!(
+            builder.try_build_gep(var, vec![value::Value::new_imm_int32(0), bad_idx]),
+            Err(BuildError::InvalidGEPIndex(struct_ty, bad_idx))
+        );
+        let field = builder
+            .try_build_gep(
+                var,
+                vec![
+                    value::Value::new_imm_int32(0),
+                    value::Value::new_imm_int32(0),
+                ],
+            )
+            .unwrap();
+        assert!(builder.try_build_store(one, field).is_err());
+        builder.try_build_store(arg, field).unwrap();
+
+        let x = builder.try_build_load(field).unwrap();
+        builder.try_build_ret(x).unwrap();
+        assert_eq!(
+            builder.try_build_add(x, arg),
+            Err(BuildError::InstAfterTerminator(opcode::Opcode::Add, entry))
+        );
+
+        builder.set_insert_point_at(0, entry);
+        assert_eq!(
+            builder.try_build_ret(x),
+            Err(BuildError::TerminatorInMiddle(opcode::Opcode::Ret, entry))
+        );
+    }
+
+    #[test]
+    fn function_pass_manager() {
+        use cilk::{
+            analysis::manager::AnalysisKind,
+            traits::pass::{FunctionPassManager, ModulePassTrait},
+        };
+
+        let mut m = module::Module::new("cilk");
+
+        cilk_ir!(m; define [i32] func [(i32), (i32)] {
+        entry:
+            i = alloca i32;
+            s = alloca i32;
+            store (i32 0), (%i);
+            store (i32 0), (%s);
+            br cond;
+        cond:
+            li = load (%i);
+            c = icmp lt (%li), (%arg.0);
+            br (%c) body, end;
+        body:
+            x = mul (%arg.1), (i32 3);
+            ls = load (%s);
+            a = add (%ls), (%x);
+            store (%a), (%s);
+            li = load (%i);
+            n = add (%li), (i32 1);
+            store (%n), (%i);
+            br cond;
+        end:
+            ls = load (%s);
+            ret (%ls);
+        });
+
+        let mut pm = FunctionPassManager::new();
+        pm.add_pass(ir::mem2reg::Mem2Reg::new());
+        pm.add_pass(ir::cse::CommonSubexprElimination::new());
+        pm.add_pass(ir::licm::LoopInvariantCodeMotion::new());
+        pm.add_pass(ir::dce::DeadCodeElimination::new());
+        pm.run_on_module(&mut m);
+
+        // mem2reg and cse keep the CFG, so the dominator tree is computed only once.
+        assert_eq!(
+            pm.analysis_manager
+                .num_computed(AnalysisKind::DominatorTree),
+            1
+        );
+        assert_eq!(pm.analysis_manager.num_computed(AnalysisKind::Loops), 1);
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("func").unwrap();
+        assert_eq!(
+            jit.run(
+                func,
+                vec![
+                    exec::jit::GenericValue::Int32(4),
+                    exec::jit::GenericValue::Int32(5)
+                ]
+            ),
+            exec::jit::GenericValue::Int32(60)
+        );
+    }
+
+    #[test]
+    fn pass_instrumentation() {
+        use cilk::{
+            ir::verifier::verify_function,
+            traits::pass::{FunctionPassManager, ModulePassTrait},
+        };
+
+        let mut m = module::Module::new("cilk");
+
+        let func = cilk_ir!(m; define [i32] func [(i32)] {
+        entry:
+            i = alloca i32;
+            store (%arg.0), (%i);
+            li = load (%i);
+            c = icmp eq (%li), (i32 0);
+            br (%c) l1, l2;
+        l1:
+            ret (i32 1);
+        l2:
+            ret (%li);
+        });
+
+        let mut pm = FunctionPassManager::new();
+        pm.instrumentation.time_passes = true;
+        pm.instrumentation.verify_after = true;
+        pm.instrumentation.print_after = vec!["mem2reg".to_string()];
+        pm.add_pass(ir::mem2reg::Mem2Reg::new());
+        pm.add_pass(ir::dce::DeadCodeElimination::new());
+        pm.run_on_module(&mut m);
+
+        assert_eq!(pm.timings.get("Mem2Reg").map(|(runs, _)| runs), Some(1));
+        assert_eq!(
+            pm.timings.get("DeadCodeElimination").map(|(runs, _)| runs),
+            Some(1)
+        );
+        assert!(pm.timings.report("IR").contains("Mem2Reg"));
+
+        assert!(verify_function(m.function_ref(func)).is_ok());
+
+        // An instruction whose parent is not the block it is in
+        let f = m.function_ref_mut(func);
+        let entry = f.get_entry_block().unwrap();
+        let other = f.basic_blocks.order[1];
+        let first = f.basic_block_ref(entry).iseq_ref()[0].as_instruction().id;
+        f.inst_table[first].parent = other;
+        assert!(verify_function(m.function_ref(func))
+            .unwrap_err()
+            .msg
+            .contains("parent"));
+        m.function_ref_mut(func).inst_table[first].parent = entry;
+
+        // A block without a terminator
+        let mut builder = builder::IRBuilderWithModuleAndFuncId::new(&mut m, func);
+        let bb = builder.append_basic_block();
+        builder.set_insert_point(bb);
+        let arg = builder.get_param(0).unwrap();
+        builder.build_add(arg, value::Value::new_imm_int32(1));
+        let err = verify_function(m.function_ref(func)).unwrap_err();
+        assert_eq!(err.func, "func");
+    }
+
+    #[test]
+    fn dot_output() {
+        use cilk::analysis::{dom_tree::DominatorTreeConstructor, loops::LoopsConstructor};
+
+        let mut m = module::Module::new("cilk");
+
+        let func = cilk_ir!(m; define [i32] func [(i32)] {
+        entry:
+            br header;
+        header:
+            c = icmp lt (%arg.0), (i32 10);
+            br (%c) body, exit;
+        body:
+            br header;
+        exit:
+            ret (%arg.0);
+        });
+
+        let f = m.function_ref(func);
+        let cfg = f.to_dot(&m);
+        assert!(cfg.starts_with("digraph \"func\" {"));
+        assert!(cfg.contains("label=T"));
+        assert!(cfg.contains("label=F"));
+        assert!(cfg.contains("header:\\l"));
+
+        let dom_tree = DominatorTreeConstructor::new(&f.basic_blocks).construct();
+        let dot = dom_tree.to_dot("dom", |id| f.block_label(id));
+        assert_eq!(dot.matches(" -> ").count(), 3);
+
+        let loops = LoopsConstructor::new(&dom_tree, &f.basic_blocks).analyze();
+        let dot = loops.to_dot("loops", &f.basic_blocks.order, |id| f.block_label(id));
+        assert_eq!(dot.matches("subgraph cluster_").count(), 1);
+        assert!(dot.contains("(header: header)"));
+    }
+
+    #[test]
+    fn replace_repeated_operand() {
+        use cilk::ir::const_folding::ConstantFolding;
+
+        let mut m = module::Module::new("cilk");
+
+        let func = cilk_ir!(m; define [i32] func [(i32)] {
+        entry:
+            x = add (%arg.0), (i32 1);
+            y = add (%arg.0), (i32 3);
+            z = mul (%x), (%x);
+            ret (%z);
+        });
+
+        // Once `x` is replaced with `y`, `z` uses `y` twice but is one user of it, so
+        // `z` is folded once when `y` is.
+        let f = m.function_ref_mut(func);
+        let entry = f.get_entry_block().unwrap();
+        let inst = |f: &function::Function, i: usize| {
+            f.basic_blocks.arena[entry].iseq_ref()[i]
+                .as_instruction()
+                .id
+        };
+        let (x, y, z) = (inst(f, 0), inst(f, 1), inst(f, 2));
+        let y_val = value::Value::Instruction(value::InstructionValue {
+            func_id: func,
+            id: y,
+            ty: types::Type::i32,
+        });
+        opcode::Instruction::replace_all_uses(&mut f.inst_table, x, opcode::Operand::Value(y_val));
+        f.remove_inst(x);
+        assert_eq!(*f.inst_table[y].users.borrow(), vec![z]);
+
+        f.inst_table[y].operands[0] = opcode::Operand::Value(value::Value::new_imm_int32(2));
+        ConstantFolding::new().run_on_module(&mut m);
+        let f = m.function_ref(func);
+        let ret = &f.inst_table[inst(f, 0)];
+        assert_eq!(ret.opcode, opcode::Opcode::Ret);
+        assert_eq!(*ret.operands[0].as_value(), value::Value::new_imm_int32(25));
+    }
+
+    #[test]
+    fn dom_tree_samedom() {
+        use cilk::analysis::dom_tree::DominatorTreeConstructor;
+
+        let mut m = module::Module::new("cilk");
+
+        // Every block is reachable from the entry through paths avoiding any other
+        // block, so the entry is the immediate dominator of all of them. Whatever order
+        // the successors are visited in, the semidominator of some block isn't its
+        // immediate dominator.
+        let func = cilk_ir!(m; define [void] func [(i32)] {
+        entry:
+            c = icmp lt (%arg.0), (i32 10);
+            br (%c) b, d;
+        a:
+            br (%c) b, c;
+        b:
+            br (%c) a, e;
+        c:
+            br d;
+        d:
+            br (%c) a, e;
+        e:
+            br (%c) b, c;
+        });
+
+        let f = m.function_ref(func);
+        let dom_tree = DominatorTreeConstructor::new(&f.basic_blocks).construct();
+        let entry = f.get_entry_block().unwrap();
+        assert_eq!(dom_tree.children_of(entry).unwrap().len(), 5);
+        for &block in &f.basic_blocks.order[1..] {
+            assert!(dom_tree.children_of(block).map_or(true, |c| c.is_empty()));
+        }
+    }
+
+    #[test]
+    fn parallel_lowering() {
+        use cilk::codegen::{
+            common::pipeline::{OptLevel, PipelineConfig},
+            x64::{
+                asm::print::MachineAsmPrinter, standard_conversion_into_machine_module_with_config,
+            },
+        };
+
+        fn assert_send<T: Send>() {}
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send::<module::Module>();
+        assert_send_sync::<types::Types>();
+
+        fn build() -> module::Module {
+            let mut m = module::Module::new("cilk");
+            cilk_ir!(m; define [i32] fibo [(i32)] {
+                entry:
+                    cond = icmp le (%arg.0), (i32 2);
+                    br (%cond) l1, l2;
+                l1:
+                    ret (i32 1);
+                l2:
+                    a1 = sub (%arg.0), (i32 1);
+                    r1 = call fibo [(%a1)];
+                    a2 = sub (%arg.0), (i32 2);
+                    r2 = call fibo [(%a2)];
+                    r3 = add (%r1), (%r2);
+                    ret (%r3);
+            });
+            cilk_ir!(m; define [f64] fp [] {
+                entry:
+                    a = alloca f64;
+                    store (f64 1.23), (%a);
+                    la = load (%a);
+                    b = add (%la), (f64 2.34);
+                    ret (%b);
+            });
+            cilk_ir!(m; define [i32] main [] {
+                entry:
+                    r = call fibo [(i32 10)];
+                    ret (%r);
+            });
+            m
+        }
+
+        let asm = |threads| {
+            let mut config = PipelineConfig::new(OptLevel::O2);
+            config.threads = threads;
+            let machine_module =
+                standard_conversion_into_machine_module_with_config(build(), &config);
+            let mut printer = MachineAsmPrinter::new();
+            printer.run_on_module(&machine_module);
+            printer.output
+        };
+
+        let sequential = asm(1);
+        assert!(sequential.contains(".Lconst2_0:"));
+        for _ in 0..4 {
+            assert_eq!(asm(3), sequential);
+        }
+
+        let mut config = PipelineConfig::new(OptLevel::O2);
+        config.threads = 3;
+        let machine_module = standard_conversion_into_machine_module_with_config(build(), &config);
+        assert!(!machine_module.types.is_frozen());
+    }
+
+    #[test]
+    fn call_graph_sccs() {
+        use cilk::{
+            analysis::call_graph::{CallGraph, CallGraphNode, CallGraphSCC},
+            ir::opcode::ICmpKind,
+            traits::pass::{ModulePassTrait, SCCPassManager, SCCPassTrait},
+        };
+        use std::{cell::RefCell, rc::Rc};
+
+        let mut m = module::Module::new("cilk");
+
+        let cilk_println_i32 = m.create_function(
+            "cilk.println.i32",
+            ir::types::Type::Void,
+            vec![ir::types::Type::i32],
+        );
+
+        let dec = cilk_ir!(m; define [i32] dec [(i32)] {
+            entry:
+                x = sub (%arg.0), (i32 1);
+                ret (%x);
+        });
+
+        // `odd` is filled in after `even`, which calls it
+        let odd = m.create_function("odd", ir::types::Type::i32, vec![ir::types::Type::i32]);
+
+        let even = cilk_ir!(m; define [i32] even [(i32)] {
+            entry:
+                c = icmp eq (%arg.0), (i32 0);
+                br (%c) zero, nonzero;
+            zero:
+                ret (i32 1);
+            nonzero:
+                x = call (->dec) [(%arg.0)];
+                r = call (->odd) [(%x)];
+                ret (%r);
+        });
+
+        let mut builder = builder::IRBuilderWithModuleAndFuncId::new(&mut m, odd);
+        let entry = builder.append_basic_block();
+        let zero = builder.append_basic_block();
+        let nonzero = builder.append_basic_block();
+        builder.set_insert_point(entry);
+        let arg = builder.get_param(0).unwrap();
+        let c = builder.build_icmp(ICmpKind::Eq, arg, value::Value::new_imm_int32(0));
+        builder.build_cond_br(c, zero, nonzero);
+        builder.set_insert_point(zero);
+        builder.build_ret(value::Value::new_imm_int32(0));
+        builder.set_insert_point(nonzero);
+        let x = builder.build_sub(arg, value::Value::new_imm_int32(1));
+        let even_ty = builder.module().unwrap().function_ref(even).ty;
+        let r = builder.build_call(
+            value::Value::Function(value::FunctionValue {
+                func_id: even,
+                ty: even_ty,
+            }),
+            vec![x],
+        );
+        builder.build_ret(r);
+
+        let main = cilk_ir!(m; define [void] main [] {
+            entry:
+                r = call (->even) [(i32 10)];
+                __ = call (->cilk_println_i32) [(%r)];
+                ret (void);
+        });
+
+        let cg = CallGraph::new(&m);
+        assert_eq!(
+            cg.callees(even),
+            vec![CallGraphNode::Function(dec), CallGraphNode::Function(odd)]
+        );
+        assert_eq!(cg.callers(dec), &[even]);
+        assert_eq!(cg.callers(even), &[odd, main]);
+        assert!(!cg.calls_unknown(main));
+        assert!(!cg.is_address_taken(even));
+
+        let sccs = cg.sccs();
+        assert_eq!(
+            sccs,
+            vec![
+                CallGraphSCC {
+                    functions: vec![cilk_println_i32],
+                    is_recursive: false
+                },
+                CallGraphSCC {
+                    functions: vec![dec],
+                    is_recursive: false
+                },
+                CallGraphSCC {
+                    functions: vec![odd, even],
+                    is_recursive: true
+                },
+                CallGraphSCC {
+                    functions: vec![main],
+                    is_recursive: false
+                },
+            ]
+        );
+
+        struct VisitOrder(Rc<RefCell<Vec<usize>>>);
+
+        impl SCCPassTrait for VisitOrder {
+            fn name(&self) -> &'static str {
+                "VisitOrder"
+            }
+
+            fn run_on_scc(
+                &mut self,
+                _: &mut module::Module,
+                scc: &CallGraphSCC,
+                _: &CallGraph,
+            ) -> bool {
+                self.0.borrow_mut().push(scc.functions.len());
+                false
+            }
+        }
+
+        let order = Rc::new(RefCell::new(vec![]));
+        let mut pm = SCCPassManager::new();
+        pm.add_pass(VisitOrder(order.clone()));
+        pm.run_on_module(&mut m);
+        assert_eq!(*order.borrow(), vec![1, 1, 2, 1]);
+
+        // A pass adding a function makes the SCCs left to visit be recomputed, without
+        // visiting any SCC twice.
+        struct AddFunction(Rc<RefCell<Vec<Vec<ir::function::FunctionId>>>>);
+
+        impl SCCPassTrait for AddFunction {
+            fn name(&self) -> &'static str {
+                "AddFunction"
+            }
+
+            fn run_on_scc(
+                &mut self,
+                m: &mut module::Module,
+                scc: &CallGraphSCC,
+                _: &CallGraph,
+            ) -> bool {
+                self.0.borrow_mut().push(scc.functions.clone());
+                if m.find_function("extra").is_some() {
+                    return false;
+                }
+                m.create_function("extra", ir::types::Type::Void, vec![]);
+                true
+            }
+        }
+
+        let visited = Rc::new(RefCell::new(vec![]));
+        let mut pm = SCCPassManager::new();
+        pm.add_pass(AddFunction(visited.clone()));
+        pm.run_on_module(&mut m);
+        let extra = m.find_function("extra").unwrap();
+        assert_eq!(
+            *visited.borrow(),
+            vec![
+                vec![cilk_println_i32],
+                vec![dec],
+                vec![odd, even],
+                vec![main],
+                vec![extra]
+            ]
+        );
+    }
+
+    #[test]
+    fn scc_pass_manager_function_passes() {
+        use cilk::{
+            analysis::call_graph::{CallGraph, CallGraphSCC},
+            ir::{loop_unroll::LoopUnroll, mem2reg::Mem2Reg},
+            traits::pass::{FunctionPassManager, ModulePassTrait, SCCPassManager, SCCPassTrait},
+        };
+        use std::{cell::RefCell, rc::Rc};
+
+        let mut m = module::Module::new("cilk");
+
+        let g = cilk_ir!(m; define [i32] g [(i32)] {
+        entry:
+            ret (%arg.0);
+        });
+        let f = cilk_ir!(m; define [i32] f [] {
+        entry:
+            i = alloca i32;
+            store (i32 0), (%i);
+            br header;
+        header:
+            li = load (%i);
+            c = icmp lt (%li), (i32 3);
+            br (%c) body, exit;
+        body:
+            x = load (%i);
+            __ = call (->g) [(%x)];
+            x1 = add (%x), (i32 1);
+            store (%x1), (%i);
+            br header;
+        exit:
+            ret (i32 0);
+        });
+
+        // Records the number of call sites of each function in the call graph it's given
+        struct CountCallSites(Rc<RefCell<Vec<usize>>>);
+
+        impl SCCPassTrait for CountCallSites {
+            fn name(&self) -> &'static str {
+                "CountCallSites"
+            }
+
+            fn run_on_scc(
+                &mut self,
+                _: &mut module::Module,
+                scc: &CallGraphSCC,
+                call_graph: &CallGraph,
+            ) -> bool {
+                for &id in &scc.functions {
+                    self.0.borrow_mut().push(call_graph.call_sites(id).len());
+                }
+                false
+            }
+        }
+
+        // Unrolling the loop in `f` copies the call, which the next pass sees.
+        let mut fpm = FunctionPassManager::new();
+        fpm.add_pass(Mem2Reg::new());
+        fpm.add_pass(LoopUnroll::new());
+        let counts = Rc::new(RefCell::new(vec![]));
+        let mut pm = SCCPassManager::new();
+        pm.add_pass(fpm);
+        pm.add_pass(CountCallSites(counts.clone()));
+        pm.run_on_module(&mut m);
+        assert_eq!(*counts.borrow(), vec![0, 3]);
+        assert_eq!(CallGraph::new(&m).callers(g), &[f]);
+    }
+
+    #[test]
+    fn alias_analysis() {
+        use cilk::analysis::alias::{AliasAnalysis, AliasResult, ModRef};
+        use types::{ParamAttribute, Type};
+        use value::{FunctionValue, Value};
+
+        let mut m = module::Module::new("cilk");
+
+        let ptr_i32 = m.types.new_pointer_ty(Type::i32);
+        let ary_ty = m.types.new_array_ty(Type::i32, 16);
+        let memset = m.create_function(
+            "cilk.memset.p0i32.i32",
+            Type::Void,
+            vec![ptr_i32, Type::i32, Type::i32],
+        );
+        let g = m.create_function("g", Type::Void, vec![ptr_i32]);
+        let f = m.create_function("f", Type::Void, vec![ptr_i32, ptr_i32]);
+        m.function_ref_mut(f).set_param_attr(
+            1,
+            ParamAttribute {
+                noalias: true,
+                ..Default::default()
+            },
+        );
+        let memset = Value::Function(FunctionValue {
+            func_id: memset,
+            ty: m.function_ref(memset).ty,
+        });
+        let g = Value::Function(FunctionValue {
+            func_id: g,
+            ty: m.function_ref(g).ty,
+        });
+
+        let mut builder = builder::IRBuilderWithModuleAndFuncId::new(&mut m, f);
+        let entry = builder.append_basic_block();
+        builder.set_insert_point(entry);
+        let i32_ = Value::new_imm_int32;
+        let p = builder.get_param(0).unwrap();
+        let q = builder.get_param(1).unwrap();
+        let a = builder.build_alloca(ary_ty);
+        let b = builder.build_alloca(Type::i32);
+        let c = builder.build_alloca(Type::i32);
+        let a0 = builder.build_gep(a, vec![i32_(0), i32_(0)]);
+        let a1 = builder.build_gep(a, vec![i32_(0), i32_(1)]);
+        let a1_ = builder.build_gep(a, vec![i32_(0), i32_(1)]);
+        let x = builder.build_load(b);
+        let ax = builder.build_gep(a, vec![i32_(0), x]);
+        let memset_call = builder.build_call(memset, vec![a, i32_(0), i32_(16)]);
+        let g_call = builder.build_call(g, vec![c]);
+        let store = builder.build_store(i32_(1), b);
+        builder.build_ret(Value::None);
+
+        let aa = AliasAnalysis::new(m.function_ref(f)).with_module(&m);
+        assert_eq!(aa.alias(&a0, &a1), AliasResult::NoAlias);
+        assert_eq!(aa.alias(&a1, &a1_), AliasResult::MustAlias);
+        assert_eq!(aa.alias(&a0, &ax), AliasResult::MayAlias);
+        assert_eq!(aa.alias(&b, &c), AliasResult::NoAlias);
+        assert_eq!(aa.alias(&b, &p), AliasResult::NoAlias);
+        assert_eq!(aa.alias(&p, &q), AliasResult::NoAlias);
+        assert_eq!(aa.alias(&p, &p), AliasResult::MustAlias);
+
+        let id = |v: Value| v.as_instruction().id;
+        assert_eq!(aa.mod_ref(id(memset_call), &a1), ModRef::Mod);
+        assert_eq!(aa.mod_ref(id(memset_call), &b), ModRef::NoModRef);
+        assert_eq!(aa.mod_ref(id(g_call), &b), ModRef::NoModRef);
+        assert_eq!(aa.mod_ref(id(g_call), &c), ModRef::ModRef);
+        assert_eq!(aa.mod_ref(id(g_call), &p), ModRef::ModRef);
+        assert_eq!(aa.mod_ref(id(store), &b), ModRef::Mod);
+        assert_eq!(aa.mod_ref(id(x), &c), ModRef::NoModRef);
+
+        // A `noalias` argument stored to memory may be loaded back.
+        let ptr_ptr_i32 = m.types.new_pointer_ty(ptr_i32);
+        let h = m.create_function("h", Type::Void, vec![ptr_ptr_i32, ptr_i32]);
+        m.function_ref_mut(h).set_param_attr(
+            1,
+            ParamAttribute {
+                noalias: true,
+                ..Default::default()
+            },
+        );
+        let mut builder = builder::IRBuilderWithModuleAndFuncId::new(&mut m, h);
+        let entry = builder.append_basic_block();
+        builder.set_insert_point(entry);
+        let p = builder.get_param(0).unwrap();
+        let q = builder.get_param(1).unwrap();
+        builder.build_store(q, p);
+        let l = builder.build_load(p);
+        builder.build_ret(Value::None);
+
+        let aa = AliasAnalysis::new(m.function_ref(h)).with_module(&m);
+        assert_eq!(aa.alias(&l, &q), AliasResult::MayAlias);
+    }
+
+    #[test]
+    fn post_dom_tree_and_control_dependence() {
+        use cilk::analysis::{
+            control_dependence::ControlDependenceGraph, manager::AnalysisManager,
+            post_dom_tree::PostDominatorTreeConstructor,
+        };
+
+        let mut m = module::Module::new("cilk");
+
+        let func = cilk_ir!(m; define [i32] func [(i32)] {
+        entry:
+            c = icmp lt (%arg.0), (i32 10);
+            br (%c) left, right;
+        left:
+            br merge;
+        right:
+            c2 = icmp eq (%arg.0), (i32 20);
+            br (%c2) merge, early;
+        early:
+            ret (i32 0);
+        merge:
+            ret (i32 1);
+        });
+
+        let f = m.function_ref_mut(func);
+        let block = |f: &function::Function, label: &str| {
+            *f.basic_blocks
+                .order
+                .iter()
+                .find(|&&id| f.block_label(id) == label)
+                .unwrap()
+        };
+        let (entry, left, right, early, merge) = (
+            block(f, "entry"),
+            block(f, "left"),
+            block(f, "right"),
+            block(f, "early"),
+            block(f, "merge"),
+        );
+
+        let mut am = AnalysisManager::new();
+        let pdt = am.get_post_dom_tree(f);
+        assert_eq!(pdt.ipdom_of(left), Some(merge));
+        assert_eq!(pdt.ipdom_of(right), None);
+        assert_eq!(pdt.ipdom_of(entry), None);
+        assert!(pdt.post_dominate_bb(merge, left));
+        assert!(!pdt.post_dominate_bb(merge, entry));
+        let mut roots = pdt.roots.clone();
+        roots.sort_by_key(|id| id.index());
+        let mut expected = vec![entry, right, early, merge];
+        expected.sort_by_key(|id| id.index());
+        assert_eq!(roots, expected);
+
+        let cdg = ControlDependenceGraph::new(&pdt);
+        assert!(cdg.is_control_dependent(left, entry));
+        assert!(cdg.is_control_dependent(right, entry));
+        assert!(cdg.is_control_dependent(early, right));
+        assert!(cdg.is_control_dependent(merge, entry));
+        assert!(cdg.is_control_dependent(merge, right));
+        assert!(!cdg.is_control_dependent(left, right));
+        assert!(cdg
+            .dependences_of(entry)
+            .map_or(true, |deps| deps.is_empty()));
+        assert_eq!(cdg.dependents_of(right).map(|deps| deps.len()), Some(2));
+
+        // Cached until the CFG changes
+        am.get_post_dom_tree(f);
+        assert_eq!(
+            am.num_computed(cilk::analysis::manager::AnalysisKind::PostDominatorTree),
+            1
+        );
+
+        // A loop without exit still gets a post-dominator tree
+        let func = cilk_ir!(m; define [void] spin [] {
+        entry:
+            br body;
+        body:
+            br body;
+        });
+        let f = m.function_ref(func);
+        let pdt = PostDominatorTreeConstructor::new(&f.basic_blocks).construct();
+        assert_eq!(pdt.roots, vec![block(f, "body")]);
+        assert_eq!(pdt.ipdom_of(block(f, "entry")), Some(block(f, "body")));
+    }
+
+    #[test]
+    fn induction_variables() {
+        use cilk::{
+            analysis::{
+                induction::{SymbolicTripCount, TripCount},
+                manager::AnalysisManager,
+            },
+            ir::opcode::{ICmpKind, Instruction, Operand},
+        };
+
+        // for (i = 0; i < 10; i++) {}
+        let mut m = module::Module::new("cilk");
+        let f = m.create_function("count_up", types::Type::Void, vec![]);
+        let mut builder = builder::IRBuilderWithModuleAndFuncId::new(&mut m, f);
+        let entry = builder.append_basic_block();
+        let header = builder.append_basic_block();
+        let body = builder.append_basic_block();
+        let exit = builder.append_basic_block();
+        builder.set_insert_point(entry);
+        builder.build_br(header);
+        builder.set_insert_point(header);
+        let i = builder.build_phi(vec![(value::Value::new_imm_int32(0), entry)]);
+        let c = builder.build_icmp(ICmpKind::Lt, i, value::Value::new_imm_int32(10));
+        builder.build_cond_br(c, body, exit);
+        builder.set_insert_point(body);
+        let next = builder.build_add(i, value::Value::new_imm_int32(1));
+        builder.build_br(header);
+        builder.set_insert_point(exit);
+        builder.build_ret(value::Value::None);
+        let phi = i.as_instruction().id;
+        let table = &mut builder.func_ref_mut().inst_table;
+        Instruction::add_operand(table, phi, Operand::Value(next));
+        Instruction::add_operand(table, phi, Operand::BasicBlock(body));
+
+        let func = m.function_ref_mut(f);
+        let mut am = AnalysisManager::new();
+        let induction = am.get_induction(func);
+        let l = induction.get_by_header(header).unwrap();
+        assert_eq!(l.preheader, Some(entry));
+        assert_eq!(l.latch, Some(body));
+        assert_eq!(l.exiting, vec![header]);
+        assert_eq!(l.exits, vec![exit]);
+        let iv = l.induction_var_of(phi).unwrap();
+        assert_eq!(iv.start, value::Value::new_imm_int32(0));
+        assert_eq!(iv.step, 1);
+        assert_eq!(iv.next, next.as_instruction().id);
+        assert_eq!(l.constant_trip_count(), Some(10));
+
+        // do { n -= 2; } while (n > 0);
+        let f = m.create_function("count_down", types::Type::Void, vec![types::Type::i32]);
+        let mut builder = builder::IRBuilderWithModuleAndFuncId::new(&mut m, f);
+        let entry = builder.append_basic_block();
+        let header = builder.append_basic_block();
+        let exit = builder.append_basic_block();
+        builder.set_insert_point(entry);
+        builder.build_br(header);
+        builder.set_insert_point(header);
+        let n = builder.get_param(0).unwrap();
+        let j = builder.build_phi(vec![(n, entry)]);
+        let next = builder.build_sub(j, value::Value::new_imm_int32(2));
+        let c = builder.build_icmp(ICmpKind::Gt, next, value::Value::new_imm_int32(0));
+        builder.build_cond_br(c, header, exit);
+        builder.set_insert_point(exit);
+        builder.build_ret(value::Value::None);
+        let phi = j.as_instruction().id;
+        let table = &mut builder.func_ref_mut().inst_table;
+        Instruction::add_operand(table, phi, Operand::Value(next));
+        Instruction::add_operand(table, phi, Operand::BasicBlock(header));
+
+        let func = m.function_ref_mut(f);
+        let induction = am.get_induction(func);
+        let l = induction.get_by_header(header).unwrap();
+        assert_eq!(l.preheader, Some(entry));
+        assert_eq!(l.latch, Some(header));
+        assert_eq!(l.induction_var_of(phi).unwrap().step, -2);
+        // n = 10 runs the body 5 times and takes the back edge 4 times.
+        assert_eq!(
+            l.trip_count,
+            Some(TripCount::Symbolic(SymbolicTripCount {
+                start: n,
+                bound: value::Value::new_imm_int32(0),
+                step: -2,
+                adjust: -2,
+            }))
+        );
+    }
+
+    #[test]
+    fn sccp() {
+        use cilk::ir::{sccp::SparseConditionalConstantPropagation, verifier};
+
+        let mut m = module::Module::new("cilk");
+
+        let func = cilk_ir!(m; define [i32] func [(i32)] {
+        entry:
+            c = icmp eq (i32 3), (i32 3);
+            br (%c) l_then, l_else;
+        l_then:
+            br merge;
+        l_else:
+            r = add (%arg.0), (i32 5);
+            br merge;
+        merge:
+            p = phi [ [(i32 7), l_then], [(%r), l_else] ];
+            x = mul (%p), (i32 6);
+            ret (%x);
+        });
+
+        SparseConditionalConstantPropagation::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        let f = m.function_ref(func);
+        assert_eq!(f.basic_blocks.order.len(), 3);
+        assert!(f
+            .basic_blocks
+            .order
+            .iter()
+            .all(|&id| f.block_label(id) != "l_else"));
+        // Only the branch and the return are left.
+        let num_insts: usize = f
+            .basic_blocks
+            .order
+            .iter()
+            .map(|&id| f.basic_blocks.arena[id].iseq_ref().len())
+            .sum();
+        assert_eq!(num_insts, 3);
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("func").unwrap();
+        let res = jit.run(func, vec![exec::jit::GenericValue::Int32(1)]);
+        assert_eq!(res, exec::jit::GenericValue::Int32(42));
+
+        // `p` is always true, but an i1 can't be replaced by an immediate in `q`. The
+        // backend doesn't support i1 phis, so this one isn't run.
+        let mut m = module::Module::new("cilk");
+        let cond = cilk_ir!(m; define [i32] cond [(i32)] {
+        entry:
+            c = icmp eq (i32 3), (i32 3);
+            br (%c) l_then, l_else;
+        l_then:
+            br merge;
+        l_else:
+            d = icmp eq (%arg.0), (i32 1);
+            br merge;
+        merge:
+            p = phi [ [(%c), l_then], [(%d), l_else] ];
+            e = icmp eq (%arg.0), (i32 2);
+            br (%e) left, right;
+        left:
+            br end;
+        right:
+            br end;
+        end:
+            q = phi [ [(%p), left], [(%e), right] ];
+            br (%q) yes, no;
+        yes:
+            ret (i32 1);
+        no:
+            ret (i32 0);
+        });
+
+        SparseConditionalConstantPropagation::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        let f = m.function_ref(cond);
+        let int8 = opcode::Operand::Value(value::Value::Immediate(value::ImmediateValue::Int8(1)));
+        assert!(f
+            .inst_table
+            .iter()
+            .all(|(_, inst)| !inst.operands.contains(&int8)));
+
+        // i64s are folded as constant folding does, and division by zero is left to run
+        // time.
+        let mut m = module::Module::new("cilk");
+        let wide = cilk_ir!(m; define [i64] wide [] {
+        entry:
+            br next;
+        next:
+            p = phi [ [(i64 4000000000), entry] ];
+            x = mul (%p), (i64 3);
+            d = div (%x), (i64 0);
+            ret (%d);
+        });
+
+        SparseConditionalConstantPropagation::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        let f = m.function_ref(wide);
+        let insts: Vec<&opcode::Instruction> = f
+            .basic_blocks
+            .order
+            .iter()
+            .flat_map(|&id| f.basic_blocks.arena[id].iseq_ref().clone())
+            .map(|v| &f.inst_table[v.as_instruction().id])
+            .collect();
+        let div = insts
+            .iter()
+            .find(|i| i.opcode == opcode::Opcode::Div)
+            .unwrap();
+        assert_eq!(
+            *div.operands[0].as_value(),
+            value::Value::Immediate(value::ImmediateValue::Int64(12000000000))
+        );
+        assert!(insts.iter().all(|i| i.opcode != opcode::Opcode::Mul));
+    }
+
+    #[test]
+    fn gvn() {
+        use cilk::ir::{gvn::GlobalValueNumbering, verifier};
+
+        let mut m = module::Module::new("cilk");
+
+        let func = cilk_ir!(m; define [i32] func [(ptr i32)] {
+        entry:
+            a = load (%arg.0);
+            b = load (%arg.0);
+            x = add (%a), (%b);
+            y = add (%b), (%a);
+            z = add (%y), (i32 0);
+            c = icmp lt (%x), (%z);
+            br (%c) l1, l2;
+        l1:
+            d = load (%arg.0);
+            w = mul (%z), (i32 1);
+            store (i32 1), (%arg.0);
+            e = load (%arg.0);
+            r = add (%w), (%e);
+            r2 = add (%r), (%d);
+            ret (%r2);
+        l2:
+            s = sub (%x), (%y);
+            ret (%s);
+        });
+
+        GlobalValueNumbering::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        let f = m.function_ref(func);
+        let count = |opcode: opcode::Opcode| {
+            f.basic_blocks
+                .order
+                .iter()
+                .map(|&id| {
+                    f.basic_blocks.arena[id]
+                        .iseq_ref()
+                        .iter()
+                        .filter(|v| f.inst_table[v.as_instruction().id].opcode == opcode)
+                        .count()
+                })
+                .sum::<usize>()
+        };
+        // `a` and the load after the store are left.
+        assert_eq!(count(opcode::Opcode::Load), 2);
+        // `x`, `r` and `r2` are left; `y`, `z` and `w` are `x`.
+        assert_eq!(count(opcode::Opcode::Add), 3);
+        assert_eq!(count(opcode::Opcode::Mul), 0);
+        // `x - y` is 0.
+        assert_eq!(count(opcode::Opcode::Sub), 0);
+    }
+
+    #[test]
+    fn inline() {
+        use cilk::ir::{inline::Inliner, verifier};
+
+        let mut m = module::Module::new("cilk");
+
+        cilk_ir!(m; define [i32] add3 [(i32), (i32), (i32)] {
+        entry:
+            r = add (%arg.0), (%arg.1);
+            s = add (%r), (%arg.2);
+            ret (%s);
+        });
+        cilk_ir!(m; define [i32] abs [(i32)] {
+        entry:
+            c = icmp lt (%arg.0), (i32 0);
+            br (%c) neg, pos;
+        neg:
+            n = sub (i32 0), (%arg.0);
+            ret (%n);
+        pos:
+            ret (%arg.0);
+        });
+        cilk_ir!(m; define [i32] twice [(i32)] {
+        entry:
+            p = alloca i32;
+            store (%arg.0), (%p);
+            l = load (%p);
+            r = add (%l), (%l);
+            ret (%r);
+        });
+        let keep = cilk_ir!(m; define [i32] keep [(i32)] {
+        entry:
+            ret (%arg.0);
+        });
+        m.function_ref_mut(keep).attributes.noinline = true;
+        // `x` is a parameter since the backend can't branch on a comparison of constants,
+        // which `abs` would become.
+        let main = cilk_ir!(m; define [i32] main [(i32)] {
+        entry:
+            x = sub (i32 0), (%arg.0);
+            a = call add3 [(i32 1), (i32 2), (i32 3)];
+            b = call abs [(%x)];
+            c = call twice [(%a)];
+            d = call keep [(%b)];
+            r = add (%c), (%d);
+            ret (%r);
+        });
+
+        Inliner::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        let f = m.function_ref(main);
+        let insts: Vec<&opcode::Instruction> = f
+            .basic_blocks
+            .order
+            .iter()
+            .flat_map(|&id| {
+                f.basic_blocks.arena[id]
+                    .iseq_ref()
+                    .iter()
+                    .map(|v| &f.inst_table[v.as_instruction().id])
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        // Only the call to `keep` is left.
+        let calls = insts
+            .iter()
+            .filter(|i| i.opcode == opcode::Opcode::Call)
+            .count();
+        assert_eq!(calls, 1);
+        // The alloca of `twice` is moved to the entry block.
+        let entry = f.basic_blocks.order[0];
+        assert!(insts
+            .iter()
+            .filter(|i| i.opcode == opcode::Opcode::Alloca)
+            .all(|i| i.parent == entry));
+        // The returns of `abs` are merged with a phi.
+        assert!(insts.iter().any(|i| i.opcode == opcode::Opcode::Phi));
+        assert!(m.dump(keep).contains("noinline"));
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        let res = jit.run(func, vec![exec::jit::GenericValue::Int32(5)]);
+        assert_eq!(res, exec::jit::GenericValue::Int32(17));
+    }
+
+    #[test]
+    fn tail_recursion() {
+        use cilk::ir::{tail_recursion::TailRecursionElimination, verifier};
+
+        let mut m = module::Module::new("cilk");
+
+        let fact = cilk_ir!(m; define [i32] fact [(i32)] {
+        entry:
+            c = icmp le (%arg.0), (i32 1);
+            br (%c) base, rec;
+        base:
+            ret (i32 1);
+        rec:
+            n1 = sub (%arg.0), (i32 1);
+            r = call fact [(%n1)];
+            p = mul (%r), (%arg.0);
+            ret (%p);
+        });
+        let sum = cilk_ir!(m; define [i32] sum [(i32), (i32)] {
+        entry:
+            c = icmp eq (%arg.0), (i32 0);
+            br (%c) done, rec;
+        done:
+            ret (%arg.1);
+        rec:
+            a = add (%arg.1), (%arg.0);
+            n1 = sub (%arg.0), (i32 1);
+            r = call sum [(%n1), (%a)];
+            ret (%r);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            f = call fact [(i32 5)];
+            s = call sum [(i32 100), (i32 0)];
+            r = add (%f), (%s);
+            ret (%r);
+        });
+
+        TailRecursionElimination::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        for &id in &[fact, sum] {
+            let f = m.function_ref(id);
+            assert!(f
+                .inst_table
+                .iter()
+                .filter(|(inst_id, _)| f.find_inst_pos(*inst_id).is_some())
+                .all(|(_, i)| i.opcode != opcode::Opcode::Call));
+            // The parameters are phis in the loop the recursion became.
+            assert!(f
+                .inst_table
+                .iter()
+                .filter(|(inst_id, _)| f.find_inst_pos(*inst_id).is_some())
+                .any(|(_, i)| i.opcode == opcode::Opcode::Phi));
+        }
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        let res = jit.run(func, vec![]);
+        assert_eq!(res, exec::jit::GenericValue::Int32(5170));
+    }
+
+    #[test]
+    fn tail_recursion_escaping_alloca() {
+        use cilk::ir::{global_val, tail_recursion::TailRecursionElimination, verifier};
+
+        let mut m = module::Module::new("cilk");
+
+        // The innermost call reads the alloca of its caller through `g`, so the calls
+        // can't share a frame.
+        let ptr_ty = m.types.new_pointer_ty(types::Type::i32);
+        let ptr_ptr_ty = m.types.new_pointer_ty(ptr_ty);
+        let id = m
+            .global_vars
+            .new_global_var_with_name(ptr_ty, global_val::Linkage::Common, "g");
+        let g = value::Value::Global(value::GlobalValue { id, ty: ptr_ptr_ty });
+        let f = cilk_ir!(m; define [i32] f [(i32)] {
+        entry:
+            a = alloca i32;
+            store (%arg.0), (%a);
+            c = icmp eq (%arg.0), (i32 0);
+            br (%c) base, rec;
+        base:
+            p = load (%g);
+            v = load (%p);
+            ret (%v);
+        rec:
+            store (%a), (%g);
+            n1 = sub (%arg.0), (i32 1);
+            r = call f [(%n1)];
+            ret (%r);
+        });
+
+        TailRecursionElimination::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        let func = m.function_ref(f);
+        assert!(func
+            .inst_table
+            .iter()
+            .filter(|(inst_id, _)| func.find_inst_pos(*inst_id).is_some())
+            .any(|(_, i)| i.opcode == opcode::Opcode::Call));
+    }
+
+    #[test]
+    fn sroa() {
+        use cilk::ir::{sroa::ScalarReplacementOfAggregates, verifier};
+
+        let mut m = module::Module::new("cilk");
+
+        let struct_ty = m
+            .types
+            .new_struct_ty(vec![types::Type::i32, types::Type::i32]);
+        let ary_ty = m.types.new_array_ty(types::Type::i32, 4);
+        let f = m.create_function("f", types::Type::i32, vec![struct_ty]);
+        {
+            let mut builder = builder::IRBuilderWithModuleAndFuncId::new(&mut m, f);
+            let entry = builder.append_basic_block();
+            builder.set_insert_point(entry);
+            cilk_ir!((builder) {
+                x = gep (%arg.0), [(i32 0), (i32 0)];
+                load_x = load (%x);
+                y = gep (%arg.0), [(i32 0), (i32 1)];
+                load_y = load (%y);
+                a = add (%load_x), (%load_y);
+                ret (%a);
+            });
+        }
+        let main = m.create_function("main", types::Type::i32, vec![]);
+        {
+            let mut builder = builder::IRBuilderWithModuleAndFuncId::new(&mut m, main);
+            let entry = builder.append_basic_block();
+            builder.set_insert_point(entry);
+            let s = builder.build_alloca(struct_ty);
+            let ary = builder.build_alloca(ary_ty);
+            cilk_ir!((builder) {
+                x = gep (%s), [(i32 0), (i32 0)];
+                store (i32 3), (%x);
+                y = gep (%s), [(i32 0), (i32 1)];
+                store (i32 4), (%y);
+                e = gep (%ary), [(i32 0), (i32 2)];
+                store (i32 5), (%e);
+                load_x = load (%x);
+                load_y = load (%y);
+                load_e = load (%e);
+                r = call f [(%s)];
+                a = add (%load_x), (%load_y);
+                b = add (%a), (%load_e);
+                c = add (%b), (%r);
+                ret (%c);
+            });
+        }
+
+        ScalarReplacementOfAggregates::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        // Only the copy passed to `f` is left in memory.
+        let func = m.function_ref(main);
+        let entry = func.basic_blocks.order[0];
+        let allocas: Vec<types::Type> = func.basic_blocks.arena[entry]
+            .iseq_ref()
+            .iter()
+            .map(|v| &func.inst_table[v.as_instruction().id])
+            .filter(|i| i.opcode == opcode::Opcode::Alloca)
+            .map(|i| *i.operands[0].as_type())
+            .collect();
+        assert_eq!(allocas, vec![struct_ty]);
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(19));
+    }
+
+    #[test]
+    fn dse() {
+        use cilk::ir::{dse::DeadStoreElimination, verifier};
+
+        let mut m = module::Module::new("cilk");
+
+        let f = cilk_ir!(m; define [i32] f [(i32)] {
+        entry:
+            a = alloca_ ([2; i32]);
+            e0 = gep (%a), [(i32 0), (i32 0)];
+            e1 = gep (%a), [(i32 0), (i32 1)];
+            store (i32 1), (%e0);
+            store (i32 2), (%e0);
+            store (i32 3), (%e1);
+            c = icmp eq (%arg.0), (i32 0);
+            br (%c) yes, join;
+        yes:
+            store (i32 5), (%e1);
+            br join;
+        join:
+            x = load (%e0);
+            y = load (%e1);
+            r = add (%x), (%y);
+            ret (%r);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            a = call f [(i32 0)];
+            b = call f [(i32 1)];
+            c = mul (%a), (i32 10);
+            r = add (%c), (%b);
+            ret (%r);
+        });
+
+        DeadStoreElimination::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        // Only `y` can't be forwarded, so only the stores to `e1` are left.
+        let func = m.function_ref(f);
+        let count = |opcode| {
+            func.basic_blocks
+                .order
+                .iter()
+                .flat_map(|&id| {
+                    func.basic_blocks.arena[id]
+                        .iseq_ref()
+                        .iter()
+                        .map(|v| func.inst_table[v.as_instruction().id].opcode)
+                        .collect::<Vec<_>>()
+                })
+                .filter(|&o| o == opcode)
+                .count()
+        };
+        assert_eq!(count(opcode::Opcode::Load), 1);
+        assert_eq!(count(opcode::Opcode::Store), 2);
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(75));
+    }
+
+    #[test]
+    fn simplify_cfg() {
+        use cilk::{
+            codegen::common::pipeline::{OptLevel, PipelineConfig},
+            ir::{function::FunctionId, simplify_cfg::SimplifyCFG, verifier},
+            traits::pass::ModulePassTrait,
+        };
+
+        fn build() -> (module::Module, FunctionId) {
+            let mut m = module::Module::new("cilk");
+            let f = cilk_ir!(m; define [i32] f [(i32)] {
+            entry:
+                br (i8 0) dead, check;
+            dead:
+                ret (i32 100);
+            check:
+                c = icmp le (%arg.0), (i32 10);
+                br (%c) small, big;
+            small:
+                x = add (%arg.0), (i32 1);
+                br hop;
+            hop:
+                br join;
+            big:
+                y = mul (%arg.0), (i32 2);
+                br join;
+            join:
+                p = phi [ [(%x), hop], [(%y), big] ];
+                br exit;
+            exit:
+                ret (%p);
+            });
+            cilk_ir!(m; define [i32] main [] {
+            entry:
+                a = call f [(i32 3)];
+                b = call f [(i32 20)];
+                c = mul (%a), (i32 100);
+                r = add (%c), (%b);
+                ret (%r);
+            });
+            (m, f)
+        }
+
+        let has_select = |m: &module::Module, f| {
+            let func = m.function_ref(f);
+            func.basic_blocks.order.iter().any(|&block| {
+                func.basic_blocks.arena[block].iseq_ref().iter().any(|v| {
+                    func.inst_table[v.as_instruction().id].opcode == opcode::Opcode::Select
+                })
+            })
+        };
+
+        // The diamond is left as it is by default.
+        let (mut m, f) = build();
+        SimplifyCFG::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+        assert_eq!(m.function_ref(f).basic_blocks.order.len(), 4);
+        assert!(!has_select(&m, f));
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(440));
+
+        // Everything ends up in the entry block, with the diamond turned into a select.
+        let (mut m, f) = build();
+        SimplifyCFG::new().with_selects(true).run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+        assert_eq!(m.function_ref(f).basic_blocks.order.len(), 1);
+        assert!(has_select(&m, f));
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(440));
+
+        // The IR pipelines running it
+        for &level in &[OptLevel::O2, OptLevel::Os] {
+            let (mut m, f) = build();
+            let config = PipelineConfig::new(level);
+            assert!(config.ir_passes().contains(&"simplifycfg".to_string()));
+            config.ir_pass_manager().run_on_module(&mut m);
+            verifier::verify_module(&m).unwrap();
+            assert_eq!(m.function_ref(f).basic_blocks.order.len(), 4);
+            assert!(!has_select(&m, f));
+
+            let mut jit = exec::jit::JITExecutor::new(m);
+            let func = jit.find_function_by_name("main").unwrap();
+            assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(440));
+        }
+    }
+
+    #[test]
+    fn loop_unroll() {
+        use cilk::{
+            analysis::{dom_tree::DominatorTreeConstructor, loops::LoopsConstructor},
+            ir::{loop_unroll::LoopUnroll, mem2reg::Mem2Reg, verifier},
+        };
+
+        let mut m = module::Module::new("cilk");
+
+        let squares = cilk_ir!(m; define [i32] squares [] {
+        entry:
+            i = alloca i32;
+            s = alloca i32;
+            store (i32 0), (%i);
+            store (i32 0), (%s);
+            br header;
+        header:
+            li = load (%i);
+            c = icmp lt (%li), (i32 4);
+            br (%c) body, exit;
+        body:
+            x = load (%i);
+            sq = mul (%x), (%x);
+            y = load (%s);
+            z = add (%y), (%sq);
+            store (%z), (%s);
+            x1 = add (%x), (i32 1);
+            store (%x1), (%i);
+            br header;
+        exit:
+            r = load (%s);
+            ret (%r);
+        });
+        let sum = cilk_ir!(m; define [i32] sum [(i32)] {
+        entry:
+            i = alloca i32;
+            s = alloca i32;
+            store (i32 0), (%i);
+            store (i32 0), (%s);
+            br header;
+        header:
+            li = load (%i);
+            c = icmp lt (%li), (%arg.0);
+            br (%c) body, exit;
+        body:
+            x = load (%i);
+            y = load (%s);
+            z = add (%y), (%x);
+            store (%z), (%s);
+            x1 = add (%x), (i32 1);
+            store (%x1), (%i);
+            br header;
+        exit:
+            r = load (%s);
+            ret (%r);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            a = call squares [];
+            b = call sum [(i32 10)];
+            c = call sum [(i32 3)];
+            a1 = mul (%a), (i32 1000);
+            b1 = mul (%b), (i32 10);
+            r1 = add (%a1), (%b1);
+            r = add (%r1), (%c);
+            ret (%r);
+        });
+
+        Mem2Reg::new().run_on_module(&mut m);
+        LoopUnroll::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        // `squares` runs 4 times and is fully unrolled. `sum` gets an unrolled loop and a
+        // remainder loop.
+        let num_loops = |id| {
+            let f = m.function_ref(id);
+            let dom_tree = DominatorTreeConstructor::new(&f.basic_blocks).construct();
+            let loops = LoopsConstructor::new(&dom_tree, &f.basic_blocks).analyze();
+            loops.arena.len()
+        };
+        assert_eq!(num_loops(squares), 0);
+        assert_eq!(num_loops(sum), 2);
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(14453));
+    }
+
+    #[test]
+    fn loop_unroll_near_max() {
+        use cilk::ir::{loop_unroll::LoopUnroll, mem2reg::Mem2Reg, opcode::Opcode, verifier};
+
+        let mut m = module::Module::new("cilk");
+
+        // for (i = s; i < n; i++) k++;
+        let up = cilk_ir!(m; define [i32] up [(i32), (i32)] {
+        entry:
+            i = alloca i32;
+            k = alloca i32;
+            store (%arg.0), (%i);
+            store (i32 0), (%k);
+            br header;
+        header:
+            li = load (%i);
+            c = icmp lt (%li), (%arg.1);
+            br (%c) body, exit;
+        body:
+            x = load (%i);
+            y = load (%k);
+            y1 = add (%y), (i32 1);
+            store (%y1), (%k);
+            x1 = add (%x), (i32 1);
+            store (%x1), (%i);
+            br header;
+        exit:
+            r = load (%k);
+            ret (%r);
+        });
+        // for (i = s; i > n; i--) k++;
+        cilk_ir!(m; define [i32] down [(i32), (i32)] {
+        entry:
+            i = alloca i32;
+            k = alloca i32;
+            store (%arg.0), (%i);
+            store (i32 0), (%k);
+            br header;
+        header:
+            li = load (%i);
+            c = icmp lt (%arg.1), (%li);
+            br (%c) body, exit;
+        body:
+            x = load (%i);
+            y = load (%k);
+            y1 = add (%y), (i32 1);
+            store (%y1), (%k);
+            x1 = sub (%x), (i32 1);
+            store (%x1), (%i);
+            br header;
+        exit:
+            r = load (%k);
+            ret (%r);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            a = call up [(i32 2147483637), (i32 2147483647)];
+            b = call up [(i32 0), (i32 10)];
+            c = call up [(i32 -2147483648), (i32 -2147483647)];
+            d = call down [(i32 -2147483638), (i32 -2147483648)];
+            a1 = mul (%a), (i32 1000000);
+            b1 = mul (%b), (i32 10000);
+            c1 = mul (%c), (i32 100);
+            r1 = add (%a1), (%b1);
+            r2 = add (%r1), (%c1);
+            r = add (%r2), (%d);
+            ret (%r);
+        });
+
+        Mem2Reg::new().run_on_module(&mut m);
+        LoopUnroll::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        // The unrolled loop compares the induction variable itself with `n - 3`.
+        {
+            let func = m.function_ref(up);
+            let adds = func
+                .inst_table
+                .iter()
+                .filter(|(_, inst)| inst.opcode == Opcode::Add)
+                .count();
+            let subs = func
+                .inst_table
+                .iter()
+                .filter(|(_, inst)| inst.opcode == Opcode::Sub)
+                .count();
+            assert_eq!(adds, 2 * 5);
+            assert_eq!(subs, 1);
+        }
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(
+            jit.run(func, vec![]),
+            exec::jit::GenericValue::Int32(10100110)
+        );
+    }
+
+    #[test]
+    fn loop_rotate() {
+        use cilk::{
+            analysis::{dom_tree::DominatorTreeConstructor, loops::LoopsConstructor},
+            ir::{
+                lcssa::LoopClosedSSA, loop_rotate::LoopRotate, mem2reg::Mem2Reg, opcode::Opcode,
+                verifier,
+            },
+        };
+
+        let mut m = module::Module::new("cilk");
+
+        let sum = cilk_ir!(m; define [i32] sum [(i32)] {
+        entry:
+            i = alloca i32;
+            s = alloca i32;
+            store (i32 0), (%i);
+            store (i32 0), (%s);
+            br header;
+        header:
+            li = load (%i);
+            c = icmp lt (%li), (%arg.0);
+            br (%c) body, exit;
+        body:
+            x = load (%i);
+            y = load (%s);
+            z = add (%y), (%x);
+            store (%z), (%s);
+            x1 = add (%x), (i32 1);
+            store (%x1), (%i);
+            br header;
+        exit:
+            r = load (%s);
+            ret (%r);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            a = call sum [(i32 10)];
+            b = call sum [(i32 0)];
+            a1 = mul (%a), (i32 10);
+            r = add (%a1), (%b);
+            ret (%r);
+        });
+
+        let block = |f: &function::Function, label: &str| {
+            *f.basic_blocks
+                .order
+                .iter()
+                .find(|&&id| f.block_label(id) == label)
+                .unwrap()
+        };
+
+        Mem2Reg::new().run_on_module(&mut m);
+        LoopClosedSSA::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        // The sum leaves the loop through a phi in the exit.
+        {
+            let f = m.function_ref(sum);
+            let exit = block(f, "exit");
+            let ret = *f.basic_blocks.arena[exit].iseq_ref().last().unwrap();
+            let ret = &f.inst_table[ret.as_instruction().id];
+            let s = ret.operands[0].as_value().as_instruction().id;
+            assert_eq!(f.inst_table[s].opcode, Opcode::Phi);
+            assert_eq!(f.inst_table[s].parent, exit);
+        }
+
+        LoopRotate::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        // The entry guards the loop, which now starts at the body and is tested at the
+        // bottom.
+        {
+            let f = m.function_ref(sum);
+            let entry = block(f, "entry");
+            let br = *f.basic_blocks.arena[entry].iseq_ref().last().unwrap();
+            assert_eq!(f.inst_table[br.as_instruction().id].opcode, Opcode::CondBr);
+            let dom_tree = DominatorTreeConstructor::new(&f.basic_blocks).construct();
+            let loops = LoopsConstructor::new(&dom_tree, &f.basic_blocks).analyze();
+            assert_eq!(loops.arena.len(), 1);
+            let (_, loop_) = loops.arena.iter().next().unwrap();
+            assert_eq!(f.block_label(loop_.header), "body");
+        }
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(450));
+    }
+
+    #[test]
+    fn loop_strength_reduce() {
+        use cilk::{
+            analysis::{dom_tree::DominatorTreeConstructor, loops::LoopsConstructor},
+            ir::{
+                loop_strength_reduce::LoopStrengthReduction, mem2reg::Mem2Reg, opcode::Opcode,
+                verifier,
+            },
+        };
+
+        let mut m = module::Module::new("cilk");
+
+        // for (i = 0; i < n; i++) a[i] = i * 3;
+        // for (i = 0; i < n; i++) s += a[i];
+        let f = cilk_ir!(m; define [i32] f [(i32)] {
+        entry:
+            a = alloca_ ([16; i32]);
+            i = alloca i32;
+            s = alloca i32;
+            store (i32 0), (%i);
+            store (i32 0), (%s);
+            br fill;
+        fill:
+            li = load (%i);
+            c = icmp lt (%li), (%arg.0);
+            br (%c) fill_body, fill_exit;
+        fill_body:
+            x = load (%i);
+            v = mul (%x), (i32 3);
+            p = gep (%a), [(i32 0), (%x)];
+            store (%v), (%p);
+            x1 = add (%x), (i32 1);
+            store (%x1), (%i);
+            br fill;
+        fill_exit:
+            store (i32 0), (%i);
+            br sum;
+        sum:
+            lj = load (%i);
+            d = icmp lt (%lj), (%arg.0);
+            br (%d) sum_body, exit;
+        sum_body:
+            y = load (%i);
+            q = gep (%a), [(i32 0), (%y)];
+            w = load (%q);
+            t = load (%s);
+            t1 = add (%t), (%w);
+            store (%t1), (%s);
+            y1 = add (%y), (i32 1);
+            store (%y1), (%i);
+            br sum;
+        exit:
+            r = load (%s);
+            ret (%r);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            a = call f [(i32 10)];
+            b = call f [(i32 16)];
+            c = call f [(i32 0)];
+            a1 = mul (%a), (i32 1000);
+            r1 = add (%a1), (%b);
+            r = add (%r1), (%c);
+            ret (%r);
+        });
+
+        Mem2Reg::new().run_on_module(&mut m);
+        LoopStrengthReduction::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        // The loops only increase the new phis, and the counters are gone.
+        {
+            let func = m.function_ref(f);
+            let dom_tree = DominatorTreeConstructor::new(&func.basic_blocks).construct();
+            let loops = LoopsConstructor::new(&dom_tree, &func.basic_blocks).analyze();
+            assert_eq!(loops.arena.len(), 2);
+            for (_, loop_) in &loops.arena {
+                for &block in func.basic_blocks.order.iter().filter(|b| loop_.contains(b)) {
+                    for val in &*func.basic_blocks.arena[block].iseq_ref() {
+                        let inst = &func.inst_table[val.as_instruction().id];
+                        assert_ne!(inst.opcode, Opcode::Mul);
+                        if inst.opcode == Opcode::GetElementPtr {
+                            assert!(inst.operands[1..]
+                                .iter()
+                                .all(|op| matches!(op.as_value(), value::Value::Immediate(_))));
+                        }
+                    }
+                }
+                let phis = func.basic_blocks.arena[loop_.header]
+                    .iseq_ref()
+                    .iter()
+                    .filter(|val| func.inst_table[val.as_instruction().id].opcode == Opcode::Phi)
+                    .count();
+                assert_eq!(phis, 2);
+            }
+        }
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(
+            jit.run(func, vec![]),
+            exec::jit::GenericValue::Int32(135360)
+        );
+    }
+
+    #[test]
+    fn loop_strength_reduce_keeps_scaled_counter() {
+        use cilk::ir::{
+            loop_strength_reduce::LoopStrengthReduction, mem2reg::Mem2Reg, opcode::Opcode, verifier,
+        };
+
+        let mut m = module::Module::new("cilk");
+
+        // for (i = 0; i < n; i++) { v = i * 1000000; k++; }
+        let f = cilk_ir!(m; define [i32] f [(i32)] {
+        entry:
+            i = alloca i32;
+            k = alloca i32;
+            store (i32 0), (%i);
+            store (i32 0), (%k);
+            br header;
+        header:
+            li = load (%i);
+            c = icmp lt (%li), (%arg.0);
+            br (%c) body, exit;
+        body:
+            x = load (%i);
+            v = mul (%x), (i32 1000000);
+            y = load (%k);
+            y1 = add (%y), (i32 1);
+            store (%y1), (%k);
+            x1 = add (%x), (i32 1);
+            store (%x1), (%i);
+            br header;
+        exit:
+            r = load (%k);
+            ret (%r);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            a = call f [(i32 2148)];
+            ret (%a);
+        });
+
+        Mem2Reg::new().run_on_module(&mut m);
+        LoopStrengthReduction::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        // `n * 1000000` wraps, so the exit test still compares the counter with `n`.
+        {
+            let func = m.function_ref(f);
+            let cmp = func
+                .inst_table
+                .iter()
+                .map(|(_, inst)| inst)
+                .find(|inst| inst.opcode == Opcode::ICmp)
+                .unwrap();
+            assert!(matches!(
+                cmp.operands[2].as_value(),
+                value::Value::Argument(_)
+            ));
+        }
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(2148));
+    }
+
+    #[test]
+    fn reassociate() {
+        use cilk::ir::{cse::CommonSubexprElimination, reassociate::Reassociate, verifier};
+
+        let mut m = module::Module::new("cilk");
+
+        // (1 + x) + (y + 2) and y + x share x + y once reassociated.
+        let f = cilk_ir!(m; define [i32] f [(i32), (i32)] {
+        entry:
+            t = add (i32 1), (%arg.0);
+            u = add (%arg.1), (i32 2);
+            r = add (%t), (%u);
+            v = add (%arg.1), (%arg.0);
+            w = mul (%r), (%v);
+            ret (%w);
+        });
+        let g = cilk_ir!(m; define [f64] g [(f64)] {
+        entry:
+            a = add (%arg.0), (f64 1.0);
+            b = add (%a), (f64 2.0);
+            ret (%b);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            a = call f [(i32 2), (i32 5)];
+            ret (%a);
+        });
+
+        let num_insts = |m: &module::Module, id| {
+            let func = m.function_ref(id);
+            func.basic_blocks
+                .order
+                .iter()
+                .map(|&block| func.basic_blocks.arena[block].iseq_ref().len())
+                .sum::<usize>()
+        };
+
+        Reassociate::new().run_on_module(&mut m);
+        CommonSubexprElimination::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+        assert_eq!(num_insts(&m, f), 4);
+
+        // Floating-point operations are left alone unless fast-math is allowed.
+        assert_eq!(num_insts(&m, g), 3);
+        Reassociate::new()
+            .with_fast_math(true)
+            .run_on_module(&mut m);
+        assert_eq!(num_insts(&m, g), 2);
+        {
+            let func = m.function_ref(g);
+            let entry = func.basic_blocks.order[0];
+            let add = func.basic_blocks.arena[entry].iseq_ref()[0];
+            let add = &func.inst_table[add.as_instruction().id];
+            assert!(add
+                .operands
+                .contains(&opcode::Operand::Value(value::Value::Immediate(
+                    value::ImmediateValue::F64(3.0)
+                ))));
+        }
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(70));
+    }
+
+    #[test]
+    fn global_dce() {
+        use cilk::ir::{global_dce::GlobalDeadCodeElimination, global_val, verifier};
+
+        let mut m = module::Module::new("cilk");
+        cilk_ir!(m; define [i32] used [(i32)] {
+        entry:
+            a = add (%arg.0), (i32 1);
+            ret (%a);
+        });
+        cilk_ir!(m; define [i32] unused [] {
+        entry:
+            a = call used [(i32 1)];
+            ret (%a);
+        });
+        cilk_ir!(m; define [i32] exported [] {
+        entry:
+            ret (i32 7);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            a = call used [(i32 41)];
+            ret (%a);
+        });
+
+        GlobalDeadCodeElimination::new()
+            .with_export("exported")
+            .run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        // The functions move to new ids, which they and the call in `main` refer to.
+        let names: Vec<&str> = m.functions.iter().map(|(_, f)| f.name.as_str()).collect();
+        assert_eq!(names, vec!["used", "exported", "main"]);
+        for (id, f) in &m.functions {
+            assert_eq!(f.id, Some(id));
+        }
+        {
+            let main = m.find_function("main").unwrap();
+            let f = m.function_ref(main);
+            let call = f.basic_blocks.arena[f.get_entry_block().unwrap()].iseq_ref()[0];
+            let call = &f.inst_table[call.as_instruction().id];
+            match call.operands[0].as_value() {
+                value::Value::Function(callee) => {
+                    assert_eq!(Some(callee.func_id), m.find_function("used"))
+                }
+                v => panic!("{:?}", v),
+            }
+            assert_eq!(
+                f.basic_blocks.arena[f.get_entry_block().unwrap()].iseq_ref()[1]
+                    .as_instruction()
+                    .func_id,
+                main
+            );
+        }
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(42));
+
+        // Global variables and constants are kept while a live function refers to them.
+        let mut m = module::Module::new("cilk");
+        let ty = m.types.new_array_ty(types::Type::i32, 4);
+        let ptr_ty = m.types.new_pointer_ty(ty);
+        let global = |m: &mut module::Module, linkage, name| {
+            let id = m.global_vars.new_global_var_with_name(ty, linkage, name);
+            value::Value::Global(value::GlobalValue { id, ty: ptr_ty })
+        };
+        let g = global(&mut m, global_val::Linkage::Common, "g");
+        let dead_g = global(&mut m, global_val::Linkage::Common, "dead_g");
+        global(&mut m, global_val::Linkage::External, "ext");
+        let dead_s = m.create_string("dead".to_string());
+        let s = m.create_string("live".to_string());
+        cilk_ir!(m; define [i32] dead [] {
+        entry:
+            p = gep (%dead_g), [(i32 0), (i32 0)];
+            store (i32 1), (%p);
+            q = gep (%dead_s), [(i32 0), (i32 0)];
+            ret (i32 0);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            p = gep (%g), [(i32 0), (i32 1)];
+            store (i32 1), (%p);
+            q = gep (%s), [(i32 0), (i32 0)];
+            ret (i32 0);
+        });
+
+        GlobalDeadCodeElimination::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        let names: Vec<&str> = m
+            .global_vars
+            .arena
+            .iter()
+            .map(|(_, g)| g.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["g", "ext"]);
+        assert_eq!(m.const_pool.arena.len(), 1);
+        let main = m.find_function("main").unwrap();
+        let f = m.function_ref(main);
+        let q = f.basic_blocks.arena[f.get_entry_block().unwrap()].iseq_ref()[2];
+        let q = &f.inst_table[q.as_instruction().id];
+        match q.operands[0].as_value() {
+            value::Value::Constant(c) => assert!(m.const_pool.arena.get(c.id).is_some()),
+            v => panic!("{:?}", v),
+        }
+        assert_eq!(m.functions.len(), 1);
+    }
+
+    #[test]
+    fn ipcp() {
+        use cilk::ir::{ipcp::InterproceduralConstantPropagation, opcode::Opcode, verifier};
+
+        fn build() -> module::Module {
+            let mut m = module::Module::new("cilk");
+            cilk_ir!(m; define [i32] scale [(i32), (i32)] {
+            entry:
+                a = mul (%arg.0), (%arg.1);
+                ret (%a);
+            });
+            // add(x, y) = x + y * y
+            cilk_ir!(m; define [i32] add [(i32), (i32)] {
+            entry:
+                y = mul (%arg.1), (%arg.1);
+                a = add (%arg.0), (%y);
+                ret (%a);
+            });
+            cilk_ir!(m; define [i32] sub [(i32), (i32)] {
+            entry:
+                a = sub (%arg.0), (%arg.1);
+                ret (%a);
+            });
+            // for (i = 0; i < n; i++) s = sub(add(s, 2), 1);
+            cilk_ir!(m; define [i32] sum [(i32)] {
+            entry:
+                i = alloca i32;
+                s = alloca i32;
+                store (i32 0), (%i);
+                store (i32 0), (%s);
+                br head;
+            head:
+                li = load (%i);
+                c = icmp lt (%li), (%arg.0);
+                br (%c) body, exit;
+            body:
+                ls = load (%s);
+                t = call add [(%ls), (i32 2)];
+                u = call sub [(%t), (i32 1)];
+                store (%u), (%s);
+                x = load (%i);
+                x1 = add (%x), (i32 1);
+                store (%x1), (%i);
+                br head;
+            exit:
+                r = load (%s);
+                ret (%r);
+            });
+            cilk_ir!(m; define [i32] main [] {
+            entry:
+                a = call sum [(i32 5)];
+                b = call add [(i32 1), (%a)];
+                a1 = call sub [(%b), (i32 2)];
+                c = call scale [(i32 3), (i32 10)];
+                d = call scale [(i32 4), (i32 10)];
+                e = mul (%a1), (i32 100);
+                f = add (%e), (%c);
+                g = add (%f), (%d);
+                ret (%g);
+            });
+            m
+        }
+
+        let params_len = |m: &module::Module, name: &str| {
+            m.function_ref(m.find_function(name).unwrap())
+                .get_params_len()
+        };
+        let callees_in_sum = |m: &module::Module| {
+            let sum = m.function_ref(m.find_function("sum").unwrap());
+            sum.basic_blocks
+                .order
+                .iter()
+                .flat_map(|&b| sum.basic_blocks.arena[b].iseq_ref().clone())
+                .map(|v| &sum.inst_table[v.as_instruction().id])
+                .filter(|inst| inst.opcode == Opcode::Call)
+                .map(|inst| match inst.operands[0].as_value() {
+                    value::Value::Function(f) => m.function_ref(f.func_id).name.clone(),
+                    v => panic!("{:?}", v),
+                })
+                .collect::<Vec<String>>()
+        };
+
+        let mut m = build();
+        InterproceduralConstantPropagation::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        // `scale` always gets 10 and `sum` 5, while `add` and `sub` get different
+        // arguments.
+        assert_eq!(params_len(&m, "scale"), 1);
+        assert_eq!(params_len(&m, "sum"), 0);
+        assert_eq!(params_len(&m, "add"), 2);
+        assert_eq!(params_len(&m, "sub"), 2);
+
+        // The call to `add` in the loop gets a clone of it adding 4. Nothing folds in a
+        // clone of `sub`, so the loop still calls `sub`.
+        assert_eq!(params_len(&m, "add.specialized.1"), 1);
+        assert!(m.find_function("sub.specialized.1").is_none());
+        assert_eq!(callees_in_sum(&m), vec!["add.specialized.1", "sub"]);
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(22470));
+
+        // No clones are made past the limit.
+        let mut m = build();
+        InterproceduralConstantPropagation::new()
+            .with_max_clones(0)
+            .run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+        assert!(m.find_function("add.specialized.1").is_none());
+        assert_eq!(callees_in_sum(&m), vec!["add", "sub"]);
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(22470));
+    }
+
+    #[test]
+    fn pre() {
+        use cilk::ir::{opcode::Opcode, pre::PartialRedundancyElimination, verifier};
+
+        let mut m = module::Module::new("cilk");
+
+        // r = c < 1 ? x * y : 1; return r + x * y;
+        let f = cilk_ir!(m; define [i32] f [(i32), (i32), (i32)] {
+        entry:
+            c = icmp lt (%arg.2), (i32 1);
+            br (%c) then, join;
+        then:
+            a = mul (%arg.0), (%arg.1);
+            br join;
+        join:
+            r = phi [ [(%a), then], [(i32 1), entry] ];
+            b = mul (%arg.0), (%arg.1);
+            s = add (%r), (%b);
+            ret (%s);
+        });
+        // r = c < 1 ? x * y : 1; if (c < 2) r += x * y; return r;
+        let g = cilk_ir!(m; define [i32] g [(i32), (i32), (i32)] {
+        entry:
+            c = icmp lt (%arg.2), (i32 1);
+            br (%c) then, join;
+        then:
+            a = mul (%arg.0), (%arg.1);
+            br join;
+        join:
+            r = phi [ [(%a), then], [(i32 1), entry] ];
+            d = icmp lt (%arg.2), (i32 2);
+            br (%d) more, exit;
+        more:
+            b = mul (%arg.0), (%arg.1);
+            s = add (%r), (%b);
+            br exit;
+        exit:
+            t = phi [ [(%s), more], [(%r), join] ];
+            ret (%t);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            a = call f [(i32 3), (i32 4), (i32 0)];
+            b = call f [(i32 3), (i32 4), (i32 5)];
+            c = call g [(i32 3), (i32 4), (i32 1)];
+            x = mul (%a), (i32 100);
+            y = add (%x), (%b);
+            z = mul (%y), (i32 100);
+            w = add (%z), (%c);
+            ret (%w);
+        });
+
+        PartialRedundancyElimination::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        let muls_by_block = |func| {
+            let f = m.function_ref(func);
+            f.basic_blocks
+                .order
+                .iter()
+                .map(|&id| {
+                    f.basic_blocks.arena[id]
+                        .iseq_ref()
+                        .iter()
+                        .filter(|v| f.inst_table[v.as_instruction().id].opcode == Opcode::Mul)
+                        .count()
+                })
+                .collect::<Vec<usize>>()
+        };
+        // `x * y` is computed on the critical edge from `entry` to `join`, which is
+        // split, and `b` becomes a phi.
+        assert_eq!(muls_by_block(f), vec![0, 1, 1, 0]);
+        // `b` isn't computed on every path from `join`, so nothing is inserted.
+        assert_eq!(muls_by_block(g), vec![0, 1, 0, 1, 0]);
+
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(
+            jit.run(func, vec![]),
+            exec::jit::GenericValue::Int32(241313)
+        );
+    }
+
+    #[test]
+    fn pre_unreachable_predecessor() {
+        use cilk::ir::{
+            opcode::Opcode, pre::PartialRedundancyElimination, simplify_cfg::SimplifyCFG, verifier,
+        };
+
+        let mut m = module::Module::new("cilk");
+
+        // r = (c < 1 ? x * y : x * y + 1) + x * y, where `join` also has a predecessor
+        // that never runs.
+        let f = cilk_ir!(m; define [i32] f [(i32), (i32), (i32)] {
+        entry:
+            c = icmp lt (%arg.2), (i32 1);
+            br (%c) then, else_;
+        then:
+            a = mul (%arg.0), (%arg.1);
+            br join;
+        else_:
+            b = mul (%arg.0), (%arg.1);
+            b1 = add (%b), (i32 1);
+            br join;
+        dead:
+            br join;
+        join:
+            p = phi [ [(%a), then], [(%b1), else_], [(i32 0), dead] ];
+            d = mul (%arg.0), (%arg.1);
+            r = add (%p), (%d);
+            ret (%r);
+        });
+        cilk_ir!(m; define [i32] main [] {
+        entry:
+            a = call f [(i32 3), (i32 4), (i32 0)];
+            b = call f [(i32 3), (i32 4), (i32 5)];
+            x = mul (%a), (i32 100);
+            y = add (%x), (%b);
+            ret (%y);
+        });
+
+        PartialRedundancyElimination::new().run_on_module(&mut m);
+        verifier::verify_module(&m).unwrap();
+
+        // `d` becomes a phi in `join`, taking no value computed in `dead`.
+        let func = m.function_ref(f);
+        let insts: Vec<&opcode::Instruction> = func
+            .basic_blocks
+            .order
+            .iter()
+            .flat_map(|&block| func.basic_blocks.arena[block].iseq_ref().clone())
+            .map(|v| &func.inst_table[v.as_instruction().id])
+            .collect();
+        let phis: Vec<&&opcode::Instruction> = insts
+            .iter()
+            .filter(|inst| inst.opcode == Opcode::Phi)
+            .collect();
+        assert_eq!(phis.len(), 2);
+        assert!(phis.iter().all(|phi| phi.operands.len() == 6));
+        assert_eq!(
+            insts
+                .iter()
+                .filter(|inst| inst.opcode == Opcode::Mul)
+                .count(),
+            2
+        );
+
+        // The backend needs every block to be reachable.
+        SimplifyCFG::new().run_on_module(&mut m);
+        let mut jit = exec::jit::JITExecutor::new(m);
+        let func = jit.find_function_by_name("main").unwrap();
+        assert_eq!(jit.run(func, vec![]), exec::jit::GenericValue::Int32(2425));
+    }
 }